@@ -13,7 +13,7 @@
  * See the License for the specific language governing permissions and
  * limitations under the License.
  */
-use clap::Parser;
+use clap::{Args, Parser, Subcommand};
 use directories::ProjectDirs;
 use ratatui::crossterm::event::{
     DisableMouseCapture, EnableMouseCapture, Event, KeyCode, KeyModifiers, MouseEventKind, poll,
@@ -26,6 +26,7 @@ use ratatui::crossterm::terminal::{
 use ratatui::prelude::*;
 use ratatui::widgets::{Block, Borders, HighlightSpacing, List, ListItem, ListState, Paragraph};
 use rust_myscript::prelude::*;
+use std::collections::BTreeSet;
 use std::fs::File;
 use std::io::prelude::*;
 use std::io::{BufReader, BufWriter, stderr};
@@ -40,6 +41,9 @@ use tracing_appender::non_blocking::WorkerGuard;
 /// Create gitignore based on `https://github.com/github/gitignore`.
 #[derive(Debug, Parser)]
 struct Opt {
+    #[command(subcommand)]
+    command: Option<Command>,
+
     /// Create a gitignore file with specified template names.
     #[arg(short, long)]
     template_names: Vec<String>,
@@ -47,8 +51,88 @@ struct Opt {
     /// Output a gitignore to a specified path instead of the stdout.
     #[arg(short, long)]
     output: Option<PathBuf>,
+
+    /// Update the ghignore section in an existing gitignore and keep the other lines.
+    /// Defaults to the `.gitignore` in `--dir`.
+    #[arg(
+        short,
+        long,
+        value_name = "PATH",
+        num_args = 0..=1,
+        require_equals = true,
+        conflicts_with = "output"
+    )]
+    update: Option<Option<PathBuf>>,
+
+    /// Directory to detect the project type to preselect templates.
+    #[arg(short, long, default_value = ".")]
+    dir: PathBuf,
+
+    /// Use the detected and specified templates without the interactive selection.
+    #[arg(short, long)]
+    auto: bool,
+}
+
+impl Opt {
+    /// Returns the gitignore to update by `--update`.
+    fn update_path(&self) -> Option<PathBuf> {
+        self.update
+            .as_ref()
+            .map(|data| data.clone().unwrap_or_else(|| self.dir.join(".gitignore")))
+    }
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Lint a repository against its gitignore.
+    ///
+    /// Only the rules of the gitignore in the root directory are linted. The nested gitignores
+    /// are applied to their own directories, and the tracked files are reported only if git
+    /// also ignores them, e.g. by `.git/info/exclude` or `core.excludesFile`.
+    Check(OptCheck),
 }
 
+#[derive(Debug, Args)]
+struct OptCheck {
+    /// Root directory of the repository that has the gitignore to check.
+    #[arg(default_value = ".")]
+    dir: PathBuf,
+}
+
+/// Marker file or directory name and the template to preselect.
+const PROJECT_MARKERS: &[(Marker, &str)] = &[
+    (Marker::Name("Cargo.toml"), "Rust"),
+    (Marker::Name("package.json"), "Node"),
+    (Marker::Name("go.mod"), "Go"),
+    (Marker::Extension("csproj"), "VisualStudio"),
+    (Marker::Name("build.gradle"), "Gradle"),
+    (Marker::Name("build.gradle.kts"), "Gradle"),
+    (Marker::Name("pyproject.toml"), "Python"),
+    (Marker::Name(".idea"), "Global/JetBrains"),
+    (Marker::Name(".vscode"), "Global/VisualStudioCode"),
+];
+
+enum Marker {
+    Name(&'static str),
+    Extension(&'static str),
+}
+
+/// Build output directory name and the template that ignores it.
+const BUILD_OUTPUT_DIRS: &[(&str, &str)] = &[
+    ("target", "Rust"),
+    ("node_modules", "Node"),
+    ("dist", "Node"),
+    ("build", "Gradle"),
+    (".gradle", "Gradle"),
+    ("bin", "VisualStudio"),
+    ("obj", "VisualStudio"),
+    ("__pycache__", "Python"),
+    (".venv", "Python"),
+];
+
+const SECTION_BEGIN_PREFIX: &str = "# --- ghignore: ";
+const SECTION_END: &str = "# --- end ghignore ---";
+
 fn main() -> Fallible<()> {
     dotenv::dotenv().ok();
 
@@ -65,6 +149,27 @@ fn main() -> Fallible<()> {
 
     check_git()?;
 
+    if let Some(Command::Check(check)) = &opt.command {
+        let gitignore_path = check.dir.join(".gitignore");
+        let gitignore = Gitignore::parse(
+            &std::fs::read_to_string(&gitignore_path)
+                .with_context(|| format!("failed to read {}", gitignore_path.display()))?,
+        );
+        let tracked_files = git_ls_files(&check.dir, &[])?;
+        let ignored_tracked_files = git_ls_files(&check.dir, &["-ci", "--exclude-standard"])?;
+        let report = check_repository(
+            &check.dir,
+            &gitignore,
+            &tracked_files,
+            &ignored_tracked_files,
+        )?;
+        print!("{}", format_check_report(&report));
+        if !report.is_empty() {
+            bail!("found {} problems", report.len());
+        }
+        return Ok(());
+    }
+
     let cache_dir = project_dir.cache_dir();
     if !cache_dir.exists() {
         std::fs::create_dir_all(cache_dir).context("failed to create cache directory")?;
@@ -83,11 +188,31 @@ fn main() -> Fallible<()> {
 
     let files = git_list_gitignore(&repo_dir)?;
 
-    let selected_files = SelectFilesApp::new(&files, opt.template_names.as_slice())
-        .run()?
-        .iter()
-        .map(|index| files[*index].as_str())
-        .collect::<Vec<_>>();
+    let mut template_names = opt.template_names.clone();
+    for name in detect_templates(&opt.dir, &files)? {
+        if !template_names.contains(&name) {
+            template_names.push(name);
+        }
+    }
+    debug!(?template_names);
+
+    let selected_files = if opt.auto {
+        let selected_files = files
+            .iter()
+            .filter(|name| matches_template_names(name, &template_names))
+            .map(String::as_str)
+            .collect::<Vec<_>>();
+        if selected_files.is_empty() {
+            bail!("no templates detected in {}", opt.dir.display());
+        }
+        selected_files
+    } else {
+        SelectFilesApp::new(&files, template_names.as_slice())
+            .run()?
+            .iter()
+            .map(|index| files[*index].as_str())
+            .collect::<Vec<_>>()
+    };
 
     if !selected_files.is_empty() {
         fn write_gitignore(
@@ -108,7 +233,23 @@ fn main() -> Fallible<()> {
             Ok(())
         }
 
-        if let Some(output_path) = opt.output {
+        if let Some(update_path) = opt.update_path() {
+            let existing = match std::fs::read_to_string(&update_path) {
+                Ok(data) => data,
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+                Err(e) => {
+                    return Err(e)
+                        .with_context(|| format!("failed to read {}", update_path.display()));
+                }
+            };
+
+            let mut templates = Vec::with_capacity(selected_files.len());
+            for entry in &selected_files {
+                templates.push((*entry, std::fs::read_to_string(repo_dir.join(entry))?));
+            }
+
+            write_atomically(&update_path, update_gitignore(&existing, &templates)?)?;
+        } else if let Some(output_path) = opt.output {
             write_gitignore(
                 BufWriter::new(File::create(output_path)?),
                 &repo_dir,
@@ -124,6 +265,126 @@ fn main() -> Fallible<()> {
     Ok(())
 }
 
+/// Returns the templates in `files` that match the markers in `dir`.
+fn detect_templates(dir: &Path, files: &[String]) -> Fallible<Vec<String>> {
+    let mut entry_names = vec![];
+    for entry in
+        std::fs::read_dir(dir).with_context(|| format!("failed to read {}", dir.display()))?
+    {
+        entry_names.push(entry?.file_name().to_string_lossy().into_owned());
+    }
+
+    let mut templates = Vec::<String>::new();
+    for (marker, template) in PROJECT_MARKERS {
+        let found = entry_names.iter().any(|name| match marker {
+            Marker::Name(data) => name == *data,
+            Marker::Extension(data) => {
+                Path::new(name).extension() == Some(std::ffi::OsStr::new(data))
+            }
+        });
+        if !found {
+            continue;
+        }
+
+        let template = files
+            .iter()
+            .find(|name| matches_template_names(name, &[template.to_string()]));
+        match template {
+            Some(data) if !templates.contains(data) => templates.push(data.clone()),
+            Some(_) => (),
+            None => debug!(template, "template not found"),
+        }
+    }
+
+    Ok(templates)
+}
+
+/// Returns `true` if `name` equals to one of `template_names` with or without `.gitignore`.
+fn matches_template_names(name: &str, template_names: &[String]) -> bool {
+    let name = name.to_lowercase();
+    template_names.iter().any(|data| {
+        let data = data.to_lowercase();
+        name == data || name.trim_end_matches(".gitignore") == data
+    })
+}
+
+/// Replaces the ghignore section of `existing` with the `templates` that are `(name, content)`.
+///
+/// The section is appended if `existing` has no section. The rules that already exist outside the
+/// section are omitted from the section.
+fn update_gitignore(existing: &str, templates: &[(&str, String)]) -> Fallible<String> {
+    let lines = existing.lines().collect::<Vec<_>>();
+    let section = match lines
+        .iter()
+        .position(|line| line.starts_with(SECTION_BEGIN_PREFIX))
+    {
+        Some(begin) => {
+            let end = lines[begin..]
+                .iter()
+                .position(|line| line.trim_end() == SECTION_END)
+                .with_context(|| format!("missing `{SECTION_END}` after line {}", begin + 1))?;
+            Some((begin, begin + end))
+        }
+        None => None,
+    };
+
+    let (before, after) = match section {
+        Some((begin, end)) => (&lines[..begin], &lines[end + 1..]),
+        None => (lines.as_slice(), &[][..]),
+    };
+
+    let mut rules = before
+        .iter()
+        .chain(after)
+        .map(|line| line.trim())
+        .filter(|line| is_rule(line))
+        .collect::<std::collections::HashSet<_>>();
+
+    let mut ret = String::new();
+    for line in before {
+        ret.push_str(line);
+        ret.push('\n');
+    }
+    if section.is_none() && before.last().is_some_and(|line| !line.trim().is_empty()) {
+        ret.push('\n');
+    }
+
+    ret.push_str(SECTION_BEGIN_PREFIX);
+    ret.push_str(
+        &templates
+            .iter()
+            .map(|(name, _)| name.trim_end_matches(".gitignore"))
+            .collect::<Vec<_>>()
+            .join(", "),
+    );
+    ret.push_str(" ---\n");
+    for (name, content) in templates {
+        ret.push_str(&format!("\n# {name}\n"));
+        for line in content.lines() {
+            let rule = line.trim();
+            if is_rule(rule) && !rules.insert(rule) {
+                continue;
+            }
+            ret.push_str(line);
+            ret.push('\n');
+        }
+    }
+    ret.push_str(SECTION_END);
+    ret.push('\n');
+
+    for line in after {
+        ret.push_str(line);
+        ret.push('\n');
+    }
+
+    Ok(ret)
+}
+
+/// Returns `true` if the `line` is neither a blank line nor a comment.
+fn is_rule(line: &str) -> bool {
+    !line.is_empty() && !line.starts_with('#')
+}
+
 fn init_tracing(project_dirs: &ProjectDirs) -> WorkerGuard {
     let log_dir = log_dir(project_dirs);
     if !log_dir.exists() {
@@ -266,6 +527,400 @@ fn git_list_gitignore(repo_path: &Path) -> Fallible<Vec<String>> {
     Ok(file_list)
 }
 
+/// Lists the tracked files filtered by the `options` of `git ls-files`.
+fn git_ls_files(repo_path: &Path, options: &[&str]) -> Fallible<Vec<String>> {
+    let output = std::process::Command::new("git")
+        .args(["ls-files", "-z"])
+        .args(options)
+        .stdin(std::process::Stdio::null())
+        .stdout(std::process::Stdio::piped())
+        .stderr(std::process::Stdio::inherit())
+        .current_dir(repo_path)
+        .spawn()?
+        .wait_with_output()?;
+
+    match output.status.code() {
+        Some(0) => (),
+        Some(_) => {
+            bail!("failed to list tracked files: {}", output.status)
+        }
+        None => bail!("killed git process"),
+    }
+
+    Ok(String::from_utf8(output.stdout)?
+        .split('\0')
+        .filter(|line| !line.is_empty())
+        .map(ToOwned::to_owned)
+        .collect())
+}
+
+/// Rules of a gitignore.
+///
+/// See `https://git-scm.com/docs/gitignore#_pattern_format`.
+#[derive(Debug)]
+struct Gitignore {
+    rules: Vec<GitignoreRule>,
+}
+
+impl Gitignore {
+    fn parse(content: &str) -> Self {
+        Self {
+            rules: content
+                .lines()
+                .enumerate()
+                .filter_map(|(index, line)| GitignoreRule::parse(index + 1, line))
+                .collect(),
+        }
+    }
+
+    /// Returns the index of the last rule that matches the `path`.
+    fn decide(&self, path: &str, is_dir: bool) -> Option<usize> {
+        self.rules
+            .iter()
+            .rposition(|rule| rule.matches(path, is_dir))
+    }
+
+    /// Returns the index of the rule that ignores the `path` or one of its parent directories.
+    ///
+    /// A file cannot be re-included if its parent directory is ignored.
+    fn ignored_by(&self, path: &str, is_dir: bool) -> Option<usize> {
+        let mut parent_end = 0;
+        while let Some(index) = path[parent_end..].find('/') {
+            parent_end += index;
+            if let Some(index) = self
+                .decide(&path[..parent_end], true)
+                .filter(|index| !self.rules[*index].negated)
+            {
+                return Some(index);
+            }
+            parent_end += 1;
+        }
+
+        self.decide(path, is_dir)
+            .filter(|index| !self.rules[*index].negated)
+    }
+}
+
+#[derive(Debug)]
+struct GitignoreRule {
+    /// 1-based line number.
+    line: usize,
+    text: String,
+    negated: bool,
+    dir_only: bool,
+    /// Pattern split by `/`. The unanchored pattern starts with `**`.
+    segments: Vec<Vec<char>>,
+}
+
+impl GitignoreRule {
+    fn parse(line: usize, text: &str) -> Option<Self> {
+        let trimmed = text.trim_end();
+        // trailing spaces are ignored unless the last one is escaped by `\`.
+        let escaped = trimmed.chars().rev().take_while(|c| *c == '\\').count() % 2 == 1;
+        let text = if escaped && text[trimmed.len()..].starts_with(' ') {
+            &text[..=trimmed.len()]
+        } else {
+            trimmed
+        };
+        if text.is_empty() || text.starts_with('#') {
+            return None;
+        }
+
+        let (negated, pattern) = match text.strip_prefix('!') {
+            Some(data) => (true, data),
+            None => (false, text),
+        };
+        let pattern = if pattern.starts_with("\\#") || pattern.starts_with("\\!") {
+            &pattern[1..]
+        } else {
+            pattern
+        };
+        let (dir_only, pattern) = match pattern.strip_suffix('/') {
+            Some(data) => (true, data),
+            None => (false, pattern),
+        };
+        let anchored = pattern.contains('/');
+        let pattern = pattern.strip_prefix('/').unwrap_or(pattern);
+        if pattern.is_empty() {
+            return None;
+        }
+
+        let mut segments = vec![];
+        if !anchored {
+            segments.push(vec!['*', '*']);
+        }
+        segments.extend(pattern.split('/').map(|data| data.chars().collect()));
+
+        Some(Self {
+            line,
+            text: text.to_owned(),
+            negated,
+            dir_only,
+            segments,
+        })
+    }
+
+    /// Returns `true` if the rule matches the `path` that is relative to the gitignore.
+    fn matches(&self, path: &str, is_dir: bool) -> bool {
+        if self.dir_only && !is_dir {
+            return false;
+        }
+        let path = path
+            .split('/')
+            .map(|data| data.chars().collect::<Vec<_>>())
+            .collect::<Vec<_>>();
+        match_segments(&self.segments, &path)
+    }
+}
+
+fn match_segments(pattern: &[Vec<char>], path: &[Vec<char>]) -> bool {
+    match pattern.split_first() {
+        None => path.is_empty(),
+        // trailing `/**` matches the inside of the directory only.
+        Some((segment, [])) if segment == &['*', '*'] => !path.is_empty(),
+        Some((segment, rest)) if segment == &['*', '*'] => {
+            (0..=path.len()).any(|index| match_segments(rest, &path[index..]))
+        }
+        Some((segment, rest)) => match path.split_first() {
+            Some((name, path_rest)) => {
+                match_wildcard(segment, name) && match_segments(rest, path_rest)
+            }
+            None => false,
+        },
+    }
+}
+
+/// Matches a path segment with `*`, `?` and `[...]`.
+fn match_wildcard(pattern: &[char], text: &[char]) -> bool {
+    match pattern.first() {
+        None => text.is_empty(),
+        Some('*') => (0..=text.len()).any(|index| match_wildcard(&pattern[1..], &text[index..])),
+        Some('?') => !text.is_empty() && match_wildcard(&pattern[1..], &text[1..]),
+        Some('[') => match match_class(&pattern[1..], text.first().copied()) {
+            Some((matched, len)) => matched && match_wildcard(&pattern[1 + len..], &text[1..]),
+            // treat unterminated `[` as a literal.
+            None => text.first() == Some(&'[') && match_wildcard(&pattern[1..], &text[1..]),
+        },
+        Some('\\') if pattern.len() > 1 => {
+            text.first() == Some(&pattern[1]) && match_wildcard(&pattern[2..], &text[1..])
+        }
+        Some(c) => text.first() == Some(c) && match_wildcard(&pattern[1..], &text[1..]),
+    }
+}
+
+/// Returns whether the `c` matches the class and the length of the class including `]`.
+fn match_class(class: &[char], c: Option<char>) -> Option<(bool, usize)> {
+    let (negated, start) = match class.first() {
+        Some('!' | '^') => (true, 1),
+        _ => (false, 0),
+    };
+    // `]` just after `[` is a literal.
+    let end = start
+        + 1
+        + class
+            .get(start + 1..)?
+            .iter()
+            .position(|data| *data == ']')?;
+
+    let c = c?;
+    let items = &class[start..end];
+    let mut matched = false;
+    let mut index = 0;
+    while index < items.len() {
+        if index + 2 < items.len() && items[index + 1] == '-' {
+            matched |= items[index] <= c && c <= items[index + 2];
+            index += 3;
+        } else {
+            matched |= items[index] == c;
+            index += 1;
+        }
+    }
+
+    Some((matched != negated, end + 1))
+}
+
+#[derive(Debug, Default)]
+struct CheckReport<'a> {
+    /// Tracked files and the rules that ignore them.
+    tracked_ignored: Vec<(String, &'a GitignoreRule)>,
+
+    /// Rules that match no files.
+    unmatched: Vec<&'a GitignoreRule>,
+
+    /// Rules and the later negations that re-include all their matches.
+    shadowed: Vec<(&'a GitignoreRule, &'a GitignoreRule)>,
+
+    /// Build output directories that are not ignored and the template for them.
+    unignored_build_outputs: Vec<(&'static str, &'static str)>,
+}
+
+impl CheckReport<'_> {
+    fn len(&self) -> usize {
+        self.tracked_ignored.len()
+            + self.unmatched.len()
+            + self.shadowed.len()
+            + self.unignored_build_outputs.len()
+    }
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// Checks the files in `repo_dir` and the `tracked_files` against the `gitignore`.
+///
+/// Ignored directories are not traversed the same as git, including the ones ignored by the
+/// nested gitignores. `ignored_tracked_files` are the tracked files that git ignores, to report
+/// only the files that git agrees with.
+fn check_repository<'a>(
+    repo_dir: &Path,
+    gitignore: &'a Gitignore,
+    tracked_files: &[String],
+    ignored_tracked_files: &[String],
+) -> Fallible<CheckReport<'a>> {
+    /// Returns `true` if the directory at `path` is ignored. The deeper gitignore takes
+    /// precedence over the shallower ones.
+    fn is_ignored_dir(gitignore: &Gitignore, nested: &[(String, Gitignore)], path: &str) -> bool {
+        for (dir, nested) in nested.iter().rev() {
+            if let Some(index) = nested.decide(&path[dir.len() + 1..], true) {
+                return !nested.rules[index].negated;
+            }
+        }
+        gitignore.ignored_by(path, true).is_some()
+    }
+
+    fn walk(
+        root: &Path,
+        relative: &str,
+        gitignore: &Gitignore,
+        nested: &mut Vec<(String, Gitignore)>,
+        entries: &mut BTreeSet<(String, bool)>,
+    ) -> Fallible<()> {
+        let dir = root.join(relative);
+        let has_nested = !relative.is_empty() && dir.join(".gitignore").is_file();
+        if has_nested {
+            let nested_path = dir.join(".gitignore");
+            let content = std::fs::read_to_string(&nested_path)
+                .with_context(|| format!("failed to read {}", nested_path.display()))?;
+            nested.push((relative.to_owned(), Gitignore::parse(&content)));
+        }
+
+        let mut names = vec![];
+        for entry in
+            std::fs::read_dir(&dir).with_context(|| format!("failed to read {}", dir.display()))?
+        {
+            let entry = entry?;
+            names.push((
+                entry.file_name().to_string_lossy().into_owned(),
+                entry.file_type()?.is_dir(),
+            ));
+        }
+
+        for (name, is_dir) in names {
+            if name == ".git" {
+                continue;
+            }
+            let path = if relative.is_empty() {
+                name
+            } else {
+                format!("{relative}/{name}")
+            };
+            if is_dir && !is_ignored_dir(gitignore, nested, &path) {
+                walk(root, &path, gitignore, nested, entries)?;
+            }
+            entries.insert((path, is_dir));
+        }
+
+        if has_nested {
+            nested.pop();
+        }
+        Ok(())
+    }
+
+    let mut entries = BTreeSet::new();
+    walk(repo_dir, "", gitignore, &mut vec![], &mut entries)?;
+    entries.extend(tracked_files.iter().map(|data| (data.clone(), false)));
+
+    let rules = &gitignore.rules;
+    let mut matched = vec![false; rules.len()];
+    let mut decisive = vec![false; rules.len()];
+    let mut overridden_by = vec![None; rules.len()];
+    for (path, is_dir) in &entries {
+        let Some(decider) = gitignore.decide(path, *is_dir) else {
+            continue;
+        };
+        decisive[decider] = true;
+        for (index, rule) in rules.iter().enumerate().take(decider + 1) {
+            if !rule.matches(path, *is_dir) {
+                continue;
+            }
+            matched[index] = true;
+            if index < decider && rules[decider].negated && !rule.negated {
+                overridden_by[index].get_or_insert(decider);
+            }
+        }
+    }
+
+    let mut report = CheckReport::default();
+    // a nested gitignore may re-include the file that the rule ignores.
+    for path in ignored_tracked_files {
+        if let Some(index) = gitignore.ignored_by(path, false) {
+            report.tracked_ignored.push((path.clone(), &rules[index]));
+        }
+    }
+    for (index, rule) in rules.iter().enumerate() {
+        if !matched[index] {
+            report.unmatched.push(rule);
+        } else if let (false, Some(negation)) = (decisive[index], overridden_by[index]) {
+            report.shadowed.push((rule, &rules[negation]));
+        }
+    }
+    for &(name, template) in BUILD_OUTPUT_DIRS {
+        // a tracked directory such as `bin/` of scripts is not a build output.
+        let tracked = tracked_files.iter().any(|data| {
+            data.strip_prefix(name)
+                .is_some_and(|rest| rest.starts_with('/'))
+        });
+        if !tracked && repo_dir.join(name).is_dir() && gitignore.ignored_by(name, true).is_none() {
+            report.unignored_build_outputs.push((name, template));
+        }
+    }
+
+    Ok(report)
+}
+
+fn format_check_report(report: &CheckReport) -> String {
+    let mut ret = String::new();
+    if !report.tracked_ignored.is_empty() {
+        ret.push_str("tracked files ignored by rules:\n");
+        for (path, rule) in &report.tracked_ignored {
+            ret.push_str(&format!("  {path} (line {}: {})\n", rule.line, rule.text));
+        }
+    }
+    if !report.unmatched.is_empty() {
+        ret.push_str("rules that never match:\n");
+        for rule in &report.unmatched {
+            ret.push_str(&format!("  line {}: {}\n", rule.line, rule.text));
+        }
+    }
+    if !report.shadowed.is_empty() {
+        ret.push_str("rules shadowed by later negations:\n");
+        for (rule, negation) in &report.shadowed {
+            ret.push_str(&format!(
+                "  line {}: {} (by line {}: {})\n",
+                rule.line, rule.text, negation.line, negation.text,
+            ));
+        }
+    }
+    if !report.unignored_build_outputs.is_empty() {
+        ret.push_str("build outputs not ignored:\n");
+        for (name, template) in &report.unignored_build_outputs {
+            ret.push_str(&format!("  {name}/ (see the {template} template)\n"));
+        }
+    }
+    ret
+}
+
 struct SelectFilesApp<'a> {
     files: Vec<FileEntry<'a>>,
     focus_area: SelectFilesFocusArea,
@@ -281,11 +936,7 @@ impl<'a> SelectFilesApp<'a> {
             files: files
                 .iter()
                 .map(|name| FileEntry {
-                    checked: template_names.iter().any(|data| {
-                        let name = name.to_lowercase();
-                        let data = data.to_lowercase();
-                        name == data || name.trim_end_matches(".gitignore") == data
-                    }),
+                    checked: matches_template_names(name, template_names),
                     name,
                 })
                 .collect(),
@@ -648,4 +1299,293 @@ mod tests {
     fn verify_cli() {
         Opt::command().debug_assert();
     }
+
+    #[test]
+    fn opt_update_default_path() {
+        let opt = Opt::try_parse_from(["ghignore", "-t", "Rust", "--update"]).unwrap();
+        assert_eq!(opt.update_path(), Some(PathBuf::from("./.gitignore")));
+
+        let opt = Opt::try_parse_from(["ghignore", "--update", "-d", "foo"]).unwrap();
+        assert_eq!(opt.update_path(), Some(PathBuf::from("foo/.gitignore")));
+
+        let opt =
+            Opt::try_parse_from(["ghignore", "--update=bar/.gitignore", "-d", "foo"]).unwrap();
+        assert_eq!(opt.update_path(), Some(PathBuf::from("bar/.gitignore")));
+
+        let opt = Opt::try_parse_from(["ghignore", "-t", "Rust"]).unwrap();
+        assert_eq!(opt.update_path(), None);
+
+        assert!(Opt::try_parse_from(["ghignore", "--update", "-o", "foo"]).is_err());
+
+        // the next word is not taken as PATH.
+        let opt = Opt::try_parse_from(["ghignore", "-u", "check"]).unwrap();
+        assert!(matches!(opt.command, Some(Command::Check(_))));
+    }
+
+    #[test]
+    fn opt_check() {
+        let opt = Opt::try_parse_from(["ghignore", "check", "foo"]).unwrap();
+        let Some(Command::Check(check)) = opt.command else {
+            panic!("check should be parsed");
+        };
+        assert_eq!(check.dir, PathBuf::from("foo"));
+    }
+
+    #[test]
+    fn gitignore_rule_matches() {
+        let rule = |text: &str| GitignoreRule::parse(1, text).unwrap();
+
+        assert!(rule("*.log").matches("debug.log", false));
+        assert!(rule("*.log").matches("logs/debug.log", false));
+        assert!(!rule("*.log").matches("debug.log.txt", false));
+
+        assert!(rule("target/").matches("target", true));
+        assert!(rule("target/").matches("crates/foo/target", true));
+        assert!(!rule("target/").matches("target", false));
+
+        assert!(rule("/build").matches("build", true));
+        assert!(!rule("/build").matches("app/build", true));
+        assert!(rule("doc/*.txt").matches("doc/notes.txt", false));
+        assert!(!rule("doc/*.txt").matches("doc/server/arch.txt", false));
+
+        assert!(rule("**/foo/bar").matches("a/b/foo/bar", false));
+        assert!(rule("a/**/b").matches("a/b", false));
+        assert!(rule("a/**/b").matches("a/x/y/b", false));
+        assert!(rule("abc/**").matches("abc/x/y", false));
+        assert!(!rule("abc/**").matches("abc", true));
+
+        assert!(rule("file?.[ch]").matches("file1.c", false));
+        assert!(!rule("file?.[!ch]").matches("file1.c", false));
+        assert!(rule("v[0-9]").matches("v5", false));
+        assert!(rule("\\#memo").matches("#memo", false));
+
+        assert!(rule("foo  ").matches("foo", false));
+        assert!(rule("foo\\ ").matches("foo ", false));
+        assert!(!rule("foo\\ ").matches("foo", false));
+        assert!(rule("foo\\  ").matches("foo ", false));
+        assert!(rule("foo\\\\ ").matches("foo\\", false));
+
+        assert!(rule("!*.log").negated);
+        assert!(GitignoreRule::parse(1, "# comment").is_none());
+        assert!(GitignoreRule::parse(1, "   ").is_none());
+    }
+
+    #[test]
+    fn gitignore_ignored_by() {
+        let gitignore = Gitignore::parse("*.log\n!important.log\n/out/\n!/out/keep.txt\n");
+
+        assert_eq!(gitignore.ignored_by("debug.log", false), Some(0));
+        assert_eq!(gitignore.ignored_by("important.log", false), None);
+        assert_eq!(gitignore.ignored_by("out", true), Some(2));
+        // cannot re-include a file if its parent directory is ignored.
+        assert_eq!(gitignore.ignored_by("out/keep.txt", false), Some(2));
+        assert_eq!(gitignore.ignored_by("src/main.rs", false), None);
+    }
+
+    #[test]
+    fn check_repository_ok() {
+        let dir = tempfile::tempdir().unwrap();
+        for path in [
+            "src/main.rs",
+            "debug.log",
+            "keep.log",
+            "notes.tmp",
+            "dist/app.js",
+            "bin/setup.sh",
+        ] {
+            let path = dir.path().join(path);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, "").unwrap();
+        }
+        std::fs::create_dir_all(dir.path().join("target/debug")).unwrap();
+        std::fs::create_dir_all(dir.path().join("node_modules")).unwrap();
+
+        let gitignore = Gitignore::parse(
+            "# build
+target/
+*.log
+*.tmp
+!notes.tmp
+*.bak
+!keep.log
+",
+        );
+        let tracked_files =
+            ["src/main.rs", "debug.log", "notes.tmp", "bin/setup.sh"].map(String::from);
+
+        let report = check_repository(
+            dir.path(),
+            &gitignore,
+            &tracked_files,
+            &["debug.log".to_string()],
+        )
+        .unwrap();
+
+        assert_eq!(
+            format_check_report(&report),
+            "tracked files ignored by rules:
+  debug.log (line 3: *.log)
+rules that never match:
+  line 6: *.bak
+rules shadowed by later negations:
+  line 4: *.tmp (by line 5: !notes.tmp)
+build outputs not ignored:
+  node_modules/ (see the Node template)
+  dist/ (see the Node template)
+",
+        );
+        assert_eq!(report.len(), 5);
+    }
+
+    #[test]
+    fn check_repository_nested_gitignore() {
+        let dir = tempfile::tempdir().unwrap();
+        for path in [
+            "app/.gitignore",
+            "app/keep.log",
+            "app/out/cache.tmp",
+            "notes.tmp",
+        ] {
+            let path = dir.path().join(path);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, "").unwrap();
+        }
+        std::fs::write(dir.path().join("app/.gitignore"), "out/\n!keep.log\n").unwrap();
+
+        let gitignore = Gitignore::parse("*.log\n*.tmp\n!notes.tmp\n");
+        let tracked_files = ["app/.gitignore", "app/keep.log", "notes.tmp"].map(String::from);
+
+        // git does not ignore app/keep.log by the nested negation, and app/out/ is not
+        // traversed.
+        let report = check_repository(dir.path(), &gitignore, &tracked_files, &[]).unwrap();
+        assert_eq!(
+            format_check_report(&report),
+            "rules shadowed by later negations:
+  line 2: *.tmp (by line 3: !notes.tmp)
+",
+        );
+    }
+
+    #[test]
+    fn detect_templates_ok() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("Cargo.toml"), "").unwrap();
+        std::fs::write(dir.path().join("build.gradle"), "").unwrap();
+        std::fs::write(dir.path().join("build.gradle.kts"), "").unwrap();
+        std::fs::write(dir.path().join("App.csproj"), "").unwrap();
+        std::fs::write(dir.path().join("go.sum"), "").unwrap();
+        std::fs::create_dir(dir.path().join(".idea")).unwrap();
+        std::fs::create_dir(dir.path().join(".vscode")).unwrap();
+
+        let files = [
+            "Global/JetBrains.gitignore",
+            "Go.gitignore",
+            "Gradle.gitignore",
+            "Rust.gitignore",
+            "VisualStudio.gitignore",
+        ]
+        .map(String::from);
+
+        assert_eq!(
+            detect_templates(dir.path(), &files).unwrap(),
+            vec![
+                "Rust.gitignore",
+                "VisualStudio.gitignore",
+                "Gradle.gitignore",
+                "Global/JetBrains.gitignore",
+            ],
+        );
+    }
+
+    #[test]
+    fn matches_template_names_ok() {
+        let names = ["rust".to_string(), "Global/macOS.gitignore".to_string()];
+        assert!(matches_template_names("Rust.gitignore", &names));
+        assert!(matches_template_names("Global/macOS.gitignore", &names));
+        assert!(!matches_template_names("Global/Linux.gitignore", &names));
+    }
+
+    #[test]
+    fn update_gitignore_append() {
+        let actual = update_gitignore(
+            "/.env\ntarget/\n",
+            &[
+                ("Rust.gitignore", "# Generated\ndebug/\ntarget/\n".into()),
+                ("Node.gitignore", "# Logs\nlogs\n*.log\ndebug/\n".into()),
+            ],
+        )
+        .unwrap();
+
+        assert_eq!(
+            actual,
+            "/.env
+target/
+
+# --- ghignore: Rust, Node ---
+
+# Rust.gitignore
+# Generated
+debug/
+
+# Node.gitignore
+# Logs
+logs
+*.log
+# --- end ghignore ---
+",
+        );
+    }
+
+    #[test]
+    fn update_gitignore_replace() {
+        let actual = update_gitignore(
+            "/.env
+# --- ghignore: Rust ---
+
+# Rust.gitignore
+debug/
+# --- end ghignore ---
+/local
+",
+            &[
+                ("Rust.gitignore", "debug/\ntarget/\n".into()),
+                ("Global/macOS.gitignore", ".DS_Store\n/local\n".into()),
+            ],
+        )
+        .unwrap();
+
+        assert_eq!(
+            actual,
+            "/.env
+# --- ghignore: Rust, Global/macOS ---
+
+# Rust.gitignore
+debug/
+target/
+
+# Global/macOS.gitignore
+.DS_Store
+# --- end ghignore ---
+/local
+",
+        );
+    }
+
+    #[test]
+    fn update_gitignore_empty() {
+        let actual = update_gitignore("", &[("Rust.gitignore", "target/\n".into())]).unwrap();
+        assert_eq!(
+            actual,
+            "# --- ghignore: Rust ---\n\n# Rust.gitignore\ntarget/\n# --- end ghignore ---\n",
+        );
+    }
+
+    #[test]
+    fn update_gitignore_unterminated() {
+        let ret = update_gitignore(
+            "/.env\n# --- ghignore: Rust ---\ntarget/\n",
+            &[("Rust.gitignore", "target/\n".into())],
+        );
+        assert!(ret.is_err());
+    }
 }
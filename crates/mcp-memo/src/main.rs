@@ -18,18 +18,38 @@ use chrono::Local;
 use clap::{Parser, ValueHint};
 use rmcp::handler::server::router::tool::ToolRouter;
 use rmcp::handler::server::wrapper::Parameters;
-use rmcp::model::{Implementation, ServerCapabilities, ServerInfo};
+use rmcp::model::{
+    AnnotateAble, Implementation, ListResourcesResult, PaginatedRequestParams, RawResource,
+    ReadResourceRequestParams, ReadResourceResult, ResourceContents,
+    ResourceUpdatedNotificationParam, ServerCapabilities, ServerInfo, SubscribeRequestParams,
+    UnsubscribeRequestParams,
+};
 use rmcp::schemars::{self, JsonSchema};
-use rmcp::{ServerHandler, ServiceExt as _, tool, tool_handler, tool_router};
+use rmcp::service::RequestContext;
+use rmcp::{
+    ErrorData as McpError, Peer, RoleServer, ServerHandler, ServiceExt as _, tool, tool_handler,
+    tool_router,
+};
 use rust_myscript::prelude::*;
 use serde::Deserialize;
+use std::collections::HashMap;
 use std::path::PathBuf;
+use std::process::Stdio;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
 use tracing::{Level, instrument};
 
 const MAX_BACKUP_COUNT: usize = 5;
+const RESOURCE_URI_PREFIX: &str = "memo://";
+const RESOURCE_POLL_INTERVAL: Duration = Duration::from_secs(2);
 
 #[derive(Debug, Parser)]
 struct Opt {
+    /// Keep the data directory as a git repository and commit on every change instead of
+    /// rotating backups.
+    #[clap(long)]
+    git: bool,
+
     /// Directory to store memos.
     #[clap(value_hint = ValueHint::DirPath)]
     data_dir: PathBuf,
@@ -49,12 +69,20 @@ async fn main() {
         return;
     }
 
-    info!(data_dir = %data_dir.display(), "data directory");
+    info!(data_dir = %data_dir.display(), git = opt.git, "data directory");
 
-    let running = match MemoServer::new(data_dir)
-        .serve(rmcp::transport::stdio())
-        .await
-    {
+    let mut server = MemoServer::new(data_dir.clone());
+    if opt.git {
+        match GitRepo::init(data_dir).await {
+            Ok(repo) => server = server.with_git(repo),
+            Err(e) => {
+                error!(?e, "failed to initialize git repository");
+                return;
+            }
+        }
+    }
+
+    let running = match server.serve(rmcp::transport::stdio()).await {
         Ok(r) => r,
         Err(e) => {
             error!(?e, "failed to initialize MCP server");
@@ -66,9 +94,168 @@ async fn main() {
     }
 }
 
+#[derive(Debug, Clone)]
+struct GitRepo {
+    repo_dir: PathBuf,
+    /// Serializes index updates so that concurrent tool calls do not race on `.git/index.lock`.
+    lock: Arc<tokio::sync::Mutex<()>>,
+}
+
+impl GitRepo {
+    async fn init(repo_dir: PathBuf) -> Fallible<Self> {
+        let repo = Self {
+            repo_dir,
+            lock: Default::default(),
+        };
+
+        if tokio::fs::metadata(repo.repo_dir.join(".git"))
+            .await
+            .is_err()
+        {
+            info!("initialize git repository");
+            repo.run(&["init", "--quiet"]).await?;
+            repo.ensure_identity().await?;
+            // Import memos written before the git mode was enabled.
+            let mut entries = tokio::fs::read_dir(&repo.repo_dir).await?;
+            while let Some(entry) = entries.next_entry().await? {
+                let name = entry.file_name().to_string_lossy().to_string();
+                if entry.file_type().await?.is_file() && name.ends_with(".txt") {
+                    repo.run(&["add", "--", &name]).await?;
+                }
+            }
+            repo.run(&[
+                "commit",
+                "--quiet",
+                "--allow-empty",
+                "-m",
+                "Initialize memo repository",
+            ])
+            .await?;
+        } else {
+            repo.ensure_identity().await?;
+        }
+
+        Ok(repo)
+    }
+
+    /// Configures a repository-local committer if the user has no git identity.
+    async fn ensure_identity(&self) -> Fallible<()> {
+        if self.run(&["config", "user.email"]).await.is_err() {
+            self.run(&["config", "user.name", env!("CARGO_PKG_NAME")])
+                .await?;
+            self.run(&[
+                "config",
+                "user.email",
+                concat!(env!("CARGO_PKG_NAME"), "@localhost"),
+            ])
+            .await?;
+        }
+        Ok(())
+    }
+
+    async fn run(&self, args: &[&str]) -> Fallible<String> {
+        let output = tokio::process::Command::new("git")
+            .args(args)
+            .current_dir(&self.repo_dir)
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .output()
+            .await
+            .context("failed to execute git")?;
+
+        match output.status.code() {
+            Some(0) => Ok(String::from_utf8(output.stdout)?),
+            Some(_) => bail!(
+                "git {} failed: {}: {}",
+                args.first().unwrap_or(&""),
+                output.status,
+                String::from_utf8_lossy(&output.stderr).trim(),
+            ),
+            None => bail!("killed git process"),
+        }
+    }
+
+    async fn commit(&self, file_name: &str, message: &str) -> Fallible<()> {
+        let _guard = self.lock.lock().await;
+        if tokio::fs::metadata(self.repo_dir.join(file_name))
+            .await
+            .is_ok()
+        {
+            self.run(&["add", "--", file_name]).await?;
+        } else {
+            self.run(&[
+                "rm",
+                "--cached",
+                "--quiet",
+                "--ignore-unmatch",
+                "--",
+                file_name,
+            ])
+            .await?;
+        }
+        // `diff --quiet` exits with 1 if there are staged changes.
+        if self
+            .run(&["diff", "--cached", "--quiet", "--", file_name])
+            .await
+            .is_ok()
+        {
+            debug!(%file_name, "nothing to commit");
+            return Ok(());
+        }
+        self.run(&["commit", "--quiet", "-m", message, "--", file_name])
+            .await?;
+        Ok(())
+    }
+
+    async fn log(&self, file_name: &str, limit: u32) -> Fallible<String> {
+        self.run(&[
+            "log",
+            &format!("--max-count={limit}"),
+            "--format=%h %cI %s",
+            "--",
+            file_name,
+        ])
+        .await
+    }
+
+    async fn show(&self, revision: &str, file_name: &str) -> Fallible<String> {
+        validate_revision(revision)?;
+        self.run(&["show", &format!("{revision}:{file_name}")])
+            .await
+    }
+}
+
+fn validate_revision(revision: &str) -> Fallible<()> {
+    if revision.is_empty() || revision.starts_with('-') {
+        bail!("revision must not be empty or start with '-'");
+    }
+    if !revision
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || matches!(c, '~' | '^' | '.' | '_' | '/' | '-'))
+    {
+        bail!("revision contains unsupported characters");
+    }
+    Ok(())
+}
+
+/// Identifies a memo file revision for detecting changes made on disk.
+type MemoFingerprint = Option<(SystemTime, u64)>;
+
+#[derive(Debug, Default)]
+struct ResourceSubscriptions {
+    peer: Option<Peer<RoleServer>>,
+    /// Subscribed memo keys and the fingerprint of the memo that was last notified.
+    keys: HashMap<String, MemoFingerprint>,
+    watching: bool,
+}
+
 #[derive(Debug, Clone)]
 struct MemoServer {
     data_dir: PathBuf,
+    git: Option<GitRepo>,
+    subscriptions: Arc<Mutex<ResourceSubscriptions>>,
+    resource_poll_interval: Duration,
 
     #[allow(dead_code)]
     tool_router: ToolRouter<Self>,
@@ -78,15 +265,53 @@ impl MemoServer {
     fn new(data_dir: PathBuf) -> Self {
         Self {
             data_dir,
+            git: None,
+            subscriptions: Default::default(),
+            resource_poll_interval: RESOURCE_POLL_INTERVAL,
             tool_router: Self::tool_router(),
         }
     }
 
+    #[cfg(test)]
+    fn with_resource_poll_interval(mut self, interval: Duration) -> Self {
+        self.resource_poll_interval = interval;
+        self
+    }
+
+    fn with_git(mut self, git: GitRepo) -> Self {
+        self.git = Some(git);
+        self
+    }
+
+    fn git(&self) -> Result<&GitRepo, String> {
+        self.git
+            .as_ref()
+            .ok_or_else(|| "git mode is not enabled".to_string())
+    }
+
+    async fn commit_memo(&self, key: &str, tool: &str) {
+        let Some(git) = &self.git else {
+            return;
+        };
+        // Commit failures are non-fatal like backup failures. The next commit of the same key
+        // picks up the pending change.
+        if let Err(e) = git
+            .commit(&format!("{key}.txt"), &format!("{tool}: {key}"))
+            .await
+        {
+            warn!(?e, %key, "failed to commit memo");
+        }
+    }
+
     fn backup_dir(&self, key: &str) -> PathBuf {
         self.data_dir.join("backup").join(key)
     }
 
     async fn backup_memo(&self, path: &std::path::Path, key: &str) {
+        if self.git.is_some() {
+            // The git history supersedes the backup rotation.
+            return;
+        }
         let backup_dir = self.backup_dir(key);
         if let Err(e) = tokio::fs::create_dir_all(&backup_dir).await {
             warn!(?e, %key, "failed to create backup directory");
@@ -120,6 +345,176 @@ impl MemoServer {
         }
     }
 
+    fn uri_to_key(uri: &str) -> Fallible<&str> {
+        uri.strip_prefix(RESOURCE_URI_PREFIX)
+            .with_context(|| format!("resource URI must start with '{RESOURCE_URI_PREFIX}'"))
+    }
+
+    async fn memo_fingerprint(&self, key: &str) -> MemoFingerprint {
+        let path = self.key_to_path(key).ok()?;
+        let metadata = tokio::fs::metadata(&path).await.ok()?;
+        Some((metadata.modified().ok()?, metadata.len()))
+    }
+
+    /// Reads a memo, applies `edit` to its content and writes the result back through the same
+    /// backup, commit and notification path as `set_memo`.
+    async fn modify_memo(
+        &self,
+        key: &str,
+        tool: &str,
+        edit: impl FnOnce(&str) -> Result<String, String>,
+    ) -> Result<(), String> {
+        let path = self.key_to_path(key).map_err(|e| e.to_string())?;
+        let content = match tokio::fs::read_to_string(&path).await {
+            Ok(c) => c,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                return Err(format!("memo '{key}' not found"));
+            }
+            Err(e) => {
+                warn!(?e, %key, "failed to read memo");
+                return Err(format!("failed to read memo '{key}'"));
+            }
+        };
+
+        let new_content = edit(&content)?;
+
+        if tokio::fs::metadata(&path).await.is_ok() {
+            self.backup_memo(&path, key).await;
+        }
+
+        if let Err(e) = tokio::fs::write(&path, &new_content).await {
+            warn!(?e, %key, "failed to write memo");
+            return Err(format!("failed to write memo '{key}'"));
+        }
+        self.commit_memo(key, tool).await;
+        self.notify_memo_updated(key).await;
+        Ok(())
+    }
+
+    /// Notifies the subscribed client that a memo has been changed by a tool call.
+    async fn notify_memo_updated(&self, key: &str) {
+        let fingerprint = self.memo_fingerprint(key).await;
+        let peer = {
+            let Ok(mut subscriptions) = self.subscriptions.lock() else {
+                return;
+            };
+            let Some(last) = subscriptions.keys.get_mut(key) else {
+                return;
+            };
+            // The watcher has already notified this revision.
+            if *last == fingerprint {
+                return;
+            }
+            // Record the fingerprint so that the watcher does not notify the same change again.
+            *last = fingerprint;
+            subscriptions.peer.clone()
+        };
+        let Some(peer) = peer else {
+            return;
+        };
+        if let Err(e) = peer
+            .notify_resource_updated(ResourceUpdatedNotificationParam::new(format!(
+                "{RESOURCE_URI_PREFIX}{key}"
+            )))
+            .await
+        {
+            warn!(?e, %key, "failed to notify resource update");
+        }
+    }
+
+    /// Polls the subscribed memos to notify changes made on disk outside of tool calls.
+    fn spawn_resource_watcher(&self) {
+        let server = self.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(server.resource_poll_interval);
+            loop {
+                interval.tick().await;
+
+                let keys = match server.subscriptions.lock() {
+                    Ok(subscriptions) => subscriptions
+                        .keys
+                        .iter()
+                        .map(|(key, last)| (key.clone(), *last))
+                        .collect::<Vec<_>>(),
+                    Err(_) => break,
+                };
+                let mut fingerprints = Vec::with_capacity(keys.len());
+                for (key, last) in keys {
+                    let fingerprint = server.memo_fingerprint(&key).await;
+                    fingerprints.push((key, last, fingerprint));
+                }
+
+                let (peer, changed) = {
+                    let Ok(mut subscriptions) = server.subscriptions.lock() else {
+                        break;
+                    };
+                    if subscriptions.keys.is_empty() || subscriptions.peer.is_none() {
+                        subscriptions.watching = false;
+                        break;
+                    }
+                    let mut changed = vec![];
+                    for (key, previous, fingerprint) in fingerprints {
+                        // Skip the memo if a tool call has recorded a new fingerprint while
+                        // reading the file, so that the newer fingerprint is not overwritten.
+                        if let Some(last) = subscriptions.keys.get_mut(&key)
+                            && *last == previous
+                            && *last != fingerprint
+                        {
+                            *last = fingerprint;
+                            changed.push(key);
+                        }
+                    }
+                    (subscriptions.peer.clone(), changed)
+                };
+                let Some(peer) = peer else {
+                    break;
+                };
+                for key in changed {
+                    debug!(%key, "memo changed on disk");
+                    if let Err(e) = peer
+                        .notify_resource_updated(ResourceUpdatedNotificationParam::new(format!(
+                            "{RESOURCE_URI_PREFIX}{key}"
+                        )))
+                        .await
+                    {
+                        warn!(?e, %key, "failed to notify resource update");
+                        if let Ok(mut subscriptions) = server.subscriptions.lock() {
+                            subscriptions.watching = false;
+                        }
+                        return;
+                    }
+                }
+            }
+        });
+    }
+
+    async fn list_keys(&self) -> Fallible<Vec<String>> {
+        let mut entries = tokio::fs::read_dir(&self.data_dir)
+            .await
+            .context("failed to read data directory")?;
+        let mut keys = Vec::new();
+        while let Some(entry) = entries
+            .next_entry()
+            .await
+            .context("failed to read directory entry")?
+        {
+            let name = entry.file_name();
+            let name = name.to_string_lossy();
+            // Check file type to avoid accidentally listing subdirectories (e.g. "backup")
+            // that might have a .txt suffix in the future.
+            let is_file = entry
+                .file_type()
+                .await
+                .map(|t| t.is_file())
+                .unwrap_or(false);
+            if is_file && let Some(key) = name.strip_suffix(".txt") {
+                keys.push(key.to_string());
+            }
+        }
+        keys.sort();
+        Ok(keys)
+    }
+
     fn key_to_path(&self, key: &str) -> Fallible<PathBuf> {
         // Validate key: only alphanumeric, hyphens, underscores, and dots allowed
         if key.is_empty() {
@@ -155,6 +550,68 @@ struct DeleteMemoRequest {
     key: String,
 }
 
+#[derive(Debug, Deserialize, JsonSchema)]
+struct AppendMemoRequest {
+    /// The key of the memo to append to.
+    key: String,
+    /// The content to append.
+    content: String,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+struct PrependMemoRequest {
+    /// The key of the memo to prepend to.
+    key: String,
+    /// The content to prepend.
+    content: String,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+struct ReplaceLinesRequest {
+    /// The key of the memo to edit.
+    key: String,
+    /// The first line to replace (1-based).
+    start: usize,
+    /// The last line to replace (1-based, inclusive).
+    end: usize,
+    /// The replacement text. An empty string deletes the lines.
+    content: String,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+struct InsertAfterRequest {
+    /// The key of the memo to edit.
+    key: String,
+    /// The string to insert after (must occur exactly once).
+    anchor: String,
+    /// The content to insert.
+    content: String,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+struct ApplyPatchRequest {
+    /// The key of the memo to patch.
+    key: String,
+    /// A unified diff (e.g. the output of `diff -u`). File headers are optional.
+    patch: String,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+struct HistoryRequest {
+    /// The key of the memo to show the history of.
+    key: String,
+    /// The maximum number of revisions to return (default: 20).
+    limit: Option<u32>,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+struct RestoreRevisionRequest {
+    /// The key of the memo to restore.
+    key: String,
+    /// The git revision to restore the memo from (e.g. a commit hash from `history`).
+    revision: String,
+}
+
 #[derive(Debug, Deserialize, JsonSchema)]
 struct EditMemoRequest {
     /// The key of the memo to edit.
@@ -207,6 +664,8 @@ impl MemoServer {
             warn!(?e, key = %req.key, "failed to write memo");
             return Err(format!("failed to write memo '{}'", req.key));
         }
+        self.commit_memo(&req.key, "set_memo").await;
+        self.notify_memo_updated(&req.key).await;
         Ok(format!("Stored memo '{}'", req.key))
     }
 
@@ -228,20 +687,21 @@ impl MemoServer {
             self.backup_memo(&path, &req.key).await;
         }
         match tokio::fs::remove_file(&path).await {
-            Ok(()) => Ok(format!("Deleted memo '{}'", req.key)),
+            Ok(()) => {}
             Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
-                if existed {
-                    // The memo was backed up via rename, so it is considered deleted.
-                    Ok(format!("Deleted memo '{}'", req.key))
-                } else {
-                    Err(format!("memo '{}' not found", req.key))
+                if !existed {
+                    return Err(format!("memo '{}' not found", req.key));
                 }
+                // The memo was backed up via rename, so it is considered deleted.
             }
             Err(e) => {
                 warn!(?e, key = %req.key, "failed to delete memo");
-                Err(format!("failed to delete memo '{}'", req.key))
+                return Err(format!("failed to delete memo '{}'", req.key));
             }
         }
+        self.commit_memo(&req.key, "delete_memo").await;
+        self.notify_memo_updated(&req.key).await;
+        Ok(format!("Deleted memo '{}'", req.key))
     }
 
     /// Edit a memo by replacing a single occurrence of `old` with `new`.
@@ -254,89 +714,186 @@ impl MemoServer {
         if req.old.is_empty() {
             return Err("old must not be empty".to_string());
         }
-        let path = self.key_to_path(&req.key).map_err(|e| e.to_string())?;
-        let content = match tokio::fs::read_to_string(&path).await {
-            Ok(c) => c,
-            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
-                return Err(format!("memo '{}' not found", req.key));
-            }
-            Err(e) => {
-                warn!(?e, key = %req.key, "failed to read memo");
-                return Err(format!("failed to read memo '{}'", req.key));
-            }
-        };
+        self.modify_memo(&req.key, "edit_memo", |content| {
+            let first = match find_unique(content, &req.old) {
+                Ok(p) => p,
+                Err(FindError::NotFound) => {
+                    return Err(format!("old text not found in memo '{}'", req.key));
+                }
+                Err(FindError::Multiple) => {
+                    return Err(format!(
+                        "old text occurs multiple times in memo '{}'",
+                        req.key
+                    ));
+                }
+            };
+            let mut new_content = content.to_string();
+            new_content.replace_range(first..first + req.old.len(), &req.new);
+            Ok(new_content)
+        })
+        .await?;
+        Ok(format!("Edited memo '{}'", req.key))
+    }
+
+    /// Append content to the end of a memo. No separator is inserted.
+    #[tool]
+    #[instrument(skip(self))]
+    async fn append_memo(
+        &self,
+        Parameters(req): Parameters<AppendMemoRequest>,
+    ) -> Result<String, String> {
+        self.modify_memo(&req.key, "append_memo", |content| {
+            Ok(format!("{content}{}", req.content))
+        })
+        .await?;
+        Ok(format!("Appended to memo '{}'", req.key))
+    }
+
+    /// Prepend content to the beginning of a memo. No separator is inserted.
+    #[tool]
+    #[instrument(skip(self))]
+    async fn prepend_memo(
+        &self,
+        Parameters(req): Parameters<PrependMemoRequest>,
+    ) -> Result<String, String> {
+        self.modify_memo(&req.key, "prepend_memo", |content| {
+            Ok(format!("{}{content}", req.content))
+        })
+        .await?;
+        Ok(format!("Prepended to memo '{}'", req.key))
+    }
+
+    /// Replace the lines `start..=end` (1-based, inclusive) of a memo with `content`.
+    #[tool]
+    #[instrument(skip(self))]
+    async fn replace_lines(
+        &self,
+        Parameters(req): Parameters<ReplaceLinesRequest>,
+    ) -> Result<String, String> {
+        self.modify_memo(&req.key, "replace_lines", |content| {
+            replace_lines(content, req.start, req.end, &req.content).map_err(|e| e.to_string())
+        })
+        .await?;
+        Ok(format!(
+            "Replaced lines {}..{} of memo '{}'",
+            req.start, req.end, req.key
+        ))
+    }
+
+    /// Insert content right after a single occurrence of `anchor` in a memo.
+    #[tool]
+    #[instrument(skip(self))]
+    async fn insert_after(
+        &self,
+        Parameters(req): Parameters<InsertAfterRequest>,
+    ) -> Result<String, String> {
+        if req.anchor.is_empty() {
+            return Err("anchor must not be empty".to_string());
+        }
+        self.modify_memo(&req.key, "insert_after", |content| {
+            let position = match find_unique(content, &req.anchor) {
+                Ok(p) => p + req.anchor.len(),
+                Err(FindError::NotFound) => {
+                    return Err(format!("anchor not found in memo '{}'", req.key));
+                }
+                Err(FindError::Multiple) => {
+                    return Err(format!(
+                        "anchor occurs multiple times in memo '{}'",
+                        req.key
+                    ));
+                }
+            };
+            let mut new_content = content.to_string();
+            new_content.insert_str(position, &req.content);
+            Ok(new_content)
+        })
+        .await?;
+        Ok(format!("Inserted into memo '{}'", req.key))
+    }
 
-        let first = match content.find(&req.old) {
-            Some(p) => p,
-            None => {
-                return Err(format!("old text not found in memo '{}'", req.key));
+    /// Apply a unified diff to a memo. The context and removed lines of every hunk must match
+    /// the memo.
+    #[tool]
+    #[instrument(skip(self))]
+    async fn apply_patch(
+        &self,
+        Parameters(req): Parameters<ApplyPatchRequest>,
+    ) -> Result<String, String> {
+        self.modify_memo(&req.key, "apply_patch", |content| {
+            apply_unified_diff(content, &req.patch)
+                .map_err(|e| format!("failed to apply patch to memo '{}': {e}", req.key))
+        })
+        .await?;
+        Ok(format!("Patched memo '{}'", req.key))
+    }
+
+    /// Show the git history of a memo, newest first. Requires the git mode.
+    #[tool]
+    #[instrument(skip(self))]
+    async fn history(&self, Parameters(req): Parameters<HistoryRequest>) -> Result<String, String> {
+        let git = self.git()?;
+        self.key_to_path(&req.key).map_err(|e| e.to_string())?;
+        let log = match git
+            .log(&format!("{}.txt", req.key), req.limit.unwrap_or(20))
+            .await
+        {
+            Ok(log) => log,
+            Err(e) => {
+                warn!(?e, key = %req.key, "failed to read history");
+                return Err(format!("failed to read history of memo '{}'", req.key));
             }
         };
-        // Check for a second occurrence (including overlapping ones) by advancing only one char.
-        let mut second_search_start = first;
-        if let Some((delta, _)) = content[second_search_start..].char_indices().nth(1) {
-            second_search_start += delta;
+        let log = log.trim_end();
+        if log.is_empty() {
+            Ok(format!("No history for memo '{}'.", req.key))
         } else {
-            second_search_start = content.len();
-        }
-        if second_search_start < content.len() && content[second_search_start..].contains(&req.old)
-        {
-            return Err(format!(
-                "old text occurs multiple times in memo '{}'",
-                req.key
-            ));
-        }
-
-        if tokio::fs::metadata(&path).await.is_ok() {
-            self.backup_memo(&path, &req.key).await;
+            Ok(log.to_string())
         }
+    }
 
-        let mut new_content = content.clone();
-        new_content.replace_range(first..first + req.old.len(), &req.new);
-
-        if let Err(e) = tokio::fs::write(&path, &new_content).await {
+    /// Restore a memo to its content at the given git revision. Requires the git mode.
+    #[tool]
+    #[instrument(skip(self))]
+    async fn restore_revision(
+        &self,
+        Parameters(req): Parameters<RestoreRevisionRequest>,
+    ) -> Result<String, String> {
+        let git = self.git()?;
+        let path = self.key_to_path(&req.key).map_err(|e| e.to_string())?;
+        validate_revision(&req.revision).map_err(|e| e.to_string())?;
+        let content = match git.show(&req.revision, &format!("{}.txt", req.key)).await {
+            Ok(c) => c,
+            Err(e) => {
+                warn!(?e, key = %req.key, revision = %req.revision, "failed to read revision");
+                return Err(format!(
+                    "revision '{}' of memo '{}' not found",
+                    req.revision, req.key
+                ));
+            }
+        };
+        if let Err(e) = tokio::fs::write(&path, &content).await {
             warn!(?e, key = %req.key, "failed to write memo");
             return Err(format!("failed to write memo '{}'", req.key));
         }
-        Ok(format!("Edited memo '{}'", req.key))
+        self.commit_memo(&req.key, "restore_revision").await;
+        self.notify_memo_updated(&req.key).await;
+        Ok(format!(
+            "Restored memo '{}' to revision '{}'",
+            req.key, req.revision
+        ))
     }
 
     /// List all memo keys.
     #[tool]
     #[instrument(skip(self))]
     async fn list_memos(&self) -> Result<String, String> {
-        let mut entries = match tokio::fs::read_dir(&self.data_dir).await {
-            Ok(e) => e,
+        let keys = match self.list_keys().await {
+            Ok(keys) => keys,
             Err(e) => {
-                warn!(?e, "failed to read data directory");
+                warn!(?e, "failed to list memos");
                 return Err("failed to read memo list".to_string());
             }
         };
-        let mut keys = Vec::new();
-        loop {
-            match entries.next_entry().await {
-                Ok(Some(entry)) => {
-                    let name = entry.file_name();
-                    let name = name.to_string_lossy();
-                    // Check file type to avoid accidentally listing subdirectories (e.g. "backup")
-                    // that might have a .txt suffix in the future.
-                    let is_file = entry
-                        .file_type()
-                        .await
-                        .map(|t| t.is_file())
-                        .unwrap_or(false);
-                    if is_file && let Some(key) = name.strip_suffix(".txt") {
-                        keys.push(key.to_string());
-                    }
-                }
-                Ok(None) => break,
-                Err(e) => {
-                    warn!(?e, "failed to read directory entry");
-                    return Err("failed to read memo list".to_string());
-                }
-            }
-        }
-        keys.sort();
         if keys.is_empty() {
             Ok("No memos stored.".to_string())
         } else {
@@ -345,50 +902,380 @@ impl MemoServer {
     }
 }
 
-#[tool_handler]
-impl ServerHandler for MemoServer {
-    fn get_info(&self) -> ServerInfo {
-        ServerInfo::new(ServerCapabilities::builder().enable_tools().build())
-            .with_server_info(Implementation::new(
-                env!("CARGO_PKG_NAME"),
-                env!("CARGO_PKG_VERSION"),
-            ))
-            .with_instructions(
-                "A memo server for storing and retrieving temporary notes by key. \
-                Useful for preserving context, intermediate results, or reminders across tasks.",
-            )
-    }
+#[derive(Debug, Eq, PartialEq)]
+enum FindError {
+    NotFound,
+    Multiple,
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use clap::CommandFactory;
-    use rmcp::model::CallToolRequestParams;
-    use rmcp::service::RunningService;
-    use rmcp::{ClientHandler, RoleClient};
-    use serde_json::json;
-    use tempfile::tempdir;
+/// Returns the byte position of `needle` if it occurs exactly once in `haystack`.
+fn find_unique(haystack: &str, needle: &str) -> Result<usize, FindError> {
+    let first = haystack.find(needle).ok_or(FindError::NotFound)?;
+    // Check for a second occurrence (including overlapping ones) by advancing only one char.
+    let second_search_start = match haystack[first..].char_indices().nth(1) {
+        Some((delta, _)) => first + delta,
+        None => haystack.len(),
+    };
+    if second_search_start < haystack.len() && haystack[second_search_start..].contains(needle) {
+        return Err(FindError::Multiple);
+    }
+    Ok(first)
+}
 
-    #[test]
-    fn opt_should_parse_valid_args() {
-        Opt::command().debug_assert();
+fn replace_lines(content: &str, start: usize, end: usize, replacement: &str) -> Fallible<String> {
+    let lines = content.split_inclusive('\n').collect::<Vec<_>>();
+    ensure!(
+        1 <= start && start <= end,
+        "invalid line range {start}..{end}"
+    );
+    ensure!(
+        end <= lines.len(),
+        "line range {start}..{end} exceeds the memo of {} lines",
+        lines.len()
+    );
+
+    let mut new_content = lines[..start - 1].concat();
+    new_content.push_str(replacement);
+    // Keep the line break of the last replaced line unless the lines are deleted.
+    if !replacement.is_empty() && !replacement.ends_with('\n') && lines[end - 1].ends_with('\n') {
+        new_content.push('\n');
     }
+    new_content.push_str(&lines[end..].concat());
+    Ok(new_content)
+}
 
-    #[derive(Debug, Clone, Default)]
-    struct DummyClientHandler;
-    impl ClientHandler for DummyClientHandler {}
+#[derive(Debug)]
+struct Hunk {
+    old_start: usize,
+    old_count: usize,
+    old_lines: Vec<String>,
+    new_lines: Vec<String>,
+    /// The last old line is marked with "\ No newline at end of file".
+    old_no_newline: bool,
+    /// The last new line is marked with "\ No newline at end of file".
+    new_no_newline: bool,
+}
 
-    struct McpTestContext {
-        client: RunningService<RoleClient, DummyClientHandler>,
-        server_handle: tokio::task::JoinHandle<()>,
+impl Hunk {
+    /// 0-based index of the first old line. A hunk without old lines inserts after `old_start`.
+    fn old_index(&self) -> usize {
+        if self.old_count == 0 {
+            self.old_start
+        } else {
+            self.old_start.saturating_sub(1)
+        }
+    }
+}
+
+fn parse_hunk_range(range: &str) -> Fallible<(usize, usize)> {
+    match range.split_once(',') {
+        Some((start, count)) => Ok((start.parse()?, count.parse()?)),
+        None => Ok((range.parse()?, 1)),
+    }
+}
+
+fn parse_unified_diff(patch: &str) -> Fallible<Vec<Hunk>> {
+    let mut hunks = vec![];
+    let mut lines = patch.lines().peekable();
+    // Skip file headers before the first hunk.
+    while lines.next_if(|line| !line.starts_with("@@ -")).is_some() {}
+
+    while let Some(line) = lines.next() {
+        let header = line
+            .strip_prefix("@@ -")
+            .with_context(|| format!("unexpected line after hunk {}: {line}", hunks.len()))?;
+        let (ranges, _) = header
+            .split_once(" @@")
+            .with_context(|| format!("malformed hunk header: {line}"))?;
+        let (old_range, new_range) = ranges
+            .split_once(" +")
+            .with_context(|| format!("malformed hunk header: {line}"))?;
+        let (old_start, old_count) = parse_hunk_range(old_range)
+            .with_context(|| format!("malformed hunk header: {line}"))?;
+        let (_, new_count) = parse_hunk_range(new_range)
+            .with_context(|| format!("malformed hunk header: {line}"))?;
+
+        let mut hunk = Hunk {
+            old_start,
+            old_count,
+            old_lines: vec![],
+            new_lines: vec![],
+            old_no_newline: false,
+            new_no_newline: false,
+        };
+        // Kind of the previous body line to apply "\ No newline at end of file".
+        let mut previous = None;
+        loop {
+            let completed = hunk.old_lines.len() >= old_count && hunk.new_lines.len() >= new_count;
+            let line = match lines.peek() {
+                Some(line) if line.starts_with('\\') => *line,
+                Some(_) if completed => break,
+                Some(line) if line.starts_with("@@ -") => {
+                    bail!("hunk @@ -{ranges} @@ is truncated")
+                }
+                Some(line) => *line,
+                None if completed => break,
+                None => bail!("hunk @@ -{ranges} @@ is truncated"),
+            };
+            lines.next();
+
+            if line.starts_with('\\') {
+                match previous {
+                    Some('-') => hunk.old_no_newline = true,
+                    Some('+') => hunk.new_no_newline = true,
+                    Some(_) => {
+                        hunk.old_no_newline = true;
+                        hunk.new_no_newline = true;
+                    }
+                    None => bail!("unexpected line in hunk @@ -{ranges} @@: {line}"),
+                }
+            } else if let Some(line) = line.strip_prefix('-') {
+                hunk.old_lines.push(line.to_string());
+                previous = Some('-');
+            } else if let Some(line) = line.strip_prefix('+') {
+                hunk.new_lines.push(line.to_string());
+                previous = Some('+');
+            } else {
+                // Some tools strip the leading space of empty context lines.
+                let line = line.strip_prefix(' ').unwrap_or(line);
+                hunk.old_lines.push(line.to_string());
+                hunk.new_lines.push(line.to_string());
+                previous = Some(' ');
+            }
+        }
+        ensure!(
+            hunk.old_lines.len() == old_count && hunk.new_lines.len() == new_count,
+            "hunk @@ -{ranges} @@ does not match its line counts",
+        );
+        hunks.push(hunk);
+
+        // Trailing blank lines of the patch are not the part of the hunk.
+        if lines.clone().all(|line| line.trim().is_empty()) {
+            break;
+        }
+    }
+    Ok(hunks)
+}
+
+/// Applies a unified diff to `content`. Each hunk is matched against its context and removed
+/// lines, searching outward from the line number in the header to tolerate drifted numbers.
+fn apply_unified_diff(content: &str, patch: &str) -> Fallible<String> {
+    let hunks = parse_unified_diff(patch)?;
+    ensure!(!hunks.is_empty(), "patch contains no hunks");
+
+    let line_ending = if content.contains("\r\n") {
+        "\r\n"
+    } else {
+        "\n"
+    };
+    let mut trailing_newline = content.ends_with('\n');
+    let mut lines = content.lines().map(str::to_string).collect::<Vec<_>>();
+    // Difference between the line numbers in the patch and the current lines.
+    let mut offset = 0isize;
+    // Hunks must not overlap with the lines produced by the previous hunk.
+    let mut min_position = 0usize;
+    for (i, hunk) in hunks.iter().enumerate() {
+        let expected = (hunk.old_index() as isize + offset).max(0) as usize;
+        let max_position = lines
+            .len()
+            .checked_sub(hunk.old_lines.len())
+            .filter(|max| min_position <= *max)
+            .with_context(|| format!("hunk {} does not match the memo", i + 1))?;
+        let expected = expected.clamp(min_position, max_position);
+        let matches = |position: usize| {
+            lines[position..position + hunk.old_lines.len()] == hunk.old_lines[..]
+        };
+        let position = (0..=(max_position - min_position))
+            .flat_map(|distance| {
+                [
+                    expected.checked_sub(distance),
+                    expected.checked_add(distance),
+                ]
+            })
+            .flatten()
+            .filter(|position| (min_position..=max_position).contains(position))
+            .find(|position| matches(*position))
+            .with_context(|| format!("hunk {} does not match the memo", i + 1))?;
+
+        lines.splice(
+            position..position + hunk.old_lines.len(),
+            hunk.new_lines.iter().cloned(),
+        );
+        offset = position as isize - hunk.old_index() as isize + hunk.new_lines.len() as isize
+            - hunk.old_lines.len() as isize;
+        min_position = position + hunk.new_lines.len();
+        if min_position == lines.len() {
+            if hunk.new_no_newline {
+                trailing_newline = false;
+            } else if hunk.old_no_newline {
+                trailing_newline = true;
+            }
+        }
+    }
+
+    let mut new_content = lines.join(line_ending);
+    if trailing_newline && !lines.is_empty() {
+        new_content.push_str(line_ending);
+    }
+    Ok(new_content)
+}
+
+#[tool_handler]
+impl ServerHandler for MemoServer {
+    fn get_info(&self) -> ServerInfo {
+        ServerInfo::new(
+            ServerCapabilities::builder()
+                .enable_tools()
+                .enable_resources()
+                .enable_resources_subscribe()
+                .build(),
+        )
+        .with_server_info(Implementation::new(
+            env!("CARGO_PKG_NAME"),
+            env!("CARGO_PKG_VERSION"),
+        ))
+        .with_instructions(
+            "A memo server for storing and retrieving temporary notes by key. \
+                Useful for preserving context, intermediate results, or reminders across tasks. \
+                Each memo is also available as a `memo://<key>` resource.",
+        )
+    }
+
+    async fn list_resources(
+        &self,
+        _request: Option<PaginatedRequestParams>,
+        _context: RequestContext<RoleServer>,
+    ) -> Result<ListResourcesResult, McpError> {
+        let keys = self.list_keys().await.map_err(|e| {
+            warn!(?e, "failed to list memos");
+            McpError::internal_error("failed to read memo list", None)
+        })?;
+        let resources = keys
+            .into_iter()
+            .map(|key| RawResource::new(format!("{RESOURCE_URI_PREFIX}{key}"), key).no_annotation())
+            .collect();
+        Ok(ListResourcesResult::with_all_items(resources))
+    }
+
+    async fn read_resource(
+        &self,
+        request: ReadResourceRequestParams,
+        _context: RequestContext<RoleServer>,
+    ) -> Result<ReadResourceResult, McpError> {
+        let key = Self::uri_to_key(&request.uri)
+            .map_err(|e| McpError::invalid_params(e.to_string(), None))?;
+        let path = self
+            .key_to_path(key)
+            .map_err(|e| McpError::invalid_params(e.to_string(), None))?;
+        match tokio::fs::read_to_string(&path).await {
+            Ok(content) => Ok(ReadResourceResult::new(vec![ResourceContents::text(
+                content,
+                request.uri,
+            )])),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Err(
+                McpError::resource_not_found(format!("memo '{key}' not found"), None),
+            ),
+            Err(e) => {
+                warn!(?e, %key, "failed to read memo");
+                Err(McpError::internal_error(
+                    format!("failed to read memo '{key}'"),
+                    None,
+                ))
+            }
+        }
+    }
+
+    async fn subscribe(
+        &self,
+        request: SubscribeRequestParams,
+        context: RequestContext<RoleServer>,
+    ) -> Result<(), McpError> {
+        let key = Self::uri_to_key(&request.uri)
+            .map_err(|e| McpError::invalid_params(e.to_string(), None))?;
+        self.key_to_path(key)
+            .map_err(|e| McpError::invalid_params(e.to_string(), None))?;
+        let fingerprint = self.memo_fingerprint(key).await;
+        let start_watcher = {
+            let mut subscriptions = self
+                .subscriptions
+                .lock()
+                .map_err(|_| McpError::internal_error("failed to lock subscriptions", None))?;
+            subscriptions.peer = Some(context.peer);
+            subscriptions.keys.insert(key.to_string(), fingerprint);
+            !std::mem::replace(&mut subscriptions.watching, true)
+        };
+        if start_watcher {
+            self.spawn_resource_watcher();
+        }
+        Ok(())
+    }
+
+    async fn unsubscribe(
+        &self,
+        request: UnsubscribeRequestParams,
+        _context: RequestContext<RoleServer>,
+    ) -> Result<(), McpError> {
+        let key = Self::uri_to_key(&request.uri)
+            .map_err(|e| McpError::invalid_params(e.to_string(), None))?;
+        self.subscriptions
+            .lock()
+            .map_err(|_| McpError::internal_error("failed to lock subscriptions", None))?
+            .keys
+            .remove(key);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::CommandFactory;
+    use rmcp::model::CallToolRequestParams;
+    use rmcp::service::{NotificationContext, RunningService};
+    use rmcp::{ClientHandler, RoleClient};
+    use serde_json::json;
+    use tempfile::tempdir;
+
+    #[test]
+    fn opt_should_parse_valid_args() {
+        Opt::command().debug_assert();
+    }
+
+    #[derive(Debug, Clone)]
+    struct TestClientHandler {
+        resource_updated_tx: tokio::sync::mpsc::UnboundedSender<String>,
+    }
+
+    impl ClientHandler for TestClientHandler {
+        async fn on_resource_updated(
+            &self,
+            params: ResourceUpdatedNotificationParam,
+            _context: NotificationContext<RoleClient>,
+        ) {
+            self.resource_updated_tx.send(params.uri).ok();
+        }
+    }
+
+    struct McpTestContext {
+        client: RunningService<RoleClient, TestClientHandler>,
+        resource_updated_rx: tokio::sync::mpsc::UnboundedReceiver<String>,
+        server_handle: tokio::task::JoinHandle<()>,
     }
 
     impl McpTestContext {
         async fn new(data_dir: PathBuf) -> Self {
+            Self::with_server(MemoServer::new(data_dir)).await
+        }
+
+        async fn new_git(data_dir: PathBuf) -> Self {
+            let repo = GitRepo::init(data_dir.clone()).await.unwrap();
+            Self::with_server(MemoServer::new(data_dir).with_git(repo)).await
+        }
+
+        async fn with_server(server: MemoServer) -> Self {
             let (server_transport, client_transport) = tokio::io::duplex(4096);
             let server_handle = tokio::spawn(async move {
-                MemoServer::new(data_dir)
+                server
                     .serve(server_transport)
                     .await
                     .unwrap()
@@ -396,9 +1283,16 @@ mod tests {
                     .await
                     .unwrap();
             });
-            let client = DummyClientHandler.serve(client_transport).await.unwrap();
+            let (resource_updated_tx, resource_updated_rx) = tokio::sync::mpsc::unbounded_channel();
+            let client = TestClientHandler {
+                resource_updated_tx,
+            }
+            .serve(client_transport)
+            .await
+            .unwrap();
             Self {
                 client,
+                resource_updated_rx,
                 server_handle,
             }
         }
@@ -792,4 +1686,521 @@ mod tests {
         let content = ctx.call("get_memo", json!({ "key": "doc" })).await.unwrap();
         assert_eq!(content, "X");
     }
+
+    #[tokio::test]
+    async fn set_memo_should_commit_in_git_mode() {
+        let dir = tempdir().unwrap();
+        let ctx = McpTestContext::new_git(dir.path().to_path_buf()).await;
+
+        ctx.call("set_memo", json!({ "key": "note", "content": "v1" }))
+            .await
+            .unwrap();
+        ctx.call("set_memo", json!({ "key": "note", "content": "v2" }))
+            .await
+            .unwrap();
+        ctx.call(
+            "edit_memo",
+            json!({ "key": "note", "old": "v2", "new": "v3" }),
+        )
+        .await
+        .unwrap();
+
+        let history = ctx.call("history", json!({ "key": "note" })).await.unwrap();
+        let subjects = history
+            .lines()
+            .map(|line| line.splitn(3, ' ').nth(2).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            subjects,
+            vec!["edit_memo: note", "set_memo: note", "set_memo: note"]
+        );
+
+        // The git history replaces the backup rotation.
+        assert!(!dir.path().join("backup").exists());
+    }
+
+    #[tokio::test]
+    async fn set_memo_should_not_commit_unchanged_content_in_git_mode() {
+        let dir = tempdir().unwrap();
+        let ctx = McpTestContext::new_git(dir.path().to_path_buf()).await;
+
+        ctx.call("set_memo", json!({ "key": "note", "content": "v1" }))
+            .await
+            .unwrap();
+        ctx.call("set_memo", json!({ "key": "note", "content": "v1" }))
+            .await
+            .unwrap();
+
+        let history = ctx.call("history", json!({ "key": "note" })).await.unwrap();
+        assert_eq!(history.lines().count(), 1);
+    }
+
+    #[tokio::test]
+    async fn delete_memo_should_commit_in_git_mode() {
+        let dir = tempdir().unwrap();
+        let ctx = McpTestContext::new_git(dir.path().to_path_buf()).await;
+
+        ctx.call("set_memo", json!({ "key": "note", "content": "v1" }))
+            .await
+            .unwrap();
+        ctx.call("delete_memo", json!({ "key": "note" }))
+            .await
+            .unwrap();
+
+        let history = ctx
+            .call("history", json!({ "key": "note", "limit": 1 }))
+            .await
+            .unwrap();
+        assert!(history.ends_with("delete_memo: note"));
+    }
+
+    #[tokio::test]
+    async fn restore_revision_should_restore_previous_content() {
+        let dir = tempdir().unwrap();
+        let ctx = McpTestContext::new_git(dir.path().to_path_buf()).await;
+
+        ctx.call("set_memo", json!({ "key": "note", "content": "v1" }))
+            .await
+            .unwrap();
+        ctx.call("delete_memo", json!({ "key": "note" }))
+            .await
+            .unwrap();
+
+        let history = ctx.call("history", json!({ "key": "note" })).await.unwrap();
+        let revision = history.lines().last().unwrap().split(' ').next().unwrap();
+        let result = ctx
+            .call(
+                "restore_revision",
+                json!({ "key": "note", "revision": revision }),
+            )
+            .await
+            .unwrap();
+        assert_eq!(
+            result,
+            format!("Restored memo 'note' to revision '{revision}'")
+        );
+
+        let content = ctx
+            .call("get_memo", json!({ "key": "note" }))
+            .await
+            .unwrap();
+        assert_eq!(content, "v1");
+
+        let history = ctx
+            .call("history", json!({ "key": "note", "limit": 1 }))
+            .await
+            .unwrap();
+        assert!(history.ends_with("restore_revision: note"));
+    }
+
+    #[tokio::test]
+    async fn restore_revision_should_fail_for_option_like_revision() {
+        let dir = tempdir().unwrap();
+        let ctx = McpTestContext::new_git(dir.path().to_path_buf()).await;
+
+        let err = ctx
+            .call(
+                "restore_revision",
+                json!({ "key": "note", "revision": "--output=/tmp/x" }),
+            )
+            .await
+            .unwrap_err();
+        assert!(err.contains("revision"));
+    }
+
+    #[tokio::test]
+    async fn history_should_fail_without_git_mode() {
+        let dir = tempdir().unwrap();
+        let ctx = McpTestContext::new(dir.path().to_path_buf()).await;
+
+        let err = ctx
+            .call("history", json!({ "key": "note" }))
+            .await
+            .unwrap_err();
+        assert_eq!(err, "git mode is not enabled");
+    }
+
+    #[tokio::test]
+    async fn git_repo_init_should_import_existing_memos() {
+        let dir = tempdir().unwrap();
+        std::fs::write(dir.path().join("old.txt"), "legacy").unwrap();
+        std::fs::create_dir_all(dir.path().join("backup").join("old")).unwrap();
+        std::fs::write(dir.path().join("backup").join("old").join("1.txt"), "x").unwrap();
+
+        let repo = GitRepo::init(dir.path().to_path_buf()).await.unwrap();
+        let tracked = repo.run(&["ls-files"]).await.unwrap();
+        assert_eq!(tracked, "old.txt\n");
+    }
+
+    #[tokio::test]
+    async fn list_resources_should_return_all_memos() {
+        let dir = tempdir().unwrap();
+        let ctx = McpTestContext::new(dir.path().to_path_buf()).await;
+
+        ctx.call("set_memo", json!({ "key": "alpha", "content": "a" }))
+            .await
+            .unwrap();
+        ctx.call("set_memo", json!({ "key": "beta", "content": "b" }))
+            .await
+            .unwrap();
+
+        let resources = ctx.client.list_all_resources().await.unwrap();
+        let uris = resources.iter().map(|r| r.uri.as_str()).collect::<Vec<_>>();
+        assert_eq!(uris, vec!["memo://alpha", "memo://beta"]);
+    }
+
+    #[tokio::test]
+    async fn read_resource_should_return_memo_content() {
+        let dir = tempdir().unwrap();
+        let ctx = McpTestContext::new(dir.path().to_path_buf()).await;
+
+        ctx.call("set_memo", json!({ "key": "hello", "content": "world" }))
+            .await
+            .unwrap();
+
+        let result = ctx
+            .client
+            .read_resource(ReadResourceRequestParams::new("memo://hello"))
+            .await
+            .unwrap();
+        match &result.contents[..] {
+            [ResourceContents::TextResourceContents { uri, text, .. }] => {
+                assert_eq!(uri, "memo://hello");
+                assert_eq!(text, "world");
+            }
+            contents => panic!("unexpected contents: {contents:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn read_resource_should_fail_for_unknown_memo() {
+        let dir = tempdir().unwrap();
+        let ctx = McpTestContext::new(dir.path().to_path_buf()).await;
+
+        ctx.client
+            .read_resource(ReadResourceRequestParams::new("memo://missing"))
+            .await
+            .unwrap_err();
+        ctx.client
+            .read_resource(ReadResourceRequestParams::new("file:///etc/passwd"))
+            .await
+            .unwrap_err();
+    }
+
+    #[tokio::test]
+    async fn subscribe_should_notify_tool_changes() {
+        let dir = tempdir().unwrap();
+        let mut ctx = McpTestContext::new(dir.path().to_path_buf()).await;
+
+        ctx.client
+            .subscribe(SubscribeRequestParams::new("memo://note"))
+            .await
+            .unwrap();
+        ctx.call("set_memo", json!({ "key": "note", "content": "v1" }))
+            .await
+            .unwrap();
+        ctx.call("set_memo", json!({ "key": "other", "content": "v1" }))
+            .await
+            .unwrap();
+        ctx.call("delete_memo", json!({ "key": "note" }))
+            .await
+            .unwrap();
+
+        assert_eq!(ctx.resource_updated_rx.recv().await.unwrap(), "memo://note");
+        assert_eq!(ctx.resource_updated_rx.recv().await.unwrap(), "memo://note");
+        assert!(ctx.resource_updated_rx.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn subscribe_should_notify_changes_on_disk() {
+        let dir = tempdir().unwrap();
+        let mut ctx = McpTestContext::new(dir.path().to_path_buf()).await;
+
+        ctx.client
+            .subscribe(SubscribeRequestParams::new("memo://note"))
+            .await
+            .unwrap();
+        std::fs::write(dir.path().join("note.txt"), "edited by hand").unwrap();
+
+        let uri = tokio::time::timeout(RESOURCE_POLL_INTERVAL * 3, ctx.resource_updated_rx.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(uri, "memo://note");
+    }
+
+    #[tokio::test]
+    async fn unsubscribe_should_stop_notifications() {
+        let dir = tempdir().unwrap();
+        let interval = Duration::from_millis(50);
+        let mut ctx = McpTestContext::with_server(
+            MemoServer::new(dir.path().to_path_buf()).with_resource_poll_interval(interval),
+        )
+        .await;
+
+        ctx.client
+            .subscribe(SubscribeRequestParams::new("memo://note"))
+            .await
+            .unwrap();
+        std::fs::write(dir.path().join("note.txt"), "v1").unwrap();
+        let uri = tokio::time::timeout(interval * 20, ctx.resource_updated_rx.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(uri, "memo://note");
+
+        ctx.client
+            .unsubscribe(UnsubscribeRequestParams::new("memo://note"))
+            .await
+            .unwrap();
+        ctx.call("set_memo", json!({ "key": "note", "content": "v2" }))
+            .await
+            .unwrap();
+        std::fs::write(dir.path().join("note.txt"), "v3").unwrap();
+
+        // wait for several polls of the watcher.
+        assert!(
+            tokio::time::timeout(interval * 5, ctx.resource_updated_rx.recv())
+                .await
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn replace_lines_should_replace_range() {
+        assert_eq!(
+            replace_lines("a\nb\nc\nd\n", 2, 3, "X").unwrap(),
+            "a\nX\nd\n"
+        );
+        assert_eq!(
+            replace_lines("a\nb\nc", 3, 3, "X\nY").unwrap(),
+            "a\nb\nX\nY"
+        );
+        assert_eq!(replace_lines("a\nb\nc\n", 1, 2, "").unwrap(), "c\n");
+    }
+
+    #[test]
+    fn replace_lines_should_fail_for_invalid_range() {
+        replace_lines("a\nb\n", 0, 1, "X").unwrap_err();
+        replace_lines("a\nb\n", 2, 1, "X").unwrap_err();
+        replace_lines("a\nb\n", 2, 3, "X").unwrap_err();
+    }
+
+    #[test]
+    fn apply_unified_diff_should_apply_hunks() {
+        let content = "one\ntwo\nthree\nfour\nfive\nsix\n";
+        let patch = r#"--- a/memo.txt
++++ b/memo.txt
+@@ -1,3 +1,3 @@
+ one
+-two
++TWO
+ three
+@@ -5,2 +5,3 @@
+ five
++five and a half
+ six
+"#;
+        assert_eq!(
+            apply_unified_diff(content, patch).unwrap(),
+            "one\nTWO\nthree\nfour\nfive\nfive and a half\nsix\n"
+        );
+    }
+
+    #[test]
+    fn apply_unified_diff_should_tolerate_drifted_line_numbers() {
+        let content = "header\nextra\none\ntwo\nthree\n";
+        let patch = "@@ -1,3 +1,2 @@\n one\n-two\n three\n";
+        assert_eq!(
+            apply_unified_diff(content, patch).unwrap(),
+            "header\nextra\none\nthree\n"
+        );
+    }
+
+    #[test]
+    fn apply_unified_diff_should_fail_when_context_does_not_match() {
+        let content = "one\ntwo\nthree\n";
+        let patch = "@@ -1,3 +1,3 @@\n one\n-zwei\n+TWO\n three\n";
+        let err = apply_unified_diff(content, patch).unwrap_err();
+        assert_eq!(err.to_string(), "hunk 1 does not match the memo");
+    }
+
+    #[test]
+    fn apply_unified_diff_should_fail_for_miscounted_hunk() {
+        let content = "one\ntwo\nthree\n";
+        // The header counts only 2 lines but the body has 3.
+        let patch = "@@ -1,2 +1,2 @@\n one\n-two\n+TWO\n three\n";
+        let err = apply_unified_diff(content, patch).unwrap_err();
+        assert_eq!(err.to_string(), "unexpected line after hunk 1:  three");
+
+        // The body ends before the next hunk reaches its counts.
+        let patch = "@@ -1,3 +1,3 @@\n one\n-two\n+TWO\n@@ -3,1 +3,1 @@\n-three\n+THREE\n";
+        apply_unified_diff(content, patch).unwrap_err();
+
+        // Trailing blank lines are allowed.
+        let patch = "@@ -1,2 +1,2 @@\n one\n-two\n+TWO\n\n\n";
+        assert_eq!(
+            apply_unified_diff(content, patch).unwrap(),
+            "one\nTWO\nthree\n"
+        );
+    }
+
+    #[test]
+    fn apply_unified_diff_should_keep_crlf() {
+        let content = "one\r\ntwo\r\nthree\r\n";
+        let patch = "@@ -1,3 +1,3 @@\n one\n-two\n+TWO\n three\n";
+        assert_eq!(
+            apply_unified_diff(content, patch).unwrap(),
+            "one\r\nTWO\r\nthree\r\n"
+        );
+    }
+
+    #[test]
+    fn apply_unified_diff_should_follow_no_newline_marker() {
+        let patch = "@@ -1,2 +1,2 @@\n one\n-two\n+TWO\n\\ No newline at end of file\n";
+        assert_eq!(apply_unified_diff("one\ntwo\n", patch).unwrap(), "one\nTWO");
+
+        let patch = "@@ -1,2 +1,2 @@\n one\n-two\n\\ No newline at end of file\n+TWO\n";
+        assert_eq!(apply_unified_diff("one\ntwo", patch).unwrap(), "one\nTWO\n");
+
+        // Keep the original state without markers.
+        let patch = "@@ -1,2 +1,2 @@\n one\n-two\n+TWO\n";
+        assert_eq!(apply_unified_diff("one\ntwo", patch).unwrap(), "one\nTWO");
+    }
+
+    #[test]
+    fn apply_unified_diff_should_fail_for_truncated_hunk() {
+        apply_unified_diff("one\ntwo\n", "@@ -1,2 +1,2 @@\n one\n").unwrap_err();
+        apply_unified_diff("one\ntwo\n", "not a patch").unwrap_err();
+    }
+
+    #[tokio::test]
+    async fn append_memo_and_prepend_memo_should_add_content() {
+        let dir = tempdir().unwrap();
+        let ctx = McpTestContext::new(dir.path().to_path_buf()).await;
+
+        ctx.call("set_memo", json!({ "key": "doc", "content": "body\n" }))
+            .await
+            .unwrap();
+        let result = ctx
+            .call(
+                "append_memo",
+                json!({ "key": "doc", "content": "footer\n" }),
+            )
+            .await
+            .unwrap();
+        assert_eq!(result, "Appended to memo 'doc'");
+        let result = ctx
+            .call(
+                "prepend_memo",
+                json!({ "key": "doc", "content": "header\n" }),
+            )
+            .await
+            .unwrap();
+        assert_eq!(result, "Prepended to memo 'doc'");
+
+        let content = ctx.call("get_memo", json!({ "key": "doc" })).await.unwrap();
+        assert_eq!(content, "header\nbody\nfooter\n");
+
+        let backup_dir = dir.path().join("backup").join("doc");
+        assert_eq!(std::fs::read_dir(&backup_dir).unwrap().count(), 2);
+    }
+
+    #[tokio::test]
+    async fn append_memo_should_fail_when_memo_not_found() {
+        let dir = tempdir().unwrap();
+        let ctx = McpTestContext::new(dir.path().to_path_buf()).await;
+
+        let err = ctx
+            .call("append_memo", json!({ "key": "missing", "content": "x" }))
+            .await
+            .unwrap_err();
+        assert!(err.contains("not found"));
+    }
+
+    #[tokio::test]
+    async fn replace_lines_should_replace_memo_lines() {
+        let dir = tempdir().unwrap();
+        let ctx = McpTestContext::new(dir.path().to_path_buf()).await;
+
+        ctx.call("set_memo", json!({ "key": "doc", "content": "a\nb\nc\n" }))
+            .await
+            .unwrap();
+        let result = ctx
+            .call(
+                "replace_lines",
+                json!({ "key": "doc", "start": 2, "end": 2, "content": "B1\nB2" }),
+            )
+            .await
+            .unwrap();
+        assert_eq!(result, "Replaced lines 2..2 of memo 'doc'");
+
+        let content = ctx.call("get_memo", json!({ "key": "doc" })).await.unwrap();
+        assert_eq!(content, "a\nB1\nB2\nc\n");
+    }
+
+    #[tokio::test]
+    async fn insert_after_should_insert_after_anchor() {
+        let dir = tempdir().unwrap();
+        let ctx = McpTestContext::new(dir.path().to_path_buf()).await;
+
+        ctx.call(
+            "set_memo",
+            json!({ "key": "doc", "content": "## TODO\n- a\n## DONE\n" }),
+        )
+        .await
+        .unwrap();
+        ctx.call(
+            "insert_after",
+            json!({ "key": "doc", "anchor": "## TODO\n", "content": "- b\n" }),
+        )
+        .await
+        .unwrap();
+
+        let content = ctx.call("get_memo", json!({ "key": "doc" })).await.unwrap();
+        assert_eq!(content, "## TODO\n- b\n- a\n## DONE\n");
+
+        let err = ctx
+            .call(
+                "insert_after",
+                json!({ "key": "doc", "anchor": "- ", "content": "x" }),
+            )
+            .await
+            .unwrap_err();
+        assert!(err.contains("multiple times"));
+    }
+
+    #[tokio::test]
+    async fn apply_patch_should_patch_memo() {
+        let dir = tempdir().unwrap();
+        let ctx = McpTestContext::new(dir.path().to_path_buf()).await;
+
+        ctx.call("set_memo", json!({ "key": "doc", "content": "a\nb\nc\n" }))
+            .await
+            .unwrap();
+        let result = ctx
+            .call(
+                "apply_patch",
+                json!({ "key": "doc", "patch": "@@ -1,3 +1,3 @@\n a\n-b\n+B\n c\n" }),
+            )
+            .await
+            .unwrap();
+        assert_eq!(result, "Patched memo 'doc'");
+
+        let content = ctx.call("get_memo", json!({ "key": "doc" })).await.unwrap();
+        assert_eq!(content, "a\nB\nc\n");
+
+        let err = ctx
+            .call(
+                "apply_patch",
+                json!({ "key": "doc", "patch": "@@ -1,3 +1,3 @@\n a\n-b\n+B\n c\n" }),
+            )
+            .await
+            .unwrap_err();
+        assert!(err.contains("does not match"));
+
+        // A rejected patch must not touch the memo or its backups.
+        let backup_dir = dir.path().join("backup").join("doc");
+        assert_eq!(std::fs::read_dir(&backup_dir).unwrap().count(), 1);
+    }
 }
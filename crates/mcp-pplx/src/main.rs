@@ -14,20 +14,33 @@
  * limitations under the License.
  */
 
-use clap::Parser;
-use reqwest::header;
+use chrono::{DateTime, Datelike, Local, NaiveDate, NaiveTime, Utc};
+use clap::{Parser, ValueHint};
+use reqwest::{StatusCode, header};
 use rmcp::handler::server::tool::ToolRouter;
 use rmcp::handler::server::wrapper::Parameters;
 use rmcp::model::{Implementation, ServerCapabilities, ServerInfo};
 use rmcp::schemars::{self, JsonSchema};
 use rmcp::{Json, ServerHandler, ServiceExt, tool, tool_handler, tool_router};
+use rusqlite::{Connection, OptionalExtension, TransactionBehavior, params};
 use rust_myscript::prelude::*;
 use serde::{Deserialize, Serialize};
-use std::time::Duration;
+use std::collections::BTreeMap;
+use std::fmt::{Display, Formatter};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime};
 use tracing::Level;
+use url::Url;
 
-/// Perplexity Search API endpoint.
-const SEARCH_API_URL: &str = "https://api.perplexity.ai/search";
+/// Base URL of the Perplexity API. Endpoint paths are joined to it.
+const DEFAULT_BASE_URL: &str = "https://api.perplexity.ai/";
+
+/// Delay before the first retry. Doubled on every retry.
+const RETRY_BASE_DELAY: Duration = Duration::from_secs(1);
+
+/// Longest delay to wait before a retry. A longer `Retry-After` gives up the request.
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(60);
 
 /// HTTP request timeout. Matches the default of the official Perplexity MCP server.
 const REQUEST_TIMEOUT: Duration = Duration::from_secs(300);
@@ -38,6 +51,34 @@ struct Opt {
     /// API Key for Perplexity AI
     #[arg(long, env = "PERPLEXITY_API_KEY")]
     api_key: String,
+
+    /// Base URL of the Perplexity API, e.g. a proxy or a local stand-in for tests.
+    #[arg(long, env = "PERPLEXITY_BASE_URL", default_value = DEFAULT_BASE_URL, value_hint = ValueHint::Url)]
+    base_url: Url,
+
+    /// Number of retries on HTTP 429 and 5xx responses.
+    #[arg(long, default_value_t = 3)]
+    max_retries: u32,
+
+    /// Directory to store the response cache. Defaults to the user cache directory.
+    #[arg(long, value_hint = ValueHint::DirPath)]
+    cache_dir: Option<PathBuf>,
+
+    /// Seconds to reuse a cached search response. 0 disables the cache.
+    #[arg(long, default_value_t = 3600)]
+    cache_ttl: u64,
+
+    /// Directory to store the request log. Defaults to the user data directory.
+    #[arg(long, value_hint = ValueHint::DirPath)]
+    data_dir: Option<PathBuf>,
+
+    /// Maximum number of API requests per day (local time).
+    #[arg(long, env = "PERPLEXITY_DAILY_LIMIT")]
+    daily_limit: Option<u32>,
+
+    /// Maximum number of API requests per month (local time).
+    #[arg(long, env = "PERPLEXITY_MONTHLY_LIMIT")]
+    monthly_limit: Option<u32>,
 }
 
 /// Amount of content to extract from each page.
@@ -60,11 +101,93 @@ enum SearchRecencyFilter {
     Year,
 }
 
+/// Sonar model used to answer the question.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "kebab-case")]
+enum SonarModel {
+    #[default]
+    Sonar,
+    SonarPro,
+    SonarReasoningPro,
+}
+
+#[derive(Deserialize, JsonSchema)]
+struct AskToolParams {
+    /// 回答してほしい質問。背景・期間・求める観点を含めるほど的確な回答が得られる。
+    question: String,
+
+    /// 使用するモデル。"sonar"（高速・安価）/ "sonar-pro"（高品質・引用が多い）/
+    /// "sonar-reasoning-pro"（多段の推論が必要な質問向け）のいずれか。未指定なら "sonar"。
+    #[serde(default)]
+    model: Option<SonarModel>,
+
+    /// 検索結果を特定の言語に絞る ISO 639-1 言語コード（2 文字小文字、"en" / "ja" など）のリスト。
+    /// 限定しないなら空でよい。
+    #[serde(default)]
+    search_language_filter: Vec<String>,
+
+    /// 検索対象を特定のドメインに絞るドメイン名（プロトコル無し、"nature.com" など）のリスト。最大 20 件。
+    /// プレフィックス無しは許可リスト、先頭 "-" は除外リスト（両者は混在不可）。
+    /// ドメインを限定する必要がなければ空でよい。
+    #[serde(default)]
+    search_domain_filter: Vec<String>,
+
+    /// 公開時期の新しさで絞り込む。"hour" / "day" / "week" / "month" / "year" のいずれか。
+    /// 期間を限定しないなら未指定でよい。
+    #[serde(default)]
+    search_recency_filter: Option<SearchRecencyFilter>,
+
+    /// 回答の根拠として検索する本文量。"low" / "medium" / "high" のいずれか。未指定なら API の既定値。
+    /// "high" ほど詳細だが、Sonar はトークン課金のため費用が増える。
+    #[serde(default)]
+    search_context_size: Option<SearchContextSize>,
+}
+
+/// Search query. Multiple queries are sent as one multi-query request.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(untagged)]
+enum SearchQuery {
+    Single(String),
+    Multiple(Vec<String>),
+}
+
+impl SearchQuery {
+    /// The maximum number of queries in one multi-query request.
+    const MAX_QUERIES: usize = 5;
+
+    /// Checks the queries before sending them to the API.
+    fn validate(&self) -> Result<(), String> {
+        let SearchQuery::Multiple(queries) = self else {
+            return Ok(());
+        };
+        if !(1..=Self::MAX_QUERIES).contains(&queries.len()) {
+            return Err(format!(
+                "query には 1〜{} 件のクエリを指定してください",
+                Self::MAX_QUERIES
+            ));
+        }
+        if queries.iter().any(|query| query.trim().is_empty()) {
+            return Err("query に空のクエリは指定できません".to_string());
+        }
+        Ok(())
+    }
+}
+
+impl Display for SearchQuery {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SearchQuery::Single(query) => f.write_str(query),
+            SearchQuery::Multiple(queries) => f.write_str(&queries.join(" | ")),
+        }
+    }
+}
+
 #[derive(Deserialize, JsonSchema)]
 struct SearchToolParams {
     /// 検索したい内容を表すクエリ。具体的なキーワードや質問文を指定する。
     /// 文脈・期間・正確な用語を含めるほど関連性の高い結果が得られる（曖昧な語は避ける）。
-    query: String,
+    /// 関連する複数の観点を一度に調べる場合はクエリの配列（最大 5 件）を指定でき、結果はクエリごとにまとめて返る。
+    query: SearchQuery,
 
     /// 返す検索結果の最大件数。1〜20、未指定なら 10。
     /// 必要な件数だけにすると応答が速くなる（多いほど遅くなる）。
@@ -132,6 +255,11 @@ struct SearchToolParams {
     /// 日付フィルタ（search_*_date_filter / last_updated_*_filter）とは併用できない。
     #[serde(default)]
     search_recency_filter: Option<SearchRecencyFilter>,
+
+    /// true ならローカルキャッシュを使わずに必ず API へ問い合わせる（結果はキャッシュに保存する）。
+    /// 最新の結果が必要な場合のみ指定する。通常は未指定でよい。
+    #[serde(default)]
+    bypass_cache: bool,
 }
 
 /// Request body for the Perplexity Search API (`POST https://api.perplexity.ai/search`).
@@ -141,8 +269,8 @@ struct SearchToolParams {
 /// Fields left unset are omitted from the serialized body.
 #[derive(Debug, Serialize)]
 struct SearchPostRequest {
-    /// Search query.
-    query: String,
+    /// Search query, or up to 5 queries for a multi-query search.
+    query: SearchQuery,
     /// Maximum number of results to return (1-20, default 10).
     #[serde(skip_serializing_if = "Option::is_none")]
     max_results: Option<u8>,
@@ -184,12 +312,136 @@ struct SearchPostRequest {
     search_recency_filter: Option<SearchRecencyFilter>,
 }
 
+/// Request body for the Perplexity chat completions API
+/// (`POST https://api.perplexity.ai/chat/completions`).
+///
+/// See <https://docs.perplexity.ai/api-reference/chat-completions-post>.
+///
+/// Fields left unset are omitted from the serialized body.
+#[derive(Debug, Serialize)]
+struct ChatCompletionsPostRequest {
+    /// Sonar model name.
+    model: SonarModel,
+    /// Conversation messages.
+    messages: Vec<ChatMessage>,
+    /// Language filter (ISO 639-1, two lowercase letters).
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    search_language_filter: Vec<String>,
+    /// Domain filter (up to 20 entries). Bare domain = allowlist, leading `-` = denylist.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    search_domain_filter: Vec<String>,
+    /// Filter by publication recency.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    search_recency_filter: Option<SearchRecencyFilter>,
+    /// Web search options.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    web_search_options: Option<WebSearchOptions>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct ChatMessage {
+    role: String,
+    content: String,
+}
+
+#[derive(Debug, Serialize)]
+struct WebSearchOptions {
+    /// Amount of search context to retrieve.
+    search_context_size: SearchContextSize,
+}
+
+/// Response body of the chat completions API. Only the fields used by the `ask` tool.
+#[derive(Debug, Deserialize)]
+struct ChatCompletionsResponse {
+    choices: Vec<ChatChoice>,
+    #[serde(default)]
+    citations: Vec<String>,
+    #[serde(default)]
+    search_results: Vec<SearchResultEntry>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ChatChoice {
+    message: ChatMessage,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+struct AskToolResult {
+    /// 回答本文。本文中の "[1]" などは `citations` の 1 始まりの番号を指す
+    answer: String,
+
+    /// 引用元 URL の一覧
+    citations: Vec<String>,
+
+    /// 回答の根拠となった検索結果の一覧
+    search_results: Vec<SearchResultEntry>,
+}
+
 #[derive(Debug, Serialize, Deserialize, JsonSchema)]
 struct SearchResponse {
     /// 検索結果の一覧
     results: Vec<SearchResultEntry>,
 }
 
+/// Search API response to a multi-query request. `results` holds one list per query.
+#[derive(Debug, Deserialize)]
+struct MultiSearchResponse {
+    results: Vec<Vec<SearchResultEntry>>,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+struct QueryResults {
+    /// クエリ
+    query: String,
+
+    /// このクエリの検索結果の一覧
+    results: Vec<SearchResultEntry>,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+struct SearchToolResult {
+    /// 検索結果の一覧（クエリが 1 件の場合）
+    #[serde(skip_serializing_if = "Option::is_none")]
+    results: Option<Vec<SearchResultEntry>>,
+
+    /// クエリごとの検索結果の一覧（クエリを配列で指定した場合）
+    #[serde(skip_serializing_if = "Option::is_none")]
+    results_by_query: Option<Vec<QueryResults>>,
+
+    /// ローカルキャッシュから返した結果なら true
+    cached: bool,
+
+    /// キャッシュエントリの経過秒数（キャッシュから返した場合のみ）
+    #[serde(skip_serializing_if = "Option::is_none")]
+    cache_age_secs: Option<u64>,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+struct UsageToolResult {
+    /// 本日（ローカル時刻）の API リクエスト数
+    daily_requests: u32,
+
+    /// 1 日あたりのリクエスト上限（未設定なら省略）
+    #[serde(skip_serializing_if = "Option::is_none")]
+    daily_limit: Option<u32>,
+
+    /// 今月（ローカル時刻）の API リクエスト数
+    monthly_requests: u32,
+
+    /// 1 か月あたりのリクエスト上限（未設定なら省略）
+    #[serde(skip_serializing_if = "Option::is_none")]
+    monthly_limit: Option<u32>,
+
+    /// 今月の API ごとのリクエスト数
+    monthly_requests_by_api: BTreeMap<String, u32>,
+
+    /// 今月のリクエストのうち失敗したもの（HTTP エラーと通信エラー）の数
+    monthly_failed_requests: u32,
+
+    /// 今月のリクエストのうち応答を待っているものの数
+    monthly_pending_requests: u32,
+}
+
 #[derive(Debug, Serialize, Deserialize, JsonSchema)]
 struct SearchResultEntry {
     /// ページのタイトル
@@ -211,37 +463,520 @@ struct SearchResultEntry {
     last_updated: Option<String>,
 }
 
+/// Local cache of Search API responses keyed by the serialized request body.
+struct SearchCache {
+    conn: Mutex<Connection>,
+    ttl: Duration,
+}
+
+impl SearchCache {
+    fn create_with_path(db_path: &Path, ttl: Duration) -> Fallible<Self> {
+        Self::create_with_conn(Connection::open(db_path)?, ttl)
+    }
+
+    fn create_with_conn(conn: Connection, ttl: Duration) -> Fallible<Self> {
+        let db_version = conn.query_row("pragma user_version", [], |row| row.get::<_, i32>(0))?;
+        match db_version {
+            0 => {
+                conn.execute_batch(
+                    "create table search_cache (request text primary key not null, response text not null, created_at integer not null)",
+                )?;
+
+                conn.execute("pragma user_version = 1", ())?;
+            }
+            1 => (),
+            _ => bail!("unsupported db version: {db_version}"),
+        }
+
+        Ok(Self {
+            conn: Mutex::new(conn),
+            ttl,
+        })
+    }
+
+    fn get_conn(&self) -> Fallible<std::sync::MutexGuard<'_, Connection>> {
+        match self.conn.lock() {
+            Ok(data) => Ok(data),
+            Err(_) => bail!("failed to get connection"),
+        }
+    }
+
+    /// Returns the cached response body and its age if it has not expired.
+    fn find(&self, request: &str) -> Fallible<Option<(String, Duration)>> {
+        let conn = self.get_conn()?;
+        let row = conn
+            .query_row(
+                "select response, created_at from search_cache where request = ?1",
+                [request],
+                |row| Ok((row.get::<_, String>(0)?, row.get::<_, i64>(1)?)),
+            )
+            .optional()
+            .context("failed to query the cache")?;
+        let Some((response, created_at)) = row else {
+            return Ok(None);
+        };
+
+        let age = Duration::from_secs(unix_time_secs().saturating_sub(created_at).max(0) as u64);
+        if self.ttl <= age {
+            return Ok(None);
+        }
+
+        Ok(Some((response, age)))
+    }
+
+    fn save(&self, request: &str, response: &str) -> Fallible<()> {
+        let now = unix_time_secs();
+        let conn = self.get_conn()?;
+        conn.execute(
+            "delete from search_cache where created_at <= ?1",
+            // a huge TTL keeps all entries instead of overflowing.
+            [now.saturating_sub(i64::try_from(self.ttl.as_secs()).unwrap_or(i64::MAX))],
+        )
+        .context("failed to prune the cache")?;
+        conn.execute(
+            "insert or replace into search_cache (request, response, created_at) values (?1, ?2, ?3)",
+            params![request, response, now],
+        )
+        .context("failed to save the cache")?;
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Api {
+    Search,
+    ChatCompletions,
+}
+
+impl Api {
+    fn name(&self) -> &'static str {
+        match self {
+            Api::Search => "search",
+            Api::ChatCompletions => "chat_completions",
+        }
+    }
+
+    /// Endpoint path relative to the base URL.
+    fn path(&self) -> &'static str {
+        match self {
+            Api::Search => "search",
+            Api::ChatCompletions => "chat/completions",
+        }
+    }
+}
+
+impl Display for Api {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Api::Search => f.write_str("Search API"),
+            Api::ChatCompletions => f.write_str("Chat Completions API"),
+        }
+    }
+}
+
+/// Local log of every API request, used to enforce the request budget.
+struct UsageStore {
+    conn: Mutex<Connection>,
+    daily_limit: Option<u32>,
+    monthly_limit: Option<u32>,
+}
+
+impl UsageStore {
+    fn create_with_path(
+        db_path: &Path,
+        daily_limit: Option<u32>,
+        monthly_limit: Option<u32>,
+    ) -> Fallible<Self> {
+        Self::create_with_conn(Connection::open(db_path)?, daily_limit, monthly_limit)
+    }
+
+    fn create_with_conn(
+        conn: Connection,
+        daily_limit: Option<u32>,
+        monthly_limit: Option<u32>,
+    ) -> Fallible<Self> {
+        let db_version = conn.query_row("pragma user_version", [], |row| row.get::<_, i32>(0))?;
+        match db_version {
+            0 => {
+                conn.execute_batch(
+                    "create table request_log (id integer primary key autoincrement not null, created_at integer not null, api text not null, query text not null, status integer, latency_ms integer not null, completed integer not null default 1);
+create index index_request_log_created_at on request_log (created_at)",
+                )?;
+
+                conn.execute("pragma user_version = 2", ())?;
+            }
+            1 => {
+                conn.execute_batch(
+                    "alter table request_log add column completed integer not null default 1",
+                )?;
+
+                conn.execute("pragma user_version = 2", ())?;
+            }
+            2 => (),
+            _ => bail!("unsupported db version: {db_version}"),
+        }
+
+        Ok(Self {
+            conn: Mutex::new(conn),
+            daily_limit,
+            monthly_limit,
+        })
+    }
+
+    fn get_conn(&self) -> Fallible<std::sync::MutexGuard<'_, Connection>> {
+        match self.conn.lock() {
+            Ok(data) => Ok(data),
+            Err(_) => bail!("failed to get connection"),
+        }
+    }
+
+    /// Reserves a request within the daily and monthly limits and returns the ID of the pending
+    /// record. The record is completed by [`UsageStore::complete`].
+    ///
+    /// The check and the insertion are done in a transaction so concurrent requests cannot exceed
+    /// the limits.
+    fn reserve(&self, api: Api, query: &str) -> Fallible<i64> {
+        let (day_start, month_start) = period_starts(Local::now().date_naive());
+        let mut conn = self.get_conn()?;
+        let tx = conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+
+        if let Some(limit) = self.daily_limit
+            && limit <= count_since(&tx, day_start)?
+        {
+            bail!(
+                "本日のリクエスト数が上限 ({limit}) に達しました。日付が変わるまで Perplexity API は利用できません"
+            );
+        }
+        if let Some(limit) = self.monthly_limit
+            && limit <= count_since(&tx, month_start)?
+        {
+            bail!(
+                "今月のリクエスト数が上限 ({limit}) に達しました。月が変わるまで Perplexity API は利用できません"
+            );
+        }
+
+        tx.execute(
+            "insert into request_log (created_at, api, query, status, latency_ms, completed) values (?1, ?2, ?3, null, 0, 0)",
+            params![unix_time_secs(), api.name(), query],
+        )
+        .context("failed to record the request")?;
+        let id = tx.last_insert_rowid();
+        tx.commit()?;
+        Ok(id)
+    }
+
+    /// Completes the reserved request. `status` is `None` if no response was received.
+    fn complete(&self, id: i64, status: Option<StatusCode>, latency: Duration) -> Fallible<()> {
+        self.get_conn()?
+            .execute(
+                "update request_log set status = ?2, latency_ms = ?3, completed = 1 where id = ?1",
+                params![
+                    id,
+                    status.map(|data| data.as_u16()),
+                    latency.as_millis() as i64,
+                ],
+            )
+            .context("failed to record the request")?;
+        Ok(())
+    }
+
+    fn summary(&self) -> Fallible<UsageToolResult> {
+        let (day_start, month_start) = period_starts(Local::now().date_naive());
+        let conn = self.get_conn()?;
+        let daily_requests = count_since(&conn, day_start)?;
+
+        let mut stmt = conn.prepare_cached(
+            "select api, count(*) from request_log where ?1 <= created_at group by api",
+        )?;
+        let monthly_requests_by_api = stmt
+            .query_map([month_start], |row| Ok((row.get(0)?, row.get(1)?)))?
+            .collect::<Result<BTreeMap<String, u32>, _>>()?;
+        let (monthly_failed_requests, monthly_pending_requests) = conn.query_row(
+            "select count(*) filter (where completed and (status is null or status < 200 or 300 <= status)), count(*) filter (where not completed) from request_log where ?1 <= created_at",
+            [month_start],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )?;
+
+        Ok(UsageToolResult {
+            daily_requests,
+            daily_limit: self.daily_limit,
+            monthly_requests: monthly_requests_by_api.values().sum(),
+            monthly_limit: self.monthly_limit,
+            monthly_requests_by_api,
+            monthly_failed_requests,
+            monthly_pending_requests,
+        })
+    }
+}
+
+fn count_since(conn: &Connection, since: i64) -> Fallible<u32> {
+    Ok(conn.query_row(
+        "select count(*) from request_log where ?1 <= created_at",
+        [since],
+        |row| row.get(0),
+    )?)
+}
+
+/// Returns the unix times of the start of the day and the start of the month in local time.
+fn period_starts(today: NaiveDate) -> (i64, i64) {
+    let to_unix_time = |date: NaiveDate| {
+        let date_time = date.and_time(NaiveTime::MIN);
+        date_time
+            .and_local_timezone(Local)
+            .earliest()
+            .map(|data| data.timestamp())
+            .unwrap_or_else(|| date_time.and_utc().timestamp())
+    };
+    (
+        to_unix_time(today),
+        to_unix_time(today.with_day(1).expect("first day of month")),
+    )
+}
+
+fn unix_time_secs() -> i64 {
+    SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or_default()
+}
+
+/// HTTP client for the Perplexity API.
+struct ApiClient {
+    client: reqwest::Client,
+    api_key: String,
+    base_url: Url,
+    max_retries: u32,
+    usage: Option<UsageStore>,
+}
+
+impl ApiClient {
+    fn new(api_key: String, client: reqwest::Client) -> Self {
+        Self {
+            client,
+            api_key,
+            base_url: Url::parse(DEFAULT_BASE_URL).expect("base_url"),
+            max_retries: 0,
+            usage: None,
+        }
+    }
+
+    fn with_base_url(mut self, mut base_url: Url) -> Self {
+        // keep the last path segment when joining the endpoint path.
+        if !base_url.path().ends_with('/') {
+            base_url.set_path(&format!("{}/", base_url.path()));
+        }
+        self.base_url = base_url;
+        self
+    }
+
+    fn with_max_retries(mut self, max_retries: u32) -> Self {
+        self.max_retries = max_retries;
+        self
+    }
+
+    fn with_usage(mut self, usage: UsageStore) -> Self {
+        self.usage = Some(usage);
+        self
+    }
+
+    fn endpoint(&self, api: Api) -> Fallible<Url> {
+        self.base_url
+            .join(api.path())
+            .with_context(|| format!("invalid base URL: {}", self.base_url))
+    }
+
+    /// Sends a JSON request to the Perplexity API and returns the successful response body.
+    ///
+    /// HTTP 429 and 5xx responses are retried up to `max_retries` times with exponential
+    /// backoff, or after `Retry-After` if the server specifies it. Every attempt is refused if it
+    /// would exceed the budget, and recorded to `usage` otherwise.
+    async fn post(&self, api: Api, query: &str, body: &impl Serialize) -> Fallible<String> {
+        let url = self.endpoint(api)?;
+        let mut retries = 0;
+        loop {
+            let reservation = match &self.usage {
+                Some(usage) => Some(usage.reserve(api, query)?),
+                None => None,
+            };
+
+            let started = Instant::now();
+            let res = self
+                .client
+                .post(url.clone())
+                .header(header::ACCEPT, "application/json")
+                .bearer_auth(&self.api_key)
+                .json(body)
+                .send()
+                .await;
+
+            let res = match res {
+                Ok(res) => {
+                    let status = res.status();
+                    let retry_after = res
+                        .headers()
+                        .get(header::RETRY_AFTER)
+                        .and_then(|data| data.to_str().ok())
+                        .and_then(|data| parse_retry_after(data, Utc::now()));
+                    res.text().await.map(|text| (status, retry_after, text))
+                }
+                Err(e) => Err(e),
+            };
+
+            if let (Some(usage), Some(id)) = (&self.usage, reservation) {
+                let status = match &res {
+                    Ok((status, _, _)) => Some(*status),
+                    Err(e) => e.status(),
+                };
+                if let Err(e) = usage.complete(id, status, started.elapsed()) {
+                    warn!(?e, "failed to record the request");
+                }
+            }
+
+            let (status, retry_after, res_text) =
+                res.with_context(|| format!("{api} へのリクエストに失敗しました"))?;
+
+            if status.is_success() {
+                return Ok(res_text);
+            }
+
+            if retries < self.max_retries
+                && (status == StatusCode::TOO_MANY_REQUESTS || status.is_server_error())
+            {
+                let delay = retry_after.unwrap_or_else(|| backoff_delay(retries));
+                if delay <= MAX_RETRY_DELAY {
+                    retries += 1;
+                    info!(%status, ?delay, retries, "retry the request");
+                    tokio::time::sleep(delay).await;
+                    continue;
+                }
+            }
+
+            bail!("{api} がエラーを返しました ({status}): {res_text}");
+        }
+    }
+}
+
+/// Returns the delay before the retry after `retries` retries.
+fn backoff_delay(retries: u32) -> Duration {
+    RETRY_BASE_DELAY
+        .saturating_mul(2u32.saturating_pow(retries))
+        .min(MAX_RETRY_DELAY)
+}
+
+/// Parses the `Retry-After` header value, either delay seconds or an HTTP date.
+fn parse_retry_after(value: &str, now: DateTime<Utc>) -> Option<Duration> {
+    let value = value.trim();
+    if let Ok(secs) = value.parse::<u64>() {
+        return Some(Duration::from_secs(secs));
+    }
+
+    let date = DateTime::parse_from_rfc2822(value).ok()?;
+    Some(
+        (date.with_timezone(&Utc) - now)
+            .to_std()
+            .unwrap_or(Duration::ZERO),
+    )
+}
+
 struct McpServer {
     tool_router: ToolRouter<Self>,
-    api_key: String,
-    client: reqwest::Client,
+    api: ApiClient,
+    cache: Option<SearchCache>,
 }
 
 #[tool_router]
 impl McpServer {
-    fn new(api_key: String, client: reqwest::Client) -> Self {
+    fn new(api: ApiClient) -> Self {
         Self {
             tool_router: Self::tool_router(),
-            api_key,
-            client,
+            api,
+            cache: None,
         }
     }
 
+    fn with_cache(mut self, cache: SearchCache) -> Self {
+        self.cache = Some(cache);
+        self
+    }
+
     /// 自然言語風クエリでリアルタイム Web 検索し、構造化された検索結果を返す
     #[tool(annotations(read_only_hint = true, open_world_hint = true))]
     async fn search(
         &self,
         params: Parameters<SearchToolParams>,
-    ) -> Result<Json<SearchResponse>, String> {
+    ) -> Result<Json<SearchToolResult>, String> {
+        params.0.query.validate()?;
+
         let body = build_request_body(&params.0);
-        let response = execute_search(&self.client, &self.api_key, &body)
+        let cache_key = match &self.cache {
+            Some(_) => Some(serde_json::to_string(&body).map_err(|e| e.to_string())?),
+            None => None,
+        };
+
+        if let (Some(cache), Some(cache_key)) = (&self.cache, &cache_key)
+            && !params.0.bypass_cache
+        {
+            match cache.find(cache_key) {
+                Ok(Some((res_text, age))) => match build_search_result(&body.query, &res_text) {
+                    Ok(mut result) => {
+                        debug!(?age, "cache hit");
+                        result.cached = true;
+                        result.cache_age_secs = Some(age.as_secs());
+                        return Ok(Json(result));
+                    }
+                    Err(e) => warn!(?e, "failed to parse the cache"),
+                },
+                Ok(None) => (),
+                Err(e) => warn!(?e, "failed to read the cache"),
+            }
+        }
+
+        let search = async {
+            let res_text = execute_search(&self.api, &body).await?;
+            let result = build_search_result(&body.query, &res_text)?;
+            anyhow::Ok((res_text, result))
+        };
+        let (res_text, result) = search.await.map_err(|e| {
+            warn!(?e, "search failed");
+            e.to_string()
+        })?;
+
+        if let (Some(cache), Some(cache_key)) = (&self.cache, &cache_key)
+            && let Err(e) = cache.save(cache_key, &res_text)
+        {
+            warn!(?e, "failed to save the cache");
+        }
+
+        Ok(Json(result))
+    }
+
+    /// Perplexity Sonar に質問し、Web 検索に基づく回答本文と引用元・検索結果を返す
+    #[tool(annotations(read_only_hint = true, open_world_hint = true))]
+    async fn ask(&self, params: Parameters<AskToolParams>) -> Result<Json<AskToolResult>, String> {
+        let body = build_chat_request_body(&params.0);
+        let response = execute_chat_completions(&self.api, &body)
             .await
             .map_err(|e| {
-                warn!(?e, "search failed");
+                warn!(?e, "ask failed");
                 e.to_string()
             })?;
         Ok(Json(response))
     }
+
+    /// このサーバーが Perplexity API に送ったリクエスト数と上限を返す（API は呼び出さない）
+    #[tool(annotations(read_only_hint = true, open_world_hint = false))]
+    async fn usage(&self) -> Result<Json<UsageToolResult>, String> {
+        let usage = self
+            .api
+            .usage
+            .as_ref()
+            .ok_or_else(|| "リクエストの記録が無効です".to_string())?;
+        let summary = usage.summary().map_err(|e| {
+            warn!(?e, "failed to summarize the usage");
+            e.to_string()
+        })?;
+        Ok(Json(summary))
+    }
 }
 
 #[tool_handler]
@@ -252,7 +987,9 @@ impl ServerHandler for McpServer {
                 env!("CARGO_PKG_NAME"),
                 env!("CARGO_PKG_VERSION"),
             ))
-            .with_instructions("Perplexity API でリアルタイム Web 検索を行う")
+            .with_instructions(
+                "Perplexity API でリアルタイム Web 検索と検索結果に基づく質問応答を行う",
+            )
     }
 }
 
@@ -278,35 +1015,94 @@ fn build_request_body(params: &SearchToolParams) -> SearchPostRequest {
     }
 }
 
-/// Sends the request to the Perplexity Search API
-/// (`POST https://api.perplexity.ai/search`) and parses the response.
+/// Builds the request body for the Perplexity chat completions API
+/// (`POST https://api.perplexity.ai/chat/completions`).
 ///
-/// See <https://docs.perplexity.ai/api-reference/search-post>.
-async fn execute_search(
-    client: &reqwest::Client,
-    api_key: &str,
-    body: &SearchPostRequest,
-) -> Fallible<SearchResponse> {
-    let res = client
-        .post(SEARCH_API_URL)
-        .header(header::ACCEPT, "application/json")
-        .bearer_auth(api_key)
-        .json(body)
-        .send()
-        .await
-        .context("Search API へのリクエストに失敗しました")?;
+/// See <https://docs.perplexity.ai/api-reference/chat-completions-post>.
+fn build_chat_request_body(params: &AskToolParams) -> ChatCompletionsPostRequest {
+    ChatCompletionsPostRequest {
+        model: params.model.unwrap_or_default(),
+        messages: vec![ChatMessage {
+            role: "user".to_string(),
+            content: params.question.clone(),
+        }],
+        search_language_filter: params.search_language_filter.clone(),
+        search_domain_filter: params.search_domain_filter.clone(),
+        search_recency_filter: params.search_recency_filter,
+        web_search_options: params.search_context_size.map(|search_context_size| {
+            WebSearchOptions {
+                search_context_size,
+            }
+        }),
+    }
+}
 
-    let status = res.status();
-    let res_text = res
-        .text()
-        .await
-        .context("Search API レスポンスの読み取りに失敗しました")?;
+/// Sends the request to the Perplexity chat completions API
+/// (`POST https://api.perplexity.ai/chat/completions`) and parses the response.
+///
+/// See <https://docs.perplexity.ai/api-reference/chat-completions-post>.
+async fn execute_chat_completions(
+    api: &ApiClient,
+    body: &ChatCompletionsPostRequest,
+) -> Fallible<AskToolResult> {
+    let query = body
+        .messages
+        .last()
+        .map(|data| data.content.as_str())
+        .unwrap_or_default();
+    let res_text = api.post(Api::ChatCompletions, query, body).await?;
 
-    if !status.is_success() {
-        bail!("Search API がエラーを返しました ({status}): {res_text}");
-    }
+    parse_chat_completions_response(&res_text)
+}
+
+/// Parses the chat completions response body and removes the thinking process that the
+/// reasoning models put at the beginning of the answer.
+fn parse_chat_completions_response(text: &str) -> Fallible<AskToolResult> {
+    let response = serde_json::from_str::<ChatCompletionsResponse>(text)
+        .with_context(|| format!("Chat Completions API レスポンスの解析に失敗しました: {text}"))?;
+    let content = response
+        .choices
+        .into_iter()
+        .next()
+        .context("Chat Completions API レスポンスに choices がありません")?
+        .message
+        .content;
+    let answer = match (
+        content.trim_start().starts_with("<think>"),
+        content.find("</think>"),
+    ) {
+        (true, Some(end)) => content[end + "</think>".len()..].trim_start().to_string(),
+        _ => content,
+    };
+
+    Ok(AskToolResult {
+        answer,
+        citations: response.citations,
+        search_results: response.search_results,
+    })
+}
+
+/// Sends the request to the Perplexity Search API
+/// (`POST https://api.perplexity.ai/search`) and returns the response body.
+///
+/// See <https://docs.perplexity.ai/api-reference/search-post>.
+async fn execute_search(api: &ApiClient, body: &SearchPostRequest) -> Fallible<String> {
+    api.post(Api::Search, &body.query.to_string(), body).await
+}
 
-    parse_search_response(&res_text)
+/// Parses the Search API response body for `query`. The results of a multi-query request are
+/// grouped per query.
+fn build_search_result(query: &SearchQuery, text: &str) -> Fallible<SearchToolResult> {
+    let (results, results_by_query) = match query {
+        SearchQuery::Single(_) => (Some(parse_search_response(text)?.results), None),
+        SearchQuery::Multiple(queries) => (None, Some(parse_multi_search_response(queries, text)?)),
+    };
+    Ok(SearchToolResult {
+        results,
+        results_by_query,
+        cached: false,
+        cache_age_secs: None,
+    })
 }
 
 /// Parses the Search API response body. Unknown fields (e.g. `id`, `server_time`) are ignored.
@@ -315,6 +1111,27 @@ fn parse_search_response(text: &str) -> Fallible<SearchResponse> {
         .with_context(|| format!("Search API レスポンスの解析に失敗しました: {text}"))
 }
 
+/// Parses the Search API response body to a multi-query request and pairs each result list with
+/// its query.
+fn parse_multi_search_response(queries: &[String], text: &str) -> Fallible<Vec<QueryResults>> {
+    let response = serde_json::from_str::<MultiSearchResponse>(text)
+        .with_context(|| format!("Search API レスポンスの解析に失敗しました: {text}"))?;
+    ensure!(
+        response.results.len() == queries.len(),
+        "Search API レスポンスの結果の数 ({}) がクエリの数 ({}) と一致しません",
+        response.results.len(),
+        queries.len(),
+    );
+    Ok(queries
+        .iter()
+        .zip(response.results)
+        .map(|(query, results)| QueryResults {
+            query: query.clone(),
+            results,
+        })
+        .collect())
+}
+
 fn build_client() -> Fallible<reqwest::Client> {
     reqwest::Client::builder()
         .timeout(REQUEST_TIMEOUT)
@@ -326,7 +1143,36 @@ fn build_client() -> Fallible<reqwest::Client> {
         .context("HTTP クライアントの初期化に失敗しました")
 }
 
-async fn run_mcp_server(api_key: String) {
+fn project_dirs() -> Fallible<directories::ProjectDirs> {
+    directories::ProjectDirs::from("com", "sukawasatoru", "mcp-pplx")
+        .context("no valid home directory")
+}
+
+fn open_cache(cache_dir: Option<PathBuf>, ttl: Duration) -> Fallible<SearchCache> {
+    let cache_dir = match cache_dir {
+        Some(data) => data,
+        None => project_dirs()?.cache_dir().to_owned(),
+    };
+    std::fs::create_dir_all(&cache_dir)
+        .with_context(|| format!("failed to create {}", cache_dir.display()))?;
+    SearchCache::create_with_path(&cache_dir.join("cache.db"), ttl)
+}
+
+fn open_usage(
+    data_dir: Option<PathBuf>,
+    daily_limit: Option<u32>,
+    monthly_limit: Option<u32>,
+) -> Fallible<UsageStore> {
+    let data_dir = match data_dir {
+        Some(data) => data,
+        None => project_dirs()?.data_dir().to_owned(),
+    };
+    std::fs::create_dir_all(&data_dir)
+        .with_context(|| format!("failed to create {}", data_dir.display()))?;
+    UsageStore::create_with_path(&data_dir.join("usage.db"), daily_limit, monthly_limit)
+}
+
+async fn run_mcp_server(opt: Opt) {
     let client = match build_client() {
         Ok(client) => client,
         Err(e) => {
@@ -335,7 +1181,27 @@ async fn run_mcp_server(api_key: String) {
         }
     };
 
-    let server = McpServer::new(api_key, client).serve(rmcp::transport::stdio());
+    let mut api = ApiClient::new(opt.api_key, client)
+        .with_base_url(opt.base_url)
+        .with_max_retries(opt.max_retries);
+    match open_usage(opt.data_dir, opt.daily_limit, opt.monthly_limit) {
+        Ok(usage) => api = api.with_usage(usage),
+        Err(e) => {
+            error!(?e, "failed to open the request log");
+            return;
+        }
+    }
+
+    let mut server = McpServer::new(api);
+    if 0 < opt.cache_ttl {
+        match open_cache(opt.cache_dir, Duration::from_secs(opt.cache_ttl)) {
+            Ok(cache) => server = server.with_cache(cache),
+            // the cache is optional, so the search works without it.
+            Err(e) => warn!(?e, "failed to open the cache; run without the cache"),
+        }
+    }
+
+    let server = server.serve(rmcp::transport::stdio());
     let running = match server.await {
         Ok(running) => running,
         Err(e) => {
@@ -357,7 +1223,7 @@ async fn main() {
         .init();
 
     let opt = Opt::parse();
-    run_mcp_server(opt.api_key).await;
+    run_mcp_server(opt).await;
 }
 
 #[cfg(test)]
@@ -375,7 +1241,7 @@ mod tests {
 
     #[test]
     fn get_info_has_tools_capability() {
-        let server = McpServer::new(String::new(), reqwest::Client::new());
+        let server = McpServer::new(ApiClient::new(String::new(), reqwest::Client::new()));
         let info = server.get_info();
         assert!(
             info.capabilities.tools.is_some(),
@@ -386,7 +1252,7 @@ mod tests {
     #[test]
     fn build_request_body_minimal() {
         let params = SearchToolParams {
-            query: "rust language".to_string(),
+            query: SearchQuery::Single("rust language".to_string()),
             max_results: None,
             max_tokens_per_page: None,
             max_tokens: None,
@@ -399,6 +1265,7 @@ mod tests {
             search_after_date_filter: None,
             search_before_date_filter: None,
             search_recency_filter: None,
+            bypass_cache: false,
         };
         assert_eq!(
             serde_json::to_value(build_request_body(&params)).unwrap(),
@@ -409,7 +1276,7 @@ mod tests {
     #[test]
     fn build_request_body_full() {
         let params = SearchToolParams {
-            query: "rust language".to_string(),
+            query: SearchQuery::Single("rust language".to_string()),
             max_results: Some(5),
             max_tokens_per_page: Some(512),
             max_tokens: Some(2048),
@@ -422,6 +1289,7 @@ mod tests {
             search_after_date_filter: Some("01/01/2024".to_string()),
             search_before_date_filter: Some("12/31/2024".to_string()),
             search_recency_filter: Some(SearchRecencyFilter::Week),
+            bypass_cache: true,
         };
         assert_eq!(
             serde_json::to_value(build_request_body(&params)).unwrap(),
@@ -443,6 +1311,379 @@ mod tests {
         );
     }
 
+    #[test]
+    fn usage_store_enforces_daily_limit() {
+        let usage =
+            UsageStore::create_with_conn(Connection::open_in_memory().unwrap(), Some(2), None)
+                .unwrap();
+        let id = usage.reserve(Api::Search, "q1").unwrap();
+        usage
+            .complete(id, Some(StatusCode::OK), Duration::from_millis(10))
+            .unwrap();
+        let id = usage.reserve(Api::ChatCompletions, "q2").unwrap();
+        usage.complete(id, None, Duration::from_millis(10)).unwrap();
+        let err = usage.reserve(Api::Search, "q3").unwrap_err();
+        assert!(err.to_string().contains("本日のリクエスト数が上限 (2)"));
+    }
+
+    #[test]
+    fn usage_store_reserves_concurrent_requests_within_limit() {
+        let usage = std::sync::Arc::new(
+            UsageStore::create_with_conn(Connection::open_in_memory().unwrap(), Some(3), None)
+                .unwrap(),
+        );
+        let handles = (0..10)
+            .map(|i| {
+                let usage = usage.clone();
+                std::thread::spawn(move || usage.reserve(Api::Search, &format!("q{i}")).is_ok())
+            })
+            .collect::<Vec<_>>();
+        let reserved = handles
+            .into_iter()
+            .map(|handle| handle.join().unwrap())
+            .filter(|data| *data)
+            .count();
+        assert_eq!(reserved, 3);
+    }
+
+    #[test]
+    fn usage_store_keeps_previous_months() {
+        let usage = UsageStore::create_with_conn(Connection::open_in_memory().unwrap(), None, None)
+            .unwrap();
+        let (_, month_start) = period_starts(Local::now().date_naive());
+        usage
+            .get_conn()
+            .unwrap()
+            .execute(
+                "insert into request_log (created_at, api, query, status, latency_ms) values (?1, 'search', 'old', 200, 10)",
+                [month_start - 1],
+            )
+            .unwrap();
+
+        usage.reserve(Api::Search, "q1").unwrap();
+
+        let queries = usage
+            .get_conn()
+            .unwrap()
+            .prepare("select query from request_log")
+            .unwrap()
+            .query_map([], |row| row.get::<_, String>(0))
+            .unwrap()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(queries, vec!["old", "q1"]);
+        assert_eq!(usage.summary().unwrap().monthly_requests, 1);
+    }
+
+    #[test]
+    fn usage_store_enforces_monthly_limit() {
+        let usage =
+            UsageStore::create_with_conn(Connection::open_in_memory().unwrap(), None, Some(1))
+                .unwrap();
+        let id = usage.reserve(Api::Search, "q1").unwrap();
+        usage
+            .complete(id, Some(StatusCode::OK), Duration::from_millis(10))
+            .unwrap();
+        let err = usage.reserve(Api::Search, "q2").unwrap_err();
+        assert!(err.to_string().contains("今月のリクエスト数が上限 (1)"));
+    }
+
+    #[test]
+    fn usage_store_summary() {
+        let usage =
+            UsageStore::create_with_conn(Connection::open_in_memory().unwrap(), Some(10), None)
+                .unwrap();
+        for (api, query, status) in [
+            (Api::Search, "q1", Some(StatusCode::OK)),
+            (Api::Search, "q2", Some(StatusCode::TOO_MANY_REQUESTS)),
+            (Api::ChatCompletions, "q3", None),
+        ] {
+            let id = usage.reserve(api, query).unwrap();
+            usage
+                .complete(id, status, Duration::from_millis(10))
+                .unwrap();
+        }
+        // in flight.
+        usage.reserve(Api::Search, "q4").unwrap();
+
+        let summary = usage.summary().unwrap();
+        assert_eq!(summary.daily_requests, 4);
+        assert_eq!(summary.daily_limit, Some(10));
+        assert_eq!(summary.monthly_requests, 4);
+        assert_eq!(summary.monthly_limit, None);
+        assert_eq!(
+            summary.monthly_requests_by_api,
+            BTreeMap::from([
+                ("chat_completions".to_string(), 1),
+                ("search".to_string(), 3)
+            ])
+        );
+        assert_eq!(summary.monthly_failed_requests, 2);
+        assert_eq!(summary.monthly_pending_requests, 1);
+    }
+
+    #[test]
+    fn period_starts_returns_start_of_day_and_month() {
+        let (day_start, month_start) = period_starts(NaiveDate::from_ymd_opt(2026, 1, 15).unwrap());
+        assert_eq!(day_start - month_start, 14 * 24 * 60 * 60);
+    }
+
+    #[tokio::test]
+    async fn execute_search_refuses_request_over_budget() {
+        let usage =
+            UsageStore::create_with_conn(Connection::open_in_memory().unwrap(), Some(0), None)
+                .unwrap();
+        let params: SearchToolParams =
+            serde_json::from_value(json!({ "query": "rust language" })).unwrap();
+        let api =
+            ApiClient::new("dummy-api-key".to_string(), reqwest::Client::new()).with_usage(usage);
+        let err = execute_search(&api, &build_request_body(&params))
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("上限"));
+        assert_eq!(api.usage.unwrap().summary().unwrap().daily_requests, 0);
+    }
+
+    #[test]
+    fn build_request_body_multiple_queries() {
+        let params: SearchToolParams = serde_json::from_value(json!({
+            "query": ["rust language", "rust edition 2024"],
+            "max_results": 3,
+        }))
+        .unwrap();
+        assert_eq!(
+            serde_json::to_value(build_request_body(&params)).unwrap(),
+            json!({
+                "query": ["rust language", "rust edition 2024"],
+                "max_results": 3,
+            })
+        );
+    }
+
+    #[test]
+    fn search_query_validate() {
+        let multiple = |len: usize| SearchQuery::Multiple(vec!["q".to_string(); len]);
+        assert!(SearchQuery::Single("q".to_string()).validate().is_ok());
+        assert!(multiple(1).validate().is_ok());
+        assert!(multiple(5).validate().is_ok());
+        assert!(multiple(0).validate().is_err());
+        assert!(multiple(6).validate().is_err());
+        assert!(
+            SearchQuery::Multiple(vec!["q".to_string(), " ".to_string()])
+                .validate()
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn build_search_result_groups_results_per_query() {
+        let query = SearchQuery::Multiple(vec!["q1".to_string(), "q2".to_string()]);
+        let result = build_search_result(&query, MULTI_RES_TEXT).unwrap();
+        assert!(result.results.is_none());
+        let results_by_query = result.results_by_query.unwrap();
+        assert_eq!(results_by_query.len(), 2);
+        assert_eq!(results_by_query[0].query, "q1");
+        assert_eq!(results_by_query[0].results.len(), 1);
+        assert_eq!(results_by_query[0].results[0].title, "First Title");
+        assert_eq!(results_by_query[1].query, "q2");
+        assert_eq!(results_by_query[1].results.len(), 2);
+        assert_eq!(results_by_query[1].results[1].url, "https://example.org/");
+    }
+
+    #[test]
+    fn build_search_result_rejects_mismatched_query_count() {
+        let query = SearchQuery::Multiple(vec!["q1".to_string()]);
+        assert!(build_search_result(&query, MULTI_RES_TEXT).is_err());
+    }
+
+    #[test]
+    fn build_search_result_single_query() {
+        let query = SearchQuery::Single("q".to_string());
+        let result = build_search_result(&query, RES_TEXT).unwrap();
+        assert_eq!(result.results.unwrap().len(), 2);
+        assert!(result.results_by_query.is_none());
+    }
+
+    #[test]
+    fn api_client_endpoint_keeps_base_path() {
+        let api = ApiClient::new(String::new(), reqwest::Client::new());
+        assert_eq!(
+            api.endpoint(Api::ChatCompletions).unwrap().as_str(),
+            "https://api.perplexity.ai/chat/completions"
+        );
+
+        let api = api.with_base_url(Url::parse("http://localhost:8080/pplx").unwrap());
+        assert_eq!(
+            api.endpoint(Api::Search).unwrap().as_str(),
+            "http://localhost:8080/pplx/search"
+        );
+    }
+
+    #[test]
+    fn parse_retry_after_seconds_and_date() {
+        let now = DateTime::parse_from_rfc3339("2026-06-20T00:00:00Z")
+            .unwrap()
+            .with_timezone(&Utc);
+        assert_eq!(
+            parse_retry_after("120", now),
+            Some(Duration::from_secs(120))
+        );
+        assert_eq!(
+            parse_retry_after("Sat, 20 Jun 2026 00:00:30 GMT", now),
+            Some(Duration::from_secs(30))
+        );
+        assert_eq!(
+            parse_retry_after("Fri, 19 Jun 2026 23:59:00 GMT", now),
+            Some(Duration::ZERO)
+        );
+        assert_eq!(parse_retry_after("soon", now), None);
+    }
+
+    #[test]
+    fn backoff_delay_doubles_up_to_max() {
+        assert_eq!(backoff_delay(0), Duration::from_secs(1));
+        assert_eq!(backoff_delay(1), Duration::from_secs(2));
+        assert_eq!(backoff_delay(3), Duration::from_secs(8));
+        assert_eq!(backoff_delay(10), MAX_RETRY_DELAY);
+        assert_eq!(backoff_delay(u32::MAX), MAX_RETRY_DELAY);
+    }
+
+    /// Starts a stand-in of the Search API that responds with `responses` in order.
+    async fn serve_search_api(responses: Vec<(axum::http::StatusCode, &'static str)>) -> Url {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base_url = Url::parse(&format!("http://{}", listener.local_addr().unwrap())).unwrap();
+
+        let responses = std::sync::Arc::new(Mutex::new(responses.into_iter()));
+        let router = axum::Router::new().route(
+            "/search",
+            axum::routing::post(move || {
+                let (status, body) = responses.lock().unwrap().next().unwrap();
+                async move {
+                    (
+                        status,
+                        [(axum::http::header::RETRY_AFTER, "0")],
+                        body.to_string(),
+                    )
+                }
+            }),
+        );
+
+        tokio::spawn(async move {
+            axum::serve(listener, router).await.unwrap();
+        });
+
+        base_url
+    }
+
+    #[tokio::test]
+    async fn execute_search_retries_on_too_many_requests() {
+        let base_url = serve_search_api(vec![
+            (axum::http::StatusCode::TOO_MANY_REQUESTS, "{}"),
+            (axum::http::StatusCode::SERVICE_UNAVAILABLE, "{}"),
+            (axum::http::StatusCode::OK, RES_TEXT),
+        ])
+        .await;
+        let usage = UsageStore::create_with_conn(Connection::open_in_memory().unwrap(), None, None)
+            .unwrap();
+        let api = ApiClient::new("dummy-api-key".to_string(), reqwest::Client::new())
+            .with_base_url(base_url)
+            .with_max_retries(2)
+            .with_usage(usage);
+        let params: SearchToolParams =
+            serde_json::from_value(json!({ "query": "rust language" })).unwrap();
+
+        let res_text = execute_search(&api, &build_request_body(&params))
+            .await
+            .unwrap();
+        assert_eq!(res_text, RES_TEXT);
+
+        let summary = api.usage.unwrap().summary().unwrap();
+        assert_eq!(summary.daily_requests, 3);
+        assert_eq!(summary.monthly_failed_requests, 2);
+    }
+
+    #[tokio::test]
+    async fn execute_search_gives_up_after_max_retries() {
+        let base_url = serve_search_api(vec![
+            (axum::http::StatusCode::INTERNAL_SERVER_ERROR, "error 1"),
+            (axum::http::StatusCode::INTERNAL_SERVER_ERROR, "error 2"),
+        ])
+        .await;
+        let api = ApiClient::new("dummy-api-key".to_string(), reqwest::Client::new())
+            .with_base_url(base_url)
+            .with_max_retries(1);
+        let params: SearchToolParams =
+            serde_json::from_value(json!({ "query": "rust language" })).unwrap();
+
+        let err = execute_search(&api, &build_request_body(&params))
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("error 2"), "{err}");
+    }
+
+    #[test]
+    fn build_chat_request_body_minimal() {
+        let params: AskToolParams =
+            serde_json::from_value(json!({ "question": "What is Rust?" })).unwrap();
+        assert_eq!(
+            serde_json::to_value(build_chat_request_body(&params)).unwrap(),
+            json!({
+                "model": "sonar",
+                "messages": [{ "role": "user", "content": "What is Rust?" }],
+            })
+        );
+    }
+
+    #[test]
+    fn build_chat_request_body_full() {
+        let params = AskToolParams {
+            question: "What is Rust?".to_string(),
+            model: Some(SonarModel::SonarReasoningPro),
+            search_language_filter: vec!["en".to_string()],
+            search_domain_filter: vec!["-example.com".to_string()],
+            search_recency_filter: Some(SearchRecencyFilter::Month),
+            search_context_size: Some(SearchContextSize::Low),
+        };
+        assert_eq!(
+            serde_json::to_value(build_chat_request_body(&params)).unwrap(),
+            json!({
+                "model": "sonar-reasoning-pro",
+                "messages": [{ "role": "user", "content": "What is Rust?" }],
+                "search_language_filter": ["en"],
+                "search_domain_filter": ["-example.com"],
+                "search_recency_filter": "month",
+                "web_search_options": { "search_context_size": "low" },
+            })
+        );
+    }
+
+    #[test]
+    fn parse_chat_completions_response_ok() {
+        let parsed = parse_chat_completions_response(CHAT_RES_TEXT).unwrap();
+        assert_eq!(parsed.answer, "Rust is a systems programming language[1].");
+        assert_eq!(parsed.citations, vec!["https://www.rust-lang.org/"]);
+        assert_eq!(parsed.search_results.len(), 1);
+        assert_eq!(parsed.search_results[0].title, "Rust Programming Language");
+        assert_eq!(parsed.search_results[0].date.as_deref(), Some("2026-01-01"));
+    }
+
+    #[test]
+    fn parse_chat_completions_response_removes_thinking_process() {
+        let text = json!({
+            "choices": [{
+                "message": {
+                    "role": "assistant",
+                    "content": "<think>\nsome reasoning\n</think>\n\nThe answer.",
+                },
+            }],
+        })
+        .to_string();
+        let parsed = parse_chat_completions_response(&text).unwrap();
+        assert_eq!(parsed.answer, "The answer.");
+        assert!(parsed.citations.is_empty());
+        assert!(parsed.search_results.is_empty());
+    }
+
     #[test]
     fn parse_search_response_ignores_unknown_fields() {
         let parsed = parse_search_response(RES_TEXT).unwrap();
@@ -466,6 +1707,56 @@ mod tests {
         assert_eq!(parsed.results[0].last_updated, None);
     }
 
+    #[test]
+    fn search_cache_returns_fresh_entry() {
+        let cache = SearchCache::create_with_conn(
+            Connection::open_in_memory().unwrap(),
+            Duration::from_secs(60),
+        )
+        .unwrap();
+        assert!(cache.find("request").unwrap().is_none());
+
+        cache.save("request", RES_TEXT).unwrap();
+        let (response, age) = cache.find("request").unwrap().unwrap();
+        assert_eq!(response, RES_TEXT);
+        assert!(age < Duration::from_secs(60));
+        assert!(cache.find("other request").unwrap().is_none());
+    }
+
+    #[test]
+    fn search_cache_huge_ttl() {
+        let cache = SearchCache::create_with_conn(
+            Connection::open_in_memory().unwrap(),
+            Duration::from_secs(u64::MAX),
+        )
+        .unwrap();
+        cache.save("request", RES_TEXT).unwrap();
+        cache.save("other request", RES_TEXT).unwrap();
+        assert!(cache.find("request").unwrap().is_some());
+    }
+
+    #[test]
+    fn search_cache_ignores_expired_entry() {
+        let cache =
+            SearchCache::create_with_conn(Connection::open_in_memory().unwrap(), Duration::ZERO)
+                .unwrap();
+        cache.save("request", RES_TEXT).unwrap();
+        assert!(cache.find("request").unwrap().is_none());
+    }
+
+    #[test]
+    fn search_cache_key_distinguishes_parameters() {
+        let mut params: SearchToolParams =
+            serde_json::from_value(json!({ "query": "rust language" })).unwrap();
+        let key1 = serde_json::to_string(&build_request_body(&params)).unwrap();
+        params.bypass_cache = true;
+        let key2 = serde_json::to_string(&build_request_body(&params)).unwrap();
+        params.max_results = Some(3);
+        let key3 = serde_json::to_string(&build_request_body(&params)).unwrap();
+        assert_eq!(key1, key2, "bypass_cache must not affect the cache key");
+        assert_ne!(key1, key3);
+    }
+
     #[derive(Debug, Clone, Default)]
     struct DummyClientHandler;
     impl ClientHandler for DummyClientHandler {}
@@ -479,11 +1770,14 @@ mod tests {
         async fn new() -> Fallible<Self> {
             let (server_transport, client_transport) = tokio::io::duplex(4096);
             let server_handle = tokio::spawn(async move {
-                McpServer::new("dummy-api-key".to_string(), reqwest::Client::new())
-                    .serve(server_transport)
-                    .await?
-                    .waiting()
-                    .await?;
+                McpServer::new(ApiClient::new(
+                    "dummy-api-key".to_string(),
+                    reqwest::Client::new(),
+                ))
+                .serve(server_transport)
+                .await?
+                .waiting()
+                .await?;
                 anyhow::Ok(())
             });
             let client = DummyClientHandler.serve(client_transport).await?;
@@ -511,6 +1805,16 @@ mod tests {
         );
     }
 
+    #[tokio::test]
+    async fn mcp_exposes_ask_tool() {
+        let ctx = McpTestContext::new().await.unwrap();
+        let tools = ctx.client.list_all_tools().await.unwrap();
+        assert!(
+            tools.iter().any(|t| t.name.as_ref() == "ask"),
+            "server should expose an `ask` tool"
+        );
+    }
+
     const RES_TEXT: &str = r#"
 {
   "results": [
@@ -529,5 +1833,63 @@ mod tests {
   "id": "abc-123",
   "server_time": "2026-06-20T02:28:00Z"
 }
+"#;
+
+    const MULTI_RES_TEXT: &str = r#"
+{
+  "results": [
+    [
+      {
+        "title": "First Title",
+        "url": "https://example.com/"
+      }
+    ],
+    [
+      {
+        "title": "Second Title",
+        "url": "https://example.net/"
+      },
+      {
+        "title": "Third Title",
+        "url": "https://example.org/"
+      }
+    ]
+  ],
+  "id": "def-456"
+}
+"#;
+
+    const CHAT_RES_TEXT: &str = r#"
+{
+  "id": "4ecf86c7-f597-46dc-ad1b-784d383bd319",
+  "model": "sonar",
+  "created": 1234567890,
+  "usage": {
+    "prompt_tokens": 4,
+    "completion_tokens": 12,
+    "total_tokens": 16
+  },
+  "citations": [
+    "https://www.rust-lang.org/"
+  ],
+  "search_results": [
+    {
+      "title": "Rust Programming Language",
+      "url": "https://www.rust-lang.org/",
+      "date": "2026-01-01"
+    }
+  ],
+  "object": "chat.completion",
+  "choices": [
+    {
+      "index": 0,
+      "finish_reason": "stop",
+      "message": {
+        "role": "assistant",
+        "content": "Rust is a systems programming language[1]."
+      }
+    }
+  ]
+}
 "#;
 }
@@ -16,20 +16,31 @@
 
 use chrono::Datelike;
 use clap::builder::ArgPredicate;
-use clap::{Args, Parser, ValueEnum};
+use clap::{Args, Parser, ValueEnum, ValueHint};
+use futures::StreamExt;
 use regex::Regex;
 use reqwest::header;
+use rusqlite::{Connection, params};
 use rust_myscript::feature::otel::init_otel;
 use rust_myscript::prelude::*;
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 use serde_json::json;
+use std::collections::{HashMap, HashSet};
 use std::fmt::{Display, Formatter, Write};
+use std::path::{Path, PathBuf};
 use std::time::Duration;
+use tokio::sync::Semaphore;
 use tracing_subscriber::EnvFilter;
 use url::Url;
 
 #[derive(Parser)]
 struct Opt {
+    /// Configuration file that defines the topics to run. The topic options such as
+    /// `--ban-domain` are ignored, and `--telegram-bot-token` is used only if the file does not
+    /// have `telegram_bot_token`.
+    #[arg(long, env, value_hint = ValueHint::FilePath, conflicts_with = "model")]
+    config: Option<PathBuf>,
+
     /// Model name.
     #[arg(short, long, default_value = "sonar-pro")]
     model: OptModel,
@@ -42,17 +53,53 @@ struct Opt {
     #[arg(long, env, value_delimiter = ',')]
     ban_domain: Vec<String>,
 
+    /// Request the news items as structured JSON and render them with the template.
+    /// `--telegram-text-template` is a MiniJinja template that receives `topic`, `date` and
+    /// `items` (`title`, `summary`, `url`, `source` and `published`).
+    #[arg(long, env)]
+    structured: bool,
+
     #[command(flatten)]
     telegram: Option<OptTelegram>,
 
+    /// Authorization token to use Bot.
+    #[arg(long, env)]
+    telegram_bot_token: Option<String>,
+
+    /// Database to remember the delivered items. Defaults to the user data directory.
+    #[arg(long, env, value_hint = ValueHint::FilePath)]
+    history_file: Option<PathBuf>,
+
+    /// Days to remember the delivered items.
+    #[arg(long, env, default_value_t = 30)]
+    history_retention_days: u32,
+
+    /// Feed file to write the delivered items to. Entries of the previous runs are kept.
+    #[arg(long, env, value_hint = ValueHint::FilePath)]
+    feed_file: Option<PathBuf>,
+
+    /// Format of the feed file.
+    #[arg(long, env, default_value = "atom")]
+    feed_format: FeedFormat,
+
+    /// URL the feed file is served at.
+    #[arg(long, env)]
+    feed_url: Option<Url>,
+
+    /// Maximum number of entries in the feed file.
+    #[arg(long, env, default_value_t = 50)]
+    feed_max_entries: usize,
+
     /// OpenTelemetry logs endpoint.
     #[arg(long, env)]
     otel_logs_endpoint: Option<Url>,
 }
 
 #[allow(clippy::enum_variant_names)]
-#[derive(Clone, ValueEnum)]
+#[derive(Clone, Copy, Default, Deserialize, ValueEnum)]
+#[serde(rename_all = "kebab-case")]
 enum OptModel {
+    #[default]
     SonarPro,
     SonarReasoningPro,
     SonarDeepResearch,
@@ -83,10 +130,6 @@ struct OptTelegram {
     )]
     use_telegram: bool,
 
-    /// Authorization token to use Bot.
-    #[arg(long, env, requires = "use_telegram")]
-    telegram_bot_token: Option<String>,
-
     /// Chat ID to notify to Telegram
     #[arg(long, env, requires = "use_telegram")]
     telegram_chat_id: Option<String>,
@@ -105,16 +148,186 @@ enum SearchContextSize {
     Low,
 }
 
-#[allow(dead_code)]
-#[derive(Serialize)]
+#[derive(Clone, Copy, Default, Deserialize, Serialize)]
 #[serde(rename_all = "kebab-case")]
 enum SearchRecencyFilter {
     Month,
+    #[default]
     Week,
     Day,
     Hour,
 }
 
+/// Titles at least this similar to a delivered one are treated as the same item.
+const DUPLICATE_TITLE_SIMILARITY: f64 = 0.8;
+
+const DEFAULT_SYSTEM_PROMPT: &str =
+    "最新のニュースを提供してください。詳細はユーザーのプロンプトに従ってください。";
+
+/// Prompt of the topic defined by the command line options.
+const DEFAULT_PROMPT: &str = r#"
+{year}年{month}月{day}日の主要ニュースを要約してください。各項目は150字以内で簡潔にまとめ、以下のカテゴリーごとに、信頼性の高い情報源から検証された情報を整理してください：
+
+1. **トップニュース**：過去24時間以内の重要ニュース3点。各ニュースに少なくとも2つの一次情報源と発表時間を明記し、特に公式発表や複数メディアが報じている内容を優先。
+
+2. **テクノロジー・IT**：新製品発表、技術革新、AIやデジタル分野の最新動向3点。企業の公式発表や専門メディアからの情報を引用してください。特に発表から48時間以内の最新情報を優先。
+
+3. **社会・生活**：日本国内の社会現象、健康・安全情報など生活関連ニュース2点。政府機関や公共団体の発表、専門家の見解を含めてください。
+
+4. **今日のトレンド**：Twitter(X)等で拡散している話題1点を、トレンド化の背景や関連データと共に説明してください。
+
+5. **ゲーム**：下記のいずれかに該当するゲーム関連のニュース3点をまとめてください：
+- 新作発表・重要アップデート情報（開発元の公式発表があればそれを優先）
+- 任天堂関連の公式発表や新作情報
+- コンソール/PCゲーム市場の重要な動向
+- 人気タイトルのイベント情報
+- 業界の経済動向や企業戦略
+
+6. **天気情報**：{year}年{month}月{day}日の全国の気象状況と横浜市の詳細予報。
+
+最後に、ニュース全体から見える重要なトレンドや関連性を3文以内でまとめ、使用した合計文字数を報告してください。
+"#;
+
+/// Configuration file given by `--config`.
+#[derive(Deserialize)]
+struct Config {
+    /// Number of topics to run at the same time.
+    #[serde(default = "default_concurrency")]
+    concurrency: usize,
+
+    /// Authorization token of the Telegram Bot for the topics that notify to Telegram.
+    telegram_bot_token: Option<String>,
+
+    #[serde(rename = "topic")]
+    topics: Vec<TopicConfig>,
+}
+
+impl Config {
+    /// Uses the `telegram_bot_token` of the command line or the environment if the file does not
+    /// have it.
+    fn or_telegram_bot_token(mut self, telegram_bot_token: Option<&str>) -> Self {
+        if self.telegram_bot_token.is_none() {
+            self.telegram_bot_token = telegram_bot_token.map(str::to_owned);
+        }
+        self
+    }
+}
+
+fn default_concurrency() -> usize {
+    2
+}
+
+#[derive(Deserialize)]
+struct TopicConfig {
+    /// Name to identify the topic in the logs and the report.
+    name: String,
+
+    /// User prompt. `{year}`, `{month}` and `{day}` are replaced with the current date.
+    prompt: String,
+
+    #[serde(default = "default_system_prompt")]
+    system_prompt: String,
+
+    #[serde(default)]
+    model: OptModel,
+
+    /// Domains to exclude from the search.
+    #[serde(default)]
+    ban_domains: Vec<String>,
+
+    /// Domains to limit the search to. Cannot be combined with `ban_domains`.
+    #[serde(default)]
+    allow_domains: Vec<String>,
+
+    #[serde(default)]
+    recency: SearchRecencyFilter,
+
+    /// Requests the items as JSON that matches [`structured_response_format`] and renders them
+    /// with the Telegram template as a MiniJinja template.
+    #[serde(default)]
+    structured: bool,
+
+    telegram: Option<TopicTelegram>,
+}
+
+fn default_system_prompt() -> String {
+    DEFAULT_SYSTEM_PROMPT.to_string()
+}
+
+#[derive(Deserialize)]
+struct TopicTelegram {
+    /// Chat ID to notify to Telegram
+    chat_id: String,
+
+    /// Template to send message that include `{pplx}` to insert value
+    template: String,
+}
+
+fn load_config(path: &Path, telegram_bot_token: Option<&str>) -> Fallible<Config> {
+    let toml_string = std::fs::read_to_string(path)
+        .with_context(|| format!("failed to read {}", path.display()))?;
+    let config = toml::from_str::<Config>(&toml_string)
+        .with_context(|| format!("failed to parse {}", path.display()))?
+        .or_telegram_bot_token(telegram_bot_token);
+    verify_config(&config)?;
+    Ok(config)
+}
+
+fn verify_config(config: &Config) -> Fallible<()> {
+    ensure!(0 < config.concurrency, "concurrency should be at least 1");
+    ensure!(!config.topics.is_empty(), "no topic is defined");
+
+    let mut names = HashSet::new();
+    for topic in &config.topics {
+        ensure!(
+            names.insert(topic.name.as_str()),
+            "duplicate topic name: {}",
+            topic.name
+        );
+        ensure!(
+            topic.ban_domains.is_empty() || topic.allow_domains.is_empty(),
+            "topic {}: ban_domains and allow_domains cannot be combined",
+            topic.name
+        );
+        ensure!(
+            topic.telegram.is_none() || config.telegram_bot_token.is_some(),
+            "topic {}: telegram_bot_token is required to notify to Telegram",
+            topic.name
+        );
+    }
+    Ok(())
+}
+
+/// Builds the configuration of the single topic given by the command line options.
+fn config_from_opt(opt: &Opt) -> Config {
+    let telegram = opt.telegram.as_ref().map(|telegram| TopicTelegram {
+        chat_id: telegram
+            .telegram_chat_id
+            .clone()
+            .expect("telegram_chat_id should not be None"),
+        template: telegram
+            .telegram_text_template
+            .clone()
+            .expect("telegram_text_template should not be None"),
+    });
+
+    Config {
+        concurrency: 1,
+        telegram_bot_token: telegram.as_ref().and(opt.telegram_bot_token.clone()),
+        topics: vec![TopicConfig {
+            name: "default".to_string(),
+            prompt: DEFAULT_PROMPT.to_string(),
+            system_prompt: default_system_prompt(),
+            model: opt.model,
+            ban_domains: opt.ban_domain.clone(),
+            allow_domains: vec![],
+            recency: SearchRecencyFilter::Week,
+            structured: opt.structured,
+            telegram,
+        }],
+    }
+}
+
 #[tokio::main]
 async fn main() -> Fallible<()> {
     dotenv::dotenv().ok();
@@ -126,9 +339,9 @@ async fn main() -> Fallible<()> {
         .user_agent("pplx-news (https://github.com/sukawasatoru/rust-myscript/)")
         .build()?;
 
-    let otel_guard = match opt.otel_logs_endpoint {
+    let otel_guard = match &opt.otel_logs_endpoint {
         Some(endpoint) => {
-            let guard = init_otel(endpoint, env!("CARGO_BIN_NAME"))?;
+            let guard = init_otel(endpoint.clone(), env!("CARGO_BIN_NAME"))?;
             Some(guard)
         }
         None => {
@@ -140,124 +353,191 @@ async fn main() -> Fallible<()> {
         }
     };
 
-    let current = chrono::Local::now();
-    let res = client
-        .post("https://api.perplexity.ai/chat/completions")
-        .header(header::ACCEPT, "application/json")
-        .header(header::CONTENT_TYPE, "application/json")
-        .bearer_auth(&opt.api_key)
-        .body(serde_json::to_string(&json!({
-            "model": opt.model.to_string(),
-            "search_domain_filter": opt
-                .ban_domain
-                .iter()
-                .map(|data| format!("-{data}"))
-                .collect::<Vec<_>>(),
-            "web_search_options": {
-                "search_context_size": SearchContextSize::High,
-            },
-            "search_recency_filter": SearchRecencyFilter::Week,
-            "messages": [
-                {
-                    "role": "system",
-                    "content": "最新のニュースを提供してください。詳細はユーザーのプロンプトに従ってください。",
-                },
-                {
-                    "role": "user",
-                    "content": format!(
-                        r#"
-{year}年{month}月{day}日の主要ニュースを要約してください。各項目は150字以内で簡潔にまとめ、以下のカテゴリーごとに、信頼性の高い情報源から検証された情報を整理してください：
+    let config = match &opt.config {
+        Some(path) => load_config(path, opt.telegram_bot_token.as_deref())?,
+        None => config_from_opt(&opt),
+    };
 
-1. **トップニュース**：過去24時間以内の重要ニュース3点。各ニュースに少なくとも2つの一次情報源と発表時間を明記し、特に公式発表や複数メディアが報じている内容を優先。
+    let history = HistoryStore::create_with_path(
+        &match &opt.history_file {
+            Some(data) => data.clone(),
+            None => default_history_path()?,
+        },
+        Duration::from_secs(u64::from(opt.history_retention_days) * 24 * 60 * 60),
+    )?;
+    history.prune(chrono::Utc::now().timestamp())?;
 
-2. **テクノロジー・IT**：新製品発表、技術革新、AIやデジタル分野の最新動向3点。企業の公式発表や専門メディアからの情報を引用してください。特に発表から48時間以内の最新情報を優先。
+    let context = TopicContext {
+        client: &client,
+        api_key: &opt.api_key,
+        telegram_bot_token: config.telegram_bot_token.as_deref(),
+        history: &history,
+        otel: otel_guard.is_some(),
+        print_topic_name: 1 < config.topics.len(),
+        feed: opt.feed_file.is_some(),
+    };
 
-3. **社会・生活**：日本国内の社会現象、健康・安全情報など生活関連ニュース2点。政府機関や公共団体の発表、専門家の見解を含めてください。
+    let semaphore = Semaphore::new(config.concurrency);
+    let mut futs = futures::stream::FuturesOrdered::new();
+    for topic in &config.topics {
+        let context = &context;
+        let semaphore = &semaphore;
+        futs.push_back(async move {
+            let _permit = semaphore.acquire().await?;
+            run_topic(context, topic).await
+        });
+    }
 
-4. **今日のトレンド**：Twitter(X)等で拡散している話題1点を、トレンド化の背景や関連データと共に説明してください。
+    let mut failed = 0;
+    for topic in &config.topics {
+        match futs.next().await.expect("result of the topic") {
+            Ok(()) => info!(topic = topic.name, "succeeded"),
+            Err(e) => {
+                error!(topic = topic.name, ?e, "failed");
+                failed += 1;
+            }
+        }
+    }
 
-5. **ゲーム**：下記のいずれかに該当するゲーム関連のニュース3点をまとめてください：
-- 新作発表・重要アップデート情報（開発元の公式発表があればそれを優先）
-- 任天堂関連の公式発表や新作情報
-- コンソール/PCゲーム市場の重要な動向
-- 人気タイトルのイベント情報
-- 業界の経済動向や企業戦略
+    if let Some(feed_file) = &opt.feed_file {
+        write_feed(
+            &history,
+            feed_file,
+            opt.feed_format,
+            opt.feed_url.as_ref(),
+            opt.feed_max_entries,
+        )?;
+    }
 
-6. **天気情報**：{year}年{month}月{day}日の全国の気象状況と横浜市の詳細予報。
+    ensure!(
+        failed == 0,
+        "{failed} of {} topics failed",
+        config.topics.len()
+    );
 
-最後に、ニュース全体から見える重要なトレンドや関連性を3文以内でまとめ、使用した合計文字数を報告してください。
-"#,
-                        year = current.year(),
-                        month = current.month(),
-                        day = current.day(),
-                    ),
-                }
-            ]
-        }))?)
-        .send()
-        .await?;
+    Ok(())
+}
 
-    info!(?res);
-    let res_text = res.text().await?;
-    debug!(%res_text);
+/// Resources shared by the topics.
+struct TopicContext<'a> {
+    client: &'a reqwest::Client,
+    api_key: &'a str,
+    telegram_bot_token: Option<&'a str>,
+    history: &'a HistoryStore,
+    otel: bool,
 
-    let res = serde_json::from_str::<serde_json::Value>(&res_text)?;
-    let (pplx_content, pplx_citations) = deconstruct_payload(&res)?;
-    let pplx_citations = pplx_citations
-        .iter()
-        .map(|data| {
-            Url::parse(data).unwrap_or_else(|e| {
-                warn!(?e, url = data, "failed to parse url");
-                Url::parse("https://failed-to-parse-url.example.com/").expect("default url")
-            })
-        })
-        .collect::<Vec<_>>();
+    /// Prints the topic name before the content to tell the topics apart.
+    print_topic_name: bool,
 
-    println!(
-        "{pplx_content}\n{}",
-        pplx_citations
+    /// Adds the new items to the feed.
+    feed: bool,
+}
+
+/// Builds the request body for the Perplexity chat completions API.
+fn build_request_body(topic: &TopicConfig, current: &impl Datelike) -> serde_json::Value {
+    let prompt = topic
+        .prompt
+        .replace("{year}", &current.year().to_string())
+        .replace("{month}", &current.month().to_string())
+        .replace("{day}", &current.day().to_string());
+
+    let mut body = json!({
+        "model": topic.model.to_string(),
+        "search_domain_filter": topic
+            .ban_domains
             .iter()
-            .enumerate()
-            .map(|(i, data)| format!("[{}] {}", i + 1, data))
-            .collect::<Vec<_>>()
-            .join("\n")
-    );
+            .map(|data| format!("-{data}"))
+            .chain(topic.allow_domains.iter().cloned())
+            .collect::<Vec<_>>(),
+        "web_search_options": {
+            "search_context_size": SearchContextSize::High,
+        },
+        "search_recency_filter": topic.recency,
+        "messages": [
+            {
+                "role": "system",
+                "content": topic.system_prompt,
+            },
+            {
+                "role": "user",
+                "content": prompt,
+            }
+        ]
+    });
+    if topic.structured {
+        body["response_format"] = structured_response_format();
+    }
+    body
+}
+
+async fn run_topic(context: &TopicContext<'_>, topic: &TopicConfig) -> Fallible<()> {
+    let current = chrono::Local::now();
+    let mut body = build_request_body(topic, &current);
+    let res = post_chat_completions(context, topic, &body).await?;
 
-    if let Some(telegram) = opt.telegram {
-        info!("notify to telegram");
-        let ret_telegram = client
+    let output = if topic.structured {
+        let content = deconstruct_payload(&res)?.0;
+        let news = match parse_structured_content(content) {
+            Ok(data) => data,
+            Err(e) => {
+                warn!(topic = topic.name, ?e, "retry with a repair prompt");
+                add_repair_messages(&mut body, content, &e);
+                let res = post_chat_completions(context, topic, &body).await?;
+                parse_structured_content(deconstruct_payload(&res)?.0)
+                    .context("the repaired response does not match the schema")?
+            }
+        };
+        prepare_structured_output(topic, &current, news, &context.history.load()?)?
+    } else {
+        prepare_output(topic, &res, &context.history.load()?)?
+    };
+
+    let Some(output) = output else {
+        info!(topic = topic.name, "no new items");
+        return Ok(());
+    };
+
+    if context.print_topic_name {
+        println!("# {}", topic.name);
+    }
+    println!("{}", output.text);
+
+    if let Some(telegram_payload) = output.telegram_payload {
+        info!(topic = topic.name, "notify to telegram");
+        let ret_telegram = context
+            .client
             .post(format!(
                 "https://api.telegram.org/bot{}/sendMessage",
-                telegram
+                context
                     .telegram_bot_token
                     .expect("telegram_bot_token should not be None"),
             ))
             .header(header::ACCEPT, "application/json")
             .header(header::CONTENT_TYPE, "application/json")
-            .body(generate_telegram_payload(
-                &telegram
-                    .telegram_chat_id
-                    .expect("telegram_chat_id should not be None"),
-                &telegram
-                    .telegram_text_template
-                    .expect("telegram_text_template should not be None"),
-                pplx_content,
-                &pplx_citations,
-            )?)
+            .body(telegram_payload)
             .send()
             .await?;
-        info!(?ret_telegram);
+        info!(topic = topic.name, ?ret_telegram);
         debug!(ret_telegram_text = %ret_telegram.text().await?);
     }
 
-    if otel_guard.is_some() {
+    let now = chrono::Utc::now().timestamp();
+    context.history.save(&output.new_items, now)?;
+    if context.feed {
+        context
+            .history
+            .add_feed_entries(&topic.name, &output.new_items, now)?;
+    }
+
+    if context.otel {
         let current_string = current.to_rfc3339();
-        for entry in pplx_citations {
+        for entry in output.citations {
             info!(
                 event.name = "device.app.citations",
                 datetime = current_string,
-                model_name = %opt.model,
-                citation_url_domain = %entry.domain().expect("url.domain"),
+                topic = topic.name,
+                model_name = %topic.model,
+                citation_url_domain = entry.host_str().unwrap_or_default(),
                 citation_url_full = entry.as_str(),
             );
         }
@@ -266,6 +546,181 @@ async fn main() -> Fallible<()> {
     Ok(())
 }
 
+async fn post_chat_completions(
+    context: &TopicContext<'_>,
+    topic: &TopicConfig,
+    body: &serde_json::Value,
+) -> Fallible<serde_json::Value> {
+    let res = context
+        .client
+        .post("https://api.perplexity.ai/chat/completions")
+        .header(header::ACCEPT, "application/json")
+        .header(header::CONTENT_TYPE, "application/json")
+        .bearer_auth(context.api_key)
+        .body(serde_json::to_string(body)?)
+        .send()
+        .await?;
+
+    info!(topic = topic.name, ?res);
+    let res_text = res.text().await?;
+    debug!(topic = topic.name, %res_text);
+
+    Ok(serde_json::from_str::<serde_json::Value>(&res_text)?)
+}
+
+/// Content of a topic ready to deliver.
+struct TopicOutput {
+    /// Plain text to print.
+    text: String,
+
+    /// Body of the Telegram `sendMessage` request.
+    telegram_payload: Option<String>,
+
+    /// Items to remember as delivered.
+    new_items: Vec<NewsItem>,
+
+    /// Sources of the content.
+    citations: Vec<Url>,
+}
+
+/// Removes the delivered items from the free-form content. Returns `None` if every item has
+/// been delivered.
+fn prepare_output(
+    topic: &TopicConfig,
+    res: &serde_json::Value,
+    history: &History,
+) -> Fallible<Option<TopicOutput>> {
+    let (pplx_content, pplx_citations) = deconstruct_payload(res)?;
+    let pplx_citations = pplx_citations
+        .iter()
+        .map(|data| {
+            Url::parse(data).unwrap_or_else(|e| {
+                warn!(?e, url = data, "failed to parse url");
+                Url::parse("https://failed-to-parse-url.example.com/").expect("default url")
+            })
+        })
+        .collect::<Vec<_>>();
+
+    let filtered = filter_delivered_items(pplx_content, &pplx_citations, history)?;
+    info!(
+        topic = topic.name,
+        new_items = filtered.new_items.len(),
+        skipped_items = filtered.skipped_items,
+        "filtered delivered items"
+    );
+
+    if filtered.new_items.is_empty() && 0 < filtered.skipped_items {
+        return Ok(None);
+    }
+
+    let text = format!(
+        "{}\n{}",
+        filtered.content,
+        filtered
+            .citations
+            .iter()
+            .enumerate()
+            .map(|(i, data)| format!("[{}] {}", i + 1, data))
+            .collect::<Vec<_>>()
+            .join("\n")
+    );
+
+    let telegram_payload = match &topic.telegram {
+        Some(telegram) => Some(generate_telegram_payload(
+            &telegram.chat_id,
+            &telegram.template,
+            &filtered.content,
+            &filtered.citations,
+        )?),
+        None => None,
+    };
+
+    Ok(Some(TopicOutput {
+        text,
+        telegram_payload,
+        new_items: filtered.new_items,
+        citations: filtered.citations,
+    }))
+}
+
+/// Removes the delivered items from the structured response and renders the rest. Returns
+/// `None` if there is no new item.
+fn prepare_structured_output(
+    topic: &TopicConfig,
+    current: &impl Datelike,
+    news: StructuredNews,
+    history: &History,
+) -> Fallible<Option<TopicOutput>> {
+    let total = news.items.len();
+    let mut items = Vec::<StructuredItem>::new();
+    let mut new_items = Vec::<NewsItem>::new();
+    for item in news.items {
+        let news_item = NewsItem {
+            title: normalize_title(&item.title),
+            urls: vec![normalize_url(&item.url)],
+            text: item.summary.clone(),
+            links: vec![item.url.clone()],
+            headline: Some(item.title.clone()),
+        };
+        if history.contains(&news_item)
+            || new_items.iter().any(|data| {
+                DUPLICATE_TITLE_SIMILARITY <= title_similarity(&data.title, &news_item.title)
+            })
+        {
+            debug!(?news_item, "skip the delivered item");
+            continue;
+        }
+        items.push(item);
+        new_items.push(news_item);
+    }
+    info!(
+        topic = topic.name,
+        new_items = items.len(),
+        skipped_items = total - items.len(),
+        "filtered delivered items"
+    );
+
+    if items.is_empty() {
+        return Ok(None);
+    }
+
+    let date = format!(
+        "{}-{:02}-{:02}",
+        current.year(),
+        current.month(),
+        current.day()
+    );
+    let text = items
+        .iter()
+        .map(|data| {
+            format!(
+                "{}\n{}\n{} ({}) {}",
+                data.title, data.summary, data.source, data.published, data.url
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n\n");
+
+    let telegram_payload = match &topic.telegram {
+        Some(telegram) => {
+            let text = render_structured_template(&telegram.template, &topic.name, &date, &items)?;
+            Some(serde_json::to_string(&json!({
+                "chat_id": telegram.chat_id,
+                "text": text,
+                "parse_mode": "MarkdownV2",
+            }))?)
+        }
+        None => None,
+    };
+
+    Ok(Some(TopicOutput {
+        text,
+        telegram_payload,
+        citations: items.into_iter().map(|data| data.url).collect(),
+        new_items,
+    }))
+}
+
 fn deconstruct_payload(value: &serde_json::Value) -> Fallible<(&str, Vec<&str>)> {
     Ok((
         value["choices"][0]["message"]["content"]
@@ -324,10 +779,7 @@ fn generate_telegram_payload(
                     line,
                     r"{}\] [{}]({})",
                     i + 1,
-                    reg.replace_all(
-                        data.domain().expect("citation should have a domain"),
-                        r#"\$1"#
-                    ),
+                    reg.replace_all(data.host_str().unwrap_or_default(), r#"\$1"#),
                     data,
                 )?;
 
@@ -358,37 +810,806 @@ fn generate_telegram_payload(
     Ok(serde_json::to_string(&payload)?)
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use clap::CommandFactory;
+/// An item of the content that cites at least one source.
+#[derive(Debug, Eq, PartialEq)]
+struct NewsItem {
+    /// Normalized text of the item.
+    title: String,
 
-    #[test]
-    fn verify_cli() {
-        Opt::command().debug_assert();
-    }
+    /// Normalized URLs of the cited sources.
+    urls: Vec<String>,
 
-    #[test]
-    fn opt_telegram_ok() {
-        Opt::try_parse_from([
-            "pplx-news",
-            "--model",
-            "sonar-pro",
-            "--api-key",
-            "api-key",
-            "--use-telegram",
-            "--telegram-bot-token",
-            "token",
-            "--telegram-chat-id",
-            "chat-id",
-            "--telegram-text-template",
-            "text-template",
-        ])
-        .unwrap();
+    /// Text of the item without the markup and citation marks.
+    text: String,
+
+    /// Cited sources in the order of appearance.
+    links: Vec<Url>,
+
+    /// Title given by the structured response.
+    headline: Option<String>,
+}
+
+/// Content without the delivered items.
+#[derive(Debug)]
+struct FilteredContent {
+    content: String,
+
+    /// Items that have not been delivered yet.
+    new_items: Vec<NewsItem>,
+
+    /// Number of the removed items.
+    skipped_items: usize,
+
+    /// Citations without the ones referenced only by the removed items. The citation marks of
+    /// `content` are renumbered to this.
+    citations: Vec<Url>,
+}
+
+/// URLs and titles that have been delivered within the retention period.
+#[derive(Debug, Default)]
+struct History {
+    /// Normalized primary URLs of the delivered items.
+    urls: HashSet<String>,
+    titles: Vec<String>,
+}
+
+impl History {
+    /// Matches the primary (first cited) URL only, since the other citations may be generic pages
+    /// shared by unrelated items.
+    fn contains(&self, item: &NewsItem) -> bool {
+        item.urls
+            .first()
+            .is_some_and(|data| self.urls.contains(data))
+            || self
+                .titles
+                .iter()
+                .any(|data| DUPLICATE_TITLE_SIMILARITY <= title_similarity(data, &item.title))
     }
+}
 
-    #[test]
-    fn opt_telegram_missing_use_telegram() {
+/// Local store of the delivered items.
+struct HistoryStore {
+    conn: Connection,
+    retention: Duration,
+}
+
+impl HistoryStore {
+    fn create_with_path(db_path: &Path, retention: Duration) -> Fallible<Self> {
+        if let Some(parent) = db_path.parent() {
+            std::fs::create_dir_all(parent)
+                .with_context(|| format!("failed to create {}", parent.display()))?;
+        }
+        Self::create_with_conn(Connection::open(db_path)?, retention)
+    }
+
+    fn create_with_conn(conn: Connection, retention: Duration) -> Fallible<Self> {
+        let db_version = conn.query_row("pragma user_version", [], |row| row.get::<_, i32>(0))?;
+        match db_version {
+            0 => {
+                conn.execute_batch(
+                    "create table delivered_url (url text primary key not null, delivered_at integer not null);
+create table delivered_title (title text primary key not null, delivered_at integer not null)",
+                )?;
+                conn.execute_batch(CREATE_FEED_ENTRY_TABLE)?;
+
+                conn.execute("pragma user_version = 2", ())?;
+            }
+            1 => {
+                conn.execute_batch(CREATE_FEED_ENTRY_TABLE)?;
+
+                conn.execute("pragma user_version = 2", ())?;
+            }
+            2 => (),
+            _ => bail!("unsupported db version: {db_version}"),
+        }
+
+        Ok(Self { conn, retention })
+    }
+
+    /// Removes the items delivered before the retention period.
+    fn prune(&self, now: i64) -> Fallible<()> {
+        let expired = now - self.retention.as_secs() as i64;
+        self.conn
+            .execute(
+                "delete from delivered_url where delivered_at < ?1",
+                [expired],
+            )
+            .context("failed to prune delivered_url")?;
+        self.conn
+            .execute(
+                "delete from delivered_title where delivered_at < ?1",
+                [expired],
+            )
+            .context("failed to prune delivered_title")?;
+        self.conn
+            .execute("delete from feed_entry where published < ?1", [expired])
+            .context("failed to prune feed_entry")?;
+        Ok(())
+    }
+
+    fn load(&self) -> Fallible<History> {
+        let mut stmt = self.conn.prepare("select url from delivered_url")?;
+        let urls = stmt
+            .query_map([], |row| row.get(0))?
+            .collect::<Result<HashSet<String>, _>>()?;
+        let mut stmt = self.conn.prepare("select title from delivered_title")?;
+        let titles = stmt
+            .query_map([], |row| row.get(0))?
+            .collect::<Result<Vec<String>, _>>()?;
+        Ok(History { urls, titles })
+    }
+
+    fn save(&self, items: &[NewsItem], now: i64) -> Fallible<()> {
+        for item in items {
+            if let Some(url) = item.urls.first() {
+                self.conn
+                    .execute(
+                        "insert or replace into delivered_url (url, delivered_at) values (?1, ?2)",
+                        params![url, now],
+                    )
+                    .context("failed to save delivered_url")?;
+            }
+            self.conn
+                .execute(
+                    "insert or replace into delivered_title (title, delivered_at) values (?1, ?2)",
+                    params![item.title, now],
+                )
+                .context("failed to save delivered_title")?;
+        }
+        Ok(())
+    }
+
+    /// Adds the items to the feed.
+    fn add_feed_entries(&self, topic: &str, items: &[NewsItem], now: i64) -> Fallible<()> {
+        for item in items {
+            self.conn
+                .execute(
+                    "insert into feed_entry (guid, topic, title, summary, links, published) values (?1, ?2, ?3, ?4, ?5, ?6)",
+                    params![
+                        format!("urn:uuid:{}", uuid::Uuid::new_v4()),
+                        topic,
+                        match &item.headline {
+                            Some(data) => data.clone(),
+                            None => feed_entry_title(&item.text),
+                        },
+                        item.text,
+                        item.links
+                            .iter()
+                            .map(Url::as_str)
+                            .collect::<Vec<_>>()
+                            .join("\n"),
+                        now,
+                    ],
+                )
+                .context("failed to save feed_entry")?;
+        }
+        Ok(())
+    }
+
+    /// Returns the latest `limit` feed entries, newest first.
+    fn load_feed_entries(&self, limit: usize) -> Fallible<Vec<FeedEntry>> {
+        let mut stmt = self.conn.prepare(
+            "select guid, topic, title, summary, links, published from feed_entry order by published desc, id desc limit ?1",
+        )?;
+        let entries = stmt
+            .query_map([limit as i64], |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    row.get::<_, String>(1)?,
+                    row.get::<_, String>(2)?,
+                    row.get::<_, String>(3)?,
+                    row.get::<_, String>(4)?,
+                    row.get::<_, i64>(5)?,
+                ))
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        entries
+            .into_iter()
+            .map(
+                |(guid, topic, title, summary, links, published)| -> Fallible<FeedEntry> {
+                    Ok(FeedEntry {
+                        guid,
+                        topic,
+                        title,
+                        summary,
+                        links: links
+                            .lines()
+                            .map(Url::parse)
+                            .collect::<Result<Vec<_>, _>>()?,
+                        published: chrono::DateTime::from_timestamp(published, 0)
+                            .context("published")?,
+                    })
+                },
+            )
+            .collect()
+    }
+}
+
+const CREATE_FEED_ENTRY_TABLE: &str = "create table feed_entry (id integer primary key autoincrement not null, guid text not null, topic text not null, title text not null, summary text not null, links text not null, published integer not null);
+create index index_feed_entry_published on feed_entry (published)";
+
+fn default_history_path() -> Fallible<PathBuf> {
+    let project_dirs = directories::ProjectDirs::from("com", "sukawasatoru", "pplx-news")
+        .context("no valid home directory")?;
+    Ok(project_dirs.data_dir().join("history.db"))
+}
+
+/// Removes the lines of `pplx_content` that cite a delivered source or resemble a delivered
+/// item.
+///
+/// Lines without citations (headings, the thinking process, etc.) are kept as is.
+fn filter_delivered_items(
+    pplx_content: &str,
+    pplx_citations: &[Url],
+    history: &History,
+) -> Fallible<FilteredContent> {
+    let reg_citation = Regex::new(r"\[(\d+)\]")?;
+
+    let mut new_items = Vec::<NewsItem>::new();
+    let mut skipped_items = 0;
+    let mut lines = Vec::new();
+    // indices of the citations referenced by the removed lines.
+    let mut removed_citations = HashSet::<usize>::new();
+    let mut thinking = false;
+    for line in pplx_content.lines() {
+        if line.trim_start().starts_with("<think>") {
+            thinking = true;
+        }
+        if thinking {
+            thinking = !line.contains("</think>");
+            lines.push(line);
+            continue;
+        }
+
+        let mut links = Vec::<Url>::new();
+        for url in reg_citation
+            .captures_iter(line)
+            .filter_map(|data| data[1].parse::<usize>().ok())
+            .filter_map(|index| pplx_citations.get(index.wrapping_sub(1)))
+        {
+            if !links.contains(url) {
+                links.push(url.clone());
+            }
+        }
+        if links.is_empty() {
+            lines.push(line);
+            continue;
+        }
+
+        let text = reg_citation.replace_all(line, "");
+        let item = NewsItem {
+            title: normalize_title(&text),
+            urls: links.iter().map(normalize_url).collect(),
+            text: strip_list_marker(&text.replace("**", ""))
+                .trim()
+                .to_string(),
+            links,
+            headline: None,
+        };
+        let delivered = history.contains(&item);
+        if delivered {
+            debug!(?item, "skip the delivered item");
+        }
+
+        // the same story may be repeated in the same content.
+        if delivered
+            || new_items.iter().any(|data| {
+                DUPLICATE_TITLE_SIMILARITY <= title_similarity(&data.title, &item.title)
+            })
+        {
+            removed_citations.extend(citation_indices(&reg_citation, line, pplx_citations.len()));
+            skipped_items += 1;
+            continue;
+        }
+
+        lines.push(line);
+        new_items.push(item);
+    }
+
+    let kept_citations = lines
+        .iter()
+        .flat_map(|line| citation_indices(&reg_citation, line, pplx_citations.len()))
+        .collect::<HashSet<_>>();
+    // new 1-based number of the citations to keep.
+    let mut numbers = vec![None; pplx_citations.len()];
+    let mut citations = vec![];
+    for (index, url) in pplx_citations.iter().enumerate() {
+        if kept_citations.contains(&index) || !removed_citations.contains(&index) {
+            citations.push(url.clone());
+            numbers[index] = Some(citations.len());
+        }
+    }
+
+    let content = lines
+        .iter()
+        .map(|line| {
+            reg_citation.replace_all(line, |caps: &regex::Captures| {
+                match caps[1]
+                    .parse::<usize>()
+                    .ok()
+                    .and_then(|number| numbers.get(number.wrapping_sub(1)).copied().flatten())
+                {
+                    Some(number) => format!("[{number}]"),
+                    None => caps[0].to_string(),
+                }
+            })
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    Ok(FilteredContent {
+        content,
+        new_items,
+        skipped_items,
+        citations,
+    })
+}
+
+/// Returns the 0-based indices of the citations referenced by the `line`.
+fn citation_indices<'a>(
+    reg_citation: &'a Regex,
+    line: &'a str,
+    len: usize,
+) -> impl Iterator<Item = usize> + 'a {
+    reg_citation
+        .captures_iter(line)
+        .filter_map(|data| data[1].parse::<usize>().ok())
+        .filter_map(move |number| number.checked_sub(1).filter(|index| *index < len))
+}
+
+/// Removes the fragment, tracking parameters and trailing slash that do not identify a page.
+fn normalize_url(url: &Url) -> String {
+    let mut url = url.clone();
+    url.set_fragment(None);
+
+    let query = url
+        .query_pairs()
+        .filter(|(key, _)| !key.starts_with("utm_"))
+        .map(|(key, value)| (key.into_owned(), value.into_owned()))
+        .collect::<Vec<_>>();
+    if query.is_empty() {
+        url.set_query(None);
+    } else {
+        url.query_pairs_mut().clear().extend_pairs(query);
+    }
+
+    url.as_str().trim_end_matches('/').to_string()
+}
+
+/// Removes the leading `- `, `* ` or `1. ` of a list item.
+fn strip_list_marker(text: &str) -> &str {
+    let text = text.trim_start();
+    if let Some(rest) = text
+        .strip_prefix(['-', '*'])
+        .and_then(|data| data.strip_prefix(' '))
+    {
+        return rest;
+    }
+    match text.split_once(". ") {
+        Some((number, rest))
+            if !number.is_empty() && number.chars().all(|c| c.is_ascii_digit()) =>
+        {
+            rest
+        }
+        _ => text,
+    }
+}
+
+/// Lowercases the text and removes the list marker, markup and punctuation.
+fn normalize_title(text: &str) -> String {
+    strip_list_marker(text)
+        .chars()
+        .filter(|c| c.is_alphanumeric())
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
+/// Returns the Sørensen–Dice coefficient of the character bigrams of `a` and `b`.
+fn title_similarity(a: &str, b: &str) -> f64 {
+    fn bigrams(text: &str) -> HashMap<(char, char), usize> {
+        let chars = text.chars().collect::<Vec<_>>();
+        let mut ret = HashMap::new();
+        for pair in chars.windows(2) {
+            *ret.entry((pair[0], pair[1])).or_default() += 1;
+        }
+        ret
+    }
+
+    if a == b {
+        return 1.0;
+    }
+
+    let (a, b) = (bigrams(a), bigrams(b));
+    let total = a.values().sum::<usize>() + b.values().sum::<usize>();
+    if total == 0 {
+        return 0.0;
+    }
+
+    let shared = a
+        .iter()
+        .map(|(key, count)| b.get(key).map_or(0, |data| *count.min(data)))
+        .sum::<usize>();
+    (2 * shared) as f64 / total as f64
+}
+
+/// Entry of the feed file.
+#[derive(Debug)]
+struct FeedEntry {
+    /// Globally unique `urn:uuid:` ID.
+    guid: String,
+    topic: String,
+    title: String,
+    summary: String,
+
+    /// Cited sources. The first one is the link of the entry.
+    links: Vec<Url>,
+    published: chrono::DateTime<chrono::Utc>,
+}
+
+/// Maximum characters of the entry title cut out of the item text.
+const FEED_TITLE_MAX_CHARS: usize = 60;
+
+/// Returns the heading before `：` or `: ` of the item text, or the head of the text.
+fn feed_entry_title(text: &str) -> String {
+    let heading = text
+        .split_once('：')
+        .or_else(|| text.split_once(": "))
+        .map(|(heading, _)| heading.trim())
+        .filter(|heading| !heading.is_empty());
+    let title = heading.unwrap_or(text);
+    match title.char_indices().nth(FEED_TITLE_MAX_CHARS) {
+        Some((index, _)) => format!("{}…", &title[..index]),
+        None => title.to_string(),
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum FeedFormat {
+    Atom,
+    Rss,
+}
+
+fn escape_xml(text: &str) -> String {
+    let mut ret = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => ret.push_str("&amp;"),
+            '<' => ret.push_str("&lt;"),
+            '>' => ret.push_str("&gt;"),
+            '"' => ret.push_str("&quot;"),
+            '\'' => ret.push_str("&apos;"),
+            _ => ret.push(c),
+        }
+    }
+    ret
+}
+
+/// Generates an Atom 1.0 feed. `feed_url` is the URL the feed file is served at.
+fn generate_atom_feed(
+    feed_url: Option<&Url>,
+    entries: &[FeedEntry],
+    now: chrono::DateTime<chrono::Utc>,
+) -> Fallible<String> {
+    let mut xml = String::new();
+    writeln!(xml, r#"<?xml version="1.0" encoding="utf-8"?>"#)?;
+    writeln!(xml, r#"<feed xmlns="http://www.w3.org/2005/Atom">"#)?;
+    writeln!(xml, "  <title>pplx-news</title>")?;
+    match feed_url {
+        Some(feed_url) => {
+            let feed_url = escape_xml(feed_url.as_str());
+            writeln!(xml, "  <id>{feed_url}</id>")?;
+            writeln!(xml, r#"  <link rel="self" href="{feed_url}"/>"#)?;
+        }
+        None => writeln!(xml, "  <id>urn:pplx-news:feed</id>")?,
+    }
+    let updated = entries.first().map_or(now, |data| data.published);
+    writeln!(xml, "  <updated>{}</updated>", updated.to_rfc3339())?;
+    writeln!(xml, "  <author><name>pplx-news</name></author>")?;
+    for entry in entries {
+        writeln!(xml, "  <entry>")?;
+        writeln!(xml, "    <id>{}</id>", escape_xml(&entry.guid))?;
+        writeln!(xml, "    <title>{}</title>", escape_xml(&entry.title))?;
+        for (i, link) in entry.links.iter().enumerate() {
+            let rel = if i == 0 { "alternate" } else { "related" };
+            writeln!(
+                xml,
+                r#"    <link rel="{rel}" href="{}"/>"#,
+                escape_xml(link.as_str())
+            )?;
+        }
+        writeln!(
+            xml,
+            r#"    <category term="{}"/>"#,
+            escape_xml(&entry.topic)
+        )?;
+        writeln!(
+            xml,
+            "    <published>{}</published>",
+            entry.published.to_rfc3339()
+        )?;
+        writeln!(
+            xml,
+            "    <updated>{}</updated>",
+            entry.published.to_rfc3339()
+        )?;
+        writeln!(xml, "    <summary>{}</summary>", escape_xml(&entry.summary))?;
+        writeln!(xml, "  </entry>")?;
+    }
+    writeln!(xml, "</feed>")?;
+    Ok(xml)
+}
+
+/// Generates an RSS 2.0 feed. `feed_url` is the URL the feed file is served at.
+fn generate_rss_feed(
+    feed_url: Option<&Url>,
+    entries: &[FeedEntry],
+    now: chrono::DateTime<chrono::Utc>,
+) -> Fallible<String> {
+    let mut xml = String::new();
+    writeln!(xml, r#"<?xml version="1.0" encoding="utf-8"?>"#)?;
+    writeln!(xml, r#"<rss version="2.0">"#)?;
+    writeln!(xml, "  <channel>")?;
+    writeln!(xml, "    <title>pplx-news</title>")?;
+    writeln!(
+        xml,
+        "    <link>{}</link>",
+        escape_xml(feed_url.map_or(
+            "https://github.com/sukawasatoru/rust-myscript/",
+            Url::as_str
+        ))
+    )?;
+    writeln!(xml, "    <description>pplx-news</description>")?;
+    let updated = entries.first().map_or(now, |data| data.published);
+    writeln!(
+        xml,
+        "    <lastBuildDate>{}</lastBuildDate>",
+        updated.to_rfc2822()
+    )?;
+    for entry in entries {
+        let description = std::iter::once(entry.summary.clone())
+            .chain(
+                entry
+                    .links
+                    .iter()
+                    .enumerate()
+                    .map(|(i, data)| format!("[{}] {data}", i + 1)),
+            )
+            .collect::<Vec<_>>()
+            .join("\n");
+        writeln!(xml, "    <item>")?;
+        writeln!(xml, "      <title>{}</title>", escape_xml(&entry.title))?;
+        if let Some(link) = entry.links.first() {
+            writeln!(xml, "      <link>{}</link>", escape_xml(link.as_str()))?;
+        }
+        writeln!(
+            xml,
+            "      <description>{}</description>",
+            escape_xml(&description)
+        )?;
+        writeln!(
+            xml,
+            "      <category>{}</category>",
+            escape_xml(&entry.topic)
+        )?;
+        writeln!(
+            xml,
+            r#"      <guid isPermaLink="false">{}</guid>"#,
+            escape_xml(&entry.guid)
+        )?;
+        writeln!(
+            xml,
+            "      <pubDate>{}</pubDate>",
+            entry.published.to_rfc2822()
+        )?;
+        writeln!(xml, "    </item>")?;
+    }
+    writeln!(xml, "  </channel>")?;
+    writeln!(xml, "</rss>")?;
+    Ok(xml)
+}
+
+/// Rewrites the feed file with the latest entries of the history.
+fn write_feed(
+    history: &HistoryStore,
+    path: &Path,
+    format: FeedFormat,
+    feed_url: Option<&Url>,
+    max_entries: usize,
+) -> Fallible<()> {
+    let entries = history.load_feed_entries(max_entries)?;
+    let now = chrono::Utc::now();
+    let xml = match format {
+        FeedFormat::Atom => generate_atom_feed(feed_url, &entries, now)?,
+        FeedFormat::Rss => generate_rss_feed(feed_url, &entries, now)?,
+    };
+    write_atomically(path, xml)
+}
+
+/// Structured response of a topic.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct StructuredNews {
+    items: Vec<StructuredItem>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+struct StructuredItem {
+    title: String,
+    summary: String,
+    url: Url,
+
+    /// Name of the publisher.
+    source: String,
+    published: chrono::NaiveDate,
+}
+
+/// `response_format` of the chat completions API for [`StructuredNews`].
+fn structured_response_format() -> serde_json::Value {
+    json!({
+        "type": "json_schema",
+        "json_schema": {
+            "schema": {
+                "type": "object",
+                "properties": {
+                    "items": {
+                        "type": "array",
+                        "items": {
+                            "type": "object",
+                            "properties": {
+                                "title": { "type": "string" },
+                                "summary": { "type": "string" },
+                                "url": { "type": "string", "format": "uri" },
+                                "source": { "type": "string" },
+                                "published": { "type": "string", "format": "date" },
+                            },
+                            "required": ["title", "summary", "url", "source", "published"],
+                            "additionalProperties": false,
+                        },
+                    },
+                },
+                "required": ["items"],
+                "additionalProperties": false,
+            },
+        },
+    })
+}
+
+/// Parses the content and verifies it matches [`structured_response_format`].
+fn parse_structured_content(content: &str) -> Fallible<StructuredNews> {
+    let json_text = strip_thinking(content).trim();
+    // some models wrap the JSON in a code block even with the response_format.
+    let json_text = json_text
+        .strip_prefix("```json")
+        .or_else(|| json_text.strip_prefix("```"))
+        .and_then(|data| data.strip_suffix("```"))
+        .unwrap_or(json_text);
+
+    let news = serde_json::from_str::<StructuredNews>(json_text)?;
+    for (i, item) in news.items.iter().enumerate() {
+        ensure!(!item.title.trim().is_empty(), "items[{i}].title is empty");
+        ensure!(
+            !item.summary.trim().is_empty(),
+            "items[{i}].summary is empty"
+        );
+        ensure!(!item.source.trim().is_empty(), "items[{i}].source is empty");
+        ensure!(
+            matches!(item.url.scheme(), "http" | "https"),
+            "items[{i}].url is not a http(s) URL: {}",
+            item.url
+        );
+    }
+    Ok(news)
+}
+
+/// Returns the content after the thinking process of the reasoning models.
+fn strip_thinking(content: &str) -> &str {
+    match (
+        content.trim_start().starts_with("<think>"),
+        content.find("</think>"),
+    ) {
+        (true, Some(end)) => &content[end + "</think>".len()..],
+        _ => content,
+    }
+}
+
+/// Appends the invalid response and a prompt to fix it to the messages of the request body.
+fn add_repair_messages(body: &mut serde_json::Value, content: &str, error: &anyhow::Error) {
+    let messages = body["messages"]
+        .as_array_mut()
+        .expect("messages should be array");
+    messages.push(json!({
+        "role": "assistant",
+        "content": strip_thinking(content).trim(),
+    }));
+    messages.push(json!({
+        "role": "user",
+        "content": format!(
+            "直前の回答は指定した JSON スキーマに一致しませんでした（{error:#}）。\
+             説明やコードブロックを付けず、スキーマに一致する JSON のみを返してください。"
+        ),
+    }));
+}
+
+/// Escapes the special characters of Telegram's MarkdownV2.
+fn escape_markdown_v2(text: &str) -> String {
+    let mut ret = String::with_capacity(text.len());
+    for c in text.chars() {
+        if r"_*[]()~`>#+-=|{}.!\".contains(c) {
+            ret.push('\\');
+        }
+        ret.push(c);
+    }
+    ret
+}
+
+/// Renders the MiniJinja template with `topic`, `date` and `items`.
+///
+/// The template text is MarkdownV2 as is, and the values are escaped for it. Use the `url` filter
+/// for the URL part of an inline link, e.g. `[{{ item.source }}]({{ item.url | url }})`.
+fn render_structured_template(
+    template_txt: &str,
+    topic: &str,
+    date: &str,
+    items: &[StructuredItem],
+) -> Fallible<String> {
+    let mut env = minijinja::Environment::new();
+    env.set_formatter(|out, _state, value| {
+        if value.is_undefined() || value.is_none() {
+            return Ok(());
+        }
+        let text = value.to_string();
+        let text = match value.is_safe() {
+            true => text,
+            false => escape_markdown_v2(&text),
+        };
+        out.write_str(&text)
+            .map_err(|e| minijinja::Error::new(minijinja::ErrorKind::WriteFailure, e.to_string()))
+    });
+    env.add_filter("url", |value: String| {
+        minijinja::Value::from_safe_string(value.replace('\\', r"\\").replace(')', r"\)"))
+    });
+
+    // replace `\n` string to new line for the template given by the command line.
+    let template_txt = template_txt.replace(r#"\n"#, "\n");
+    let text = env.render_str(&template_txt, minijinja::context! { topic, date, items })?;
+
+    debug!(%text);
+    Ok(text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::{CommandFactory, FromArgMatches};
+
+    #[test]
+    fn verify_cli() {
+        Opt::command().debug_assert();
+    }
+
+    #[test]
+    fn opt_telegram_ok() {
+        Opt::try_parse_from([
+            "pplx-news",
+            "--model",
+            "sonar-pro",
+            "--api-key",
+            "api-key",
+            "--use-telegram",
+            "--telegram-bot-token",
+            "token",
+            "--telegram-chat-id",
+            "chat-id",
+            "--telegram-text-template",
+            "text-template",
+        ])
+        .unwrap();
+    }
+
+    #[test]
+    fn opt_telegram_missing_use_telegram() {
         let opt = Opt::try_parse_from([
             "pplx-news",
             "--model",
@@ -456,6 +1677,193 @@ mod tests {
         assert!(opt.is_err());
     }
 
+    #[test]
+    fn opt_config_conflicts_with_model() {
+        Opt::try_parse_from([
+            "pplx-news",
+            "--api-key",
+            "api-key",
+            "--config",
+            "config.toml",
+        ])
+        .unwrap();
+
+        let opt = Opt::try_parse_from([
+            "pplx-news",
+            "--api-key",
+            "api-key",
+            "--config",
+            "config.toml",
+            "--model",
+            "sonar-pro",
+        ]);
+        assert!(opt.is_err());
+    }
+
+    #[test]
+    fn opt_config_with_env() {
+        // rename the environment variables not to affect the other tests.
+        let mut command = Opt::command();
+        for (id, value) in [
+            ("ban_domain", "example.com"),
+            ("structured", "true"),
+            ("use_telegram", "true"),
+            ("telegram_bot_token", "token"),
+            ("telegram_chat_id", "chat-id"),
+            ("telegram_text_template", "{pplx}"),
+        ] {
+            let name = format!("PPLX_NEWS_TEST_OPT_CONFIG_WITH_ENV_{}", id.to_uppercase());
+            // SAFETY: the variables are used by this test only.
+            unsafe { std::env::set_var(&name, value) };
+            command = command.mut_arg(id, |arg| arg.env(name));
+        }
+
+        let matches = command
+            .try_get_matches_from([
+                "pplx-news",
+                "--api-key",
+                "api-key",
+                "--config",
+                "config.toml",
+            ])
+            .unwrap();
+        let opt = Opt::from_arg_matches(&matches).unwrap();
+        assert_eq!(opt.config, Some(PathBuf::from("config.toml")));
+        assert_eq!(opt.ban_domain, vec!["example.com"]);
+        assert_eq!(opt.telegram_bot_token.as_deref(), Some("token"));
+    }
+
+    #[test]
+    fn config_or_telegram_bot_token() {
+        let parse = |text: &str| toml::from_str::<Config>(text).unwrap();
+        let topic = r#"
+[[topic]]
+name = "a"
+prompt = "a"
+telegram = { chat_id = "123", template = "{pplx}" }
+"#;
+
+        let config = parse(topic).or_telegram_bot_token(Some("env"));
+        verify_config(&config).unwrap();
+        assert_eq!(config.telegram_bot_token.as_deref(), Some("env"));
+
+        let config = parse(&format!("telegram_bot_token = \"file\"\n{topic}"))
+            .or_telegram_bot_token(Some("env"));
+        assert_eq!(config.telegram_bot_token.as_deref(), Some("file"));
+
+        let config = parse(topic).or_telegram_bot_token(None);
+        assert!(verify_config(&config).is_err());
+    }
+
+    #[test]
+    fn config_parse() {
+        let config = toml::from_str::<Config>(
+            r#"
+concurrency = 3
+telegram_bot_token = "token"
+
+[[topic]]
+name = "games"
+prompt = "{year}/{month}/{day} game news"
+model = "sonar-reasoning-pro"
+allow_domains = ["example.com"]
+recency = "day"
+
+[topic.telegram]
+chat_id = "123"
+template = "games\n{pplx}"
+
+[[topic]]
+name = "tech"
+prompt = "tech news"
+ban_domains = ["example.org"]
+"#,
+        )
+        .unwrap();
+        verify_config(&config).unwrap();
+        assert_eq!(config.concurrency, 3);
+        assert_eq!(config.topics.len(), 2);
+
+        let current = chrono::NaiveDate::from_ymd_opt(2026, 4, 5).unwrap();
+        assert_eq!(
+            build_request_body(&config.topics[0], &current),
+            json!({
+                "model": "sonar-reasoning-pro",
+                "search_domain_filter": ["example.com"],
+                "web_search_options": { "search_context_size": "high" },
+                "search_recency_filter": "day",
+                "messages": [
+                    { "role": "system", "content": DEFAULT_SYSTEM_PROMPT },
+                    { "role": "user", "content": "2026/4/5 game news" },
+                ],
+            })
+        );
+        assert_eq!(
+            config.topics[0].telegram.as_ref().unwrap().template,
+            "games\n{pplx}"
+        );
+
+        let body = build_request_body(&config.topics[1], &current);
+        assert_eq!(body["model"], "sonar-pro");
+        assert_eq!(body["search_domain_filter"], json!(["-example.org"]));
+        assert_eq!(body["search_recency_filter"], "week");
+    }
+
+    #[test]
+    fn verify_config_rejects_invalid_topics() {
+        let parse = |text: &str| toml::from_str::<Config>(text).unwrap();
+
+        let config = parse("topic = []");
+        assert!(verify_config(&config).is_err());
+
+        let config = parse(
+            r#"
+[[topic]]
+name = "a"
+prompt = "a"
+ban_domains = ["example.com"]
+allow_domains = ["example.org"]
+"#,
+        );
+        assert!(verify_config(&config).is_err());
+
+        let config = parse(
+            r#"
+[[topic]]
+name = "a"
+prompt = "a"
+
+[[topic]]
+name = "a"
+prompt = "b"
+"#,
+        );
+        assert!(verify_config(&config).is_err());
+
+        let config = parse(
+            r#"
+[[topic]]
+name = "a"
+prompt = "a"
+telegram = { chat_id = "123", template = "{pplx}" }
+"#,
+        );
+        assert!(verify_config(&config).is_err());
+    }
+
+    #[test]
+    fn generate_telegram_payload_ip_literal_citation() {
+        let citations = [Url::parse("http://192.0.2.1/news").unwrap()];
+        let payload = generate_telegram_payload("123", "{pplx}", "news[1]", &citations).unwrap();
+
+        let actual = serde_json::from_str::<serde_json::Value>(&payload).unwrap();
+        let actual = actual["text"].as_str().unwrap();
+        assert!(
+            actual.contains(r"[192\.0\.2\.1](http://192.0.2.1/news)"),
+            "{actual}"
+        );
+    }
+
     #[test]
     fn generate_telegram_payload_ok() {
         let res = serde_json::from_str::<serde_json::Value>(RES_TEXT).unwrap();
@@ -518,6 +1926,439 @@ But I must explain to you how all this mistaken idea of denouncing pleasure and
         assert_eq!(actual_text, expected_text);
     }
 
+    #[test]
+    fn normalize_url_removes_fragment_and_tracking_parameters() {
+        let url =
+            Url::parse("https://example.com/news/1/?utm_source=x&id=2&utm_medium=y#top").unwrap();
+        assert_eq!(normalize_url(&url), "https://example.com/news/1/?id=2");
+
+        let url = Url::parse("https://example.com/news/1/?utm_source=x").unwrap();
+        assert_eq!(normalize_url(&url), "https://example.com/news/1");
+    }
+
+    #[test]
+    fn normalize_title_removes_markup() {
+        assert_eq!(
+            normalize_title("- **Rust 2.0** released: a New Era!"),
+            "rust20releasedanewera"
+        );
+        assert_eq!(
+            normalize_title("2. 新型ゲーム機、発売へ。"),
+            "新型ゲーム機発売へ"
+        );
+        assert_eq!(normalize_title("2026年の予測"), "2026年の予測");
+    }
+
+    #[test]
+    fn title_similarity_detects_reworded_title() {
+        let a = normalize_title("Nintendo announces a new console for 2026");
+        let b = normalize_title("Nintendo announced the new console for 2026");
+        let c = normalize_title("Heavy rain is expected in Yokohama");
+        assert!(DUPLICATE_TITLE_SIMILARITY <= title_similarity(&a, &b));
+        assert!(title_similarity(&a, &c) < DUPLICATE_TITLE_SIMILARITY);
+        assert_eq!(title_similarity(&a, &a), 1.0);
+        assert_eq!(title_similarity("", "a"), 0.0);
+    }
+
+    #[test]
+    fn filter_delivered_items_removes_delivered_lines() {
+        let citations = [
+            Url::parse("https://example.com/1").unwrap(),
+            Url::parse("https://example.com/2").unwrap(),
+            Url::parse("https://example.com/3").unwrap(),
+        ];
+        let content = "1. **Top**:
+- Nintendo announces a new console for 2026[1].
+- Heavy rain is expected in Yokohama[2].
+- A new Rust edition is released[3].";
+        let history = History {
+            urls: HashSet::from(["https://example.com/2".to_string()]),
+            titles: vec![normalize_title(
+                "Nintendo announced the new console for 2026",
+            )],
+        };
+
+        let actual = filter_delivered_items(content, &citations, &history).unwrap();
+        assert_eq!(
+            actual.content,
+            "1. **Top**:\n- A new Rust edition is released[1]."
+        );
+        assert_eq!(actual.citations, vec![citations[2].clone()]);
+        assert_eq!(
+            actual.new_items,
+            vec![NewsItem {
+                title: "anewrusteditionisreleased".to_string(),
+                urls: vec!["https://example.com/3".to_string()],
+                text: "A new Rust edition is released.".to_string(),
+                links: vec![Url::parse("https://example.com/3").unwrap()],
+                headline: None,
+            }]
+        );
+        assert_eq!(actual.skipped_items, 2);
+    }
+
+    #[test]
+    fn filter_delivered_items_ignores_shared_generic_citation() {
+        let citations = [
+            Url::parse("https://example.com/news/1").unwrap(),
+            Url::parse("https://en.wikipedia.org/wiki/Japan").unwrap(),
+            Url::parse("https://example.com/news/2").unwrap(),
+            Url::parse("https://example.com/unused").unwrap(),
+        ];
+        let content = "- Nintendo announces a new console for 2026[1][2].
+- Heavy rain is expected in Yokohama[3][2].";
+        // the previous run delivered an unrelated item citing the same wiki page.
+        let history = History {
+            urls: HashSet::from([
+                "https://example.com/news/1".to_string(),
+                "https://en.wikipedia.org/wiki/Japan".to_string(),
+            ]),
+            titles: vec![],
+        };
+
+        let actual = filter_delivered_items(content, &citations, &history).unwrap();
+        assert_eq!(
+            actual.content,
+            "- Heavy rain is expected in Yokohama[2][1]."
+        );
+        assert_eq!(
+            actual.citations,
+            vec![
+                citations[1].clone(),
+                citations[2].clone(),
+                citations[3].clone(),
+            ]
+        );
+        assert_eq!(actual.new_items.len(), 1);
+        assert_eq!(actual.skipped_items, 1);
+    }
+
+    #[test]
+    fn filter_delivered_items_keeps_content_without_citations() {
+        let content = "<think>\nreasoning[1]\n</think>\nno citations here";
+        let actual = filter_delivered_items(content, &[], &History::default()).unwrap();
+        assert_eq!(actual.content, content);
+        assert!(actual.new_items.is_empty());
+        assert_eq!(actual.skipped_items, 0);
+    }
+
+    #[test]
+    fn history_store_save_load_prune() {
+        let store = HistoryStore::create_with_conn(
+            Connection::open_in_memory().unwrap(),
+            Duration::from_secs(100),
+        )
+        .unwrap();
+        store
+            .save(
+                &[NewsItem {
+                    title: "title1".to_string(),
+                    urls: vec!["https://example.com/1".to_string()],
+                    text: "Title1".to_string(),
+                    links: vec![Url::parse("https://example.com/1").unwrap()],
+                    headline: None,
+                }],
+                1000,
+            )
+            .unwrap();
+        store
+            .save(
+                &[NewsItem {
+                    title: "title2".to_string(),
+                    urls: vec!["https://example.com/2".to_string()],
+                    text: "Title2".to_string(),
+                    links: vec![Url::parse("https://example.com/2").unwrap()],
+                    headline: None,
+                }],
+                1050,
+            )
+            .unwrap();
+
+        let history = store.load().unwrap();
+        assert_eq!(history.urls.len(), 2);
+        assert_eq!(history.titles.len(), 2);
+
+        store.prune(1120).unwrap();
+        let history = store.load().unwrap();
+        assert_eq!(
+            history.urls,
+            HashSet::from(["https://example.com/2".to_string()])
+        );
+        assert_eq!(history.titles, vec!["title2".to_string()]);
+    }
+
+    #[test]
+    fn feed_entry_title_uses_heading() {
+        assert_eq!(
+            feed_entry_title("新型ゲーム機：任天堂が発表した"),
+            "新型ゲーム機"
+        );
+        assert_eq!(
+            feed_entry_title("Rust 2.0: the new edition is released"),
+            "Rust 2.0"
+        );
+        assert_eq!(feed_entry_title("no heading."), "no heading.");
+        assert_eq!(
+            feed_entry_title(&"a".repeat(FEED_TITLE_MAX_CHARS + 1)),
+            format!("{}…", "a".repeat(FEED_TITLE_MAX_CHARS))
+        );
+    }
+
+    fn create_feed_entries() -> Vec<FeedEntry> {
+        let store = HistoryStore::create_with_conn(
+            Connection::open_in_memory().unwrap(),
+            Duration::from_secs(100),
+        )
+        .unwrap();
+        let citations = [
+            Url::parse("https://example.com/1?a=1&b=2").unwrap(),
+            Url::parse("https://example.com/2").unwrap(),
+        ];
+        let filtered = filter_delivered_items(
+            "- **Games**：<New> console[1][2].\n- Rain & wind[2].",
+            &citations,
+            &History::default(),
+        )
+        .unwrap();
+        store
+            .add_feed_entries("daily", &filtered.new_items, 1_780_000_000)
+            .unwrap();
+        store.load_feed_entries(10).unwrap()
+    }
+
+    #[test]
+    fn history_store_feed_entries() {
+        let entries = create_feed_entries();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].title, "Rain & wind.");
+        assert_eq!(entries[1].title, "Games");
+        assert_eq!(entries[1].summary, "Games：<New> console.");
+        assert_eq!(entries[1].topic, "daily");
+        assert_eq!(
+            entries[1].links,
+            vec![
+                Url::parse("https://example.com/1?a=1&b=2").unwrap(),
+                Url::parse("https://example.com/2").unwrap(),
+            ]
+        );
+        assert!(entries[1].guid.starts_with("urn:uuid:"));
+    }
+
+    #[test]
+    fn history_store_migrates_from_version_1() {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(
+            "create table delivered_url (url text primary key not null, delivered_at integer not null);
+create table delivered_title (title text primary key not null, delivered_at integer not null);
+pragma user_version = 1",
+        )
+        .unwrap();
+        let store = HistoryStore::create_with_conn(conn, Duration::from_secs(100)).unwrap();
+        assert!(store.load_feed_entries(10).unwrap().is_empty());
+    }
+
+    #[test]
+    fn generate_atom_feed_ok() {
+        let mut entries = create_feed_entries();
+        entries.truncate(1);
+        entries[0].guid = "urn:uuid:00000000-0000-0000-0000-000000000000".to_string();
+        let feed_url = Url::parse("https://example.net/news.xml").unwrap();
+        let actual = generate_atom_feed(Some(&feed_url), &entries, chrono::Utc::now()).unwrap();
+        assert_eq!(
+            actual,
+            r#"<?xml version="1.0" encoding="utf-8"?>
+<feed xmlns="http://www.w3.org/2005/Atom">
+  <title>pplx-news</title>
+  <id>https://example.net/news.xml</id>
+  <link rel="self" href="https://example.net/news.xml"/>
+  <updated>2026-05-28T20:26:40+00:00</updated>
+  <author><name>pplx-news</name></author>
+  <entry>
+    <id>urn:uuid:00000000-0000-0000-0000-000000000000</id>
+    <title>Rain &amp; wind.</title>
+    <link rel="alternate" href="https://example.com/2"/>
+    <category term="daily"/>
+    <published>2026-05-28T20:26:40+00:00</published>
+    <updated>2026-05-28T20:26:40+00:00</updated>
+    <summary>Rain &amp; wind.</summary>
+  </entry>
+</feed>
+"#
+        );
+    }
+
+    #[test]
+    fn generate_rss_feed_ok() {
+        let mut entries = create_feed_entries();
+        entries.remove(0);
+        entries[0].guid = "urn:uuid:00000000-0000-0000-0000-000000000000".to_string();
+        let actual = generate_rss_feed(None, &entries, chrono::Utc::now()).unwrap();
+        assert_eq!(
+            actual,
+            r#"<?xml version="1.0" encoding="utf-8"?>
+<rss version="2.0">
+  <channel>
+    <title>pplx-news</title>
+    <link>https://github.com/sukawasatoru/rust-myscript/</link>
+    <description>pplx-news</description>
+    <lastBuildDate>Thu, 28 May 2026 20:26:40 +0000</lastBuildDate>
+    <item>
+      <title>Games</title>
+      <link>https://example.com/1?a=1&amp;b=2</link>
+      <description>Games：&lt;New&gt; console.
+[1] https://example.com/1?a=1&amp;b=2
+[2] https://example.com/2</description>
+      <category>daily</category>
+      <guid isPermaLink="false">urn:uuid:00000000-0000-0000-0000-000000000000</guid>
+      <pubDate>Thu, 28 May 2026 20:26:40 +0000</pubDate>
+    </item>
+  </channel>
+</rss>
+"#
+        );
+    }
+
+    const STRUCTURED_CONTENT: &str = r#"{"items":[{"title":"Rust 2.0 released","summary":"The new edition (2026) is out.","url":"https://example.com/rust?a=1","source":"Example News","published":"2026-04-05"}]}"#;
+
+    #[test]
+    fn parse_structured_content_ok() {
+        let news = parse_structured_content(STRUCTURED_CONTENT).unwrap();
+        assert_eq!(news.items.len(), 1);
+        assert_eq!(news.items[0].title, "Rust 2.0 released");
+        assert_eq!(
+            news.items[0].published,
+            chrono::NaiveDate::from_ymd_opt(2026, 4, 5).unwrap()
+        );
+
+        let content = format!("<think>\nreasoning\n</think>\n```json\n{STRUCTURED_CONTENT}\n```");
+        assert_eq!(parse_structured_content(&content).unwrap().items.len(), 1);
+    }
+
+    #[test]
+    fn parse_structured_content_rejects_invalid_items() {
+        for content in [
+            "news",
+            r#"{"items":[{"title":"t","summary":"s","url":"https://e/","source":"e"}]}"#,
+            r#"{"items":[{"title":"t","summary":"s","url":"https://e/","source":"e","published":"2026-04-05","extra":1}]}"#,
+            r#"{"items":[{"title":"t","summary":"s","url":"https://e/","source":"e","published":"04/05/2026"}]}"#,
+            r#"{"items":[{"title":"","summary":"s","url":"https://e/","source":"e","published":"2026-04-05"}]}"#,
+            r#"{"items":[{"title":"t","summary":"s","url":"ftp://e/","source":"e","published":"2026-04-05"}]}"#,
+        ] {
+            assert!(parse_structured_content(content).is_err(), "{content}");
+        }
+    }
+
+    #[test]
+    fn add_repair_messages_appends_conversation() {
+        let mut body = json!({
+            "messages": [
+                { "role": "system", "content": "system" },
+                { "role": "user", "content": "user" },
+            ],
+        });
+        let error = parse_structured_content("news").unwrap_err();
+        add_repair_messages(&mut body, "<think>x</think> news", &error);
+
+        let messages = body["messages"].as_array().unwrap();
+        assert_eq!(messages.len(), 4);
+        assert_eq!(
+            messages[2],
+            json!({ "role": "assistant", "content": "news" })
+        );
+        assert_eq!(messages[3]["role"], "user");
+        assert!(messages[3]["content"].as_str().unwrap().contains("JSON"));
+    }
+
+    #[test]
+    fn build_request_body_structured() {
+        let mut config = toml::from_str::<Config>(
+            r#"
+[[topic]]
+name = "a"
+prompt = "a"
+structured = true
+"#,
+        )
+        .unwrap();
+        let current = chrono::NaiveDate::from_ymd_opt(2026, 4, 5).unwrap();
+        let body = build_request_body(&config.topics[0], &current);
+        assert_eq!(body["response_format"], structured_response_format());
+
+        config.topics[0].structured = false;
+        let body = build_request_body(&config.topics[0], &current);
+        assert!(body.get("response_format").is_none());
+    }
+
+    #[test]
+    fn render_structured_template_escapes_values() {
+        let news = parse_structured_content(STRUCTURED_CONTENT).unwrap();
+        let actual = render_structured_template(
+            r"*{{ topic }}* {{ date }}\n{% for item in items %}{{ loop.index }}\. {{ item.title }}\n{{ item.summary }}\n[{{ item.source }}]({{ item.url | url }}) {{ item.published }}\n{% endfor %}",
+            "tech-news",
+            "2026-04-05",
+            &news.items,
+        )
+        .unwrap();
+        assert_eq!(
+            actual,
+            r"*tech\-news* 2026\-04\-05
+1\. Rust 2\.0 released
+The new edition \(2026\) is out\.
+[Example News](https://example.com/rust?a=1) 2026\-04\-05
+"
+        );
+    }
+
+    #[test]
+    fn prepare_structured_output_skips_delivered_items() {
+        let config = toml::from_str::<Config>(
+            r#"
+[[topic]]
+name = "a"
+prompt = "a"
+structured = true
+telegram = { chat_id = "123", template = "{% for item in items %}{{ item.title }}{% endfor %}" }
+"#,
+        )
+        .unwrap();
+        let current = chrono::NaiveDate::from_ymd_opt(2026, 4, 5).unwrap();
+
+        let output = prepare_structured_output(
+            &config.topics[0],
+            &current,
+            parse_structured_content(STRUCTURED_CONTENT).unwrap(),
+            &History::default(),
+        )
+        .unwrap()
+        .unwrap();
+        assert_eq!(
+            output.text,
+            "Rust 2.0 released\nThe new edition (2026) is out.\nExample News (2026-04-05) https://example.com/rust?a=1"
+        );
+        assert_eq!(
+            serde_json::from_str::<serde_json::Value>(&output.telegram_payload.unwrap()).unwrap(),
+            json!({ "chat_id": "123", "text": r"Rust 2\.0 released", "parse_mode": "MarkdownV2" })
+        );
+        assert_eq!(output.new_items.len(), 1);
+        assert_eq!(
+            output.new_items[0].headline.as_deref(),
+            Some("Rust 2.0 released")
+        );
+
+        let history = History {
+            urls: HashSet::from(["https://example.com/rust?a=1".to_string()]),
+            titles: vec![],
+        };
+        let output = prepare_structured_output(
+            &config.topics[0],
+            &current,
+            parse_structured_content(STRUCTURED_CONTENT).unwrap(),
+            &history,
+        )
+        .unwrap();
+        assert!(output.is_none());
+    }
+
     const RES_TEXT: &str = r#"
 {
   "id": "4ecf86c7-f597-46dc-ad1b-784d383bd319",
@@ -137,6 +137,38 @@ fn create_logger_provider(resource: Resource, endpoint: Url) -> Fallible<SdkLogg
     Ok(provider)
 }
 
+/// Sets the global meter provider that exports the metrics to `metrics_endpoint`.
+#[cfg(feature = "otel-metrics")]
+pub fn init_otel_metrics(metrics_endpoint: Url, name: &'static str) -> Fallible<OtelMetricsGuard> {
+    let exporter = opentelemetry_otlp::MetricExporter::builder()
+        .with_http()
+        .with_endpoint(metrics_endpoint)
+        .build()
+        .context("failed to build metric exporter")?;
+
+    let provider = opentelemetry_sdk::metrics::SdkMeterProvider::builder()
+        .with_resource(create_resource(name))
+        .with_periodic_exporter(exporter)
+        .build();
+    opentelemetry::global::set_meter_provider(provider.clone());
+
+    Ok(OtelMetricsGuard { meter: provider })
+}
+
+#[cfg(feature = "otel-metrics")]
+pub struct OtelMetricsGuard {
+    meter: opentelemetry_sdk::metrics::SdkMeterProvider,
+}
+
+#[cfg(feature = "otel-metrics")]
+impl Drop for OtelMetricsGuard {
+    fn drop(&mut self) {
+        if let Err(e) = self.meter.shutdown() {
+            eprintln!("failed to shutdown meter: {}", e);
+        }
+    }
+}
+
 pub struct OtelGuards {
     logger: SdkLoggerProvider,
 }
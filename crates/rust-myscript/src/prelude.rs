@@ -23,6 +23,37 @@ impl TomlLoader {
     }
 }
 
+/// Writes the `contents` to a temporary file next to the `path` and renames it to the `path`, so
+/// that the `path` is never left partially written.
+pub fn write_atomically(path: &std::path::Path, contents: impl AsRef<[u8]>) -> Fallible<()> {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    static COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+    let mut tmp_name = path
+        .file_name()
+        .with_context(|| format!("no file name: {}", path.display()))?
+        .to_owned();
+    // unique per process and call not to clobber the temporary file of another writer.
+    tmp_name.push(format!(
+        ".{}.{}.tmp",
+        std::process::id(),
+        COUNTER.fetch_add(1, Ordering::Relaxed),
+    ));
+    let tmp_path = path.with_file_name(tmp_name);
+
+    let ret = std::fs::write(&tmp_path, contents)
+        .with_context(|| format!("failed to write {}", tmp_path.display()))
+        .and_then(|_| {
+            std::fs::rename(&tmp_path, path)
+                .with_context(|| format!("failed to write {}", path.display()))
+        });
+    if ret.is_err() {
+        let _ = std::fs::remove_file(&tmp_path);
+    }
+    ret
+}
+
 pub struct HexFormat<'a>(pub &'a [u8]);
 
 impl std::fmt::Display for HexFormat<'_> {
@@ -57,3 +88,27 @@ impl<'de> serde::de::Visitor<'de> for StrVisitor {
         Ok(v)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn write_atomically_replaces_file() {
+        let dir = std::env::temp_dir().join(format!(
+            "rust-myscript-write-atomically-{}",
+            std::process::id()
+        ));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("file.txt");
+
+        write_atomically(&path, "first").unwrap();
+        write_atomically(&path, "second").unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "second");
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 1);
+
+        assert!(write_atomically(&dir.join("missing/file.txt"), "data").is_err());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
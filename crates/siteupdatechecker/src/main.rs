@@ -24,9 +24,14 @@ use rust_myscript::prelude::*;
 use serde::{Deserialize, Serialize};
 use serde_json::json;
 use sha3::{Digest, Sha3_224};
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::fmt::{Display, Formatter};
 use std::io::prelude::*;
+use std::path::{Path, PathBuf};
 use std::rc::Rc;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Semaphore;
 use url::Url;
 
 #[derive(Deserialize)]
@@ -48,14 +53,161 @@ struct Site {
     hash: Option<String>,
     last_modified: Option<String>,
     etag: Option<String>,
+
+    /// Text of the region extracted by the region check methods.
+    extracted: Option<String>,
+
+    /// IDs of the entries in the feed checked last time.
+    seen_guids: Option<Vec<String>>,
+
+    /// Entries found by this check.
+    #[serde(skip)]
+    new_entries: Vec<FeedEntry>,
+
+    /// Interval of the checks in the daemon mode such as `6h`.
+    check_every: Option<String>,
+
+    /// Time of the next check in the daemon mode.
+    #[serde(skip)]
+    next_check: Option<chrono::DateTime<chrono::Utc>>,
+
+    /// Normalized text of the page checked by `Hash` to show the diff at the next update.
+    ///
+    /// Kept only in the state file of `--config`/`--state` mode so that the printed config of the
+    /// stdin mode does not contain the whole page. Updates in the stdin mode have no diffs.
+    #[serde(skip_serializing)]
+    text: Option<String>,
+
+    /// Unified diff of the text found by this check.
+    #[serde(skip)]
+    diff: Option<String>,
+
+    /// Number of the failed checks in a row.
+    consecutive_failures: Option<u32>,
+
+    /// Number of the failed checks in a row to report the site as an error. Uses
+    /// `--failure-threshold` if omitted.
+    failure_threshold: Option<u32>,
+
+    /// Timeout of a request such as `30s`.
+    timeout: Option<String>,
+
+    /// User agent of the requests instead of the default one.
+    user_agent: Option<String>,
+
+    auth: Option<Auth>,
+
+    /// Additional headers of the requests.
+    headers: Option<BTreeMap<String, String>>,
+
+    /// Cookies sent with the requests.
+    cookies: Option<BTreeMap<String, String>>,
+}
+
+impl Site {
+    fn restore_state(&mut self, state: SiteState) {
+        self.hash = state.hash;
+        self.last_modified = state.last_modified;
+        self.etag = state.etag;
+        self.extracted = state.extracted;
+        self.seen_guids = state.seen_guids;
+        self.next_check = state.next_check;
+        self.text = state.text;
+        self.consecutive_failures = state.consecutive_failures;
+    }
+
+    fn to_state(&self) -> SiteState {
+        SiteState {
+            hash: self.hash.clone(),
+            last_modified: self.last_modified.clone(),
+            etag: self.etag.clone(),
+            extracted: self.extracted.clone(),
+            seen_guids: self.seen_guids.clone(),
+            next_check: self.next_check,
+            text: self.text.clone(),
+            consecutive_failures: self.consecutive_failures,
+        }
+    }
+}
+
+/// Contents of the state file.
+#[derive(Debug, Default, Deserialize, Serialize)]
+struct State {
+    /// States keyed by the title of the sites.
+    #[serde(default)]
+    sites: BTreeMap<String, SiteState>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+struct SiteState {
+    hash: Option<String>,
+    last_modified: Option<String>,
+    etag: Option<String>,
+    extracted: Option<String>,
+    seen_guids: Option<Vec<String>>,
+    next_check: Option<chrono::DateTime<chrono::Utc>>,
+    text: Option<String>,
+    consecutive_failures: Option<u32>,
+}
+
+/// Authentication of the requests.
+#[derive(Debug, Deserialize, Serialize)]
+enum Auth {
+    Basic {
+        username: String,
+        password: Option<String>,
+    },
+    Bearer(String),
+}
+
+#[derive(Debug, Eq, PartialEq)]
+struct FeedEntry {
+    title: String,
+    link: Option<Url>,
 }
 
 #[derive(Debug, Deserialize, Serialize)]
 enum CheckMethod {
     Head,
     Hash,
+
+    /// Hash the matches of the regex. The first capture group is used if the regex has it.
+    Regex(String),
+
+    /// Hash the text of the elements selected by the CSS selector.
+    Selector(String),
+
+    /// Hash the value at the JSON pointer (RFC 6901) of the JSON response.
+    JsonPointer(String),
+
+    /// Report the new entries of the RSS or Atom feed.
+    Feed,
 }
 
+/// Maximum characters of the extracted text in the notifications.
+const EXTRACTED_TEXT_MAX_CHARS: usize = 200;
+
+/// Maximum number of the new feed entries listed per site in the notifications.
+const FEED_ENTRIES_MAX: usize = 10;
+
+/// Maximum lines of the diff in the notifications.
+const DIFF_MAX_LINES: usize = 20;
+
+/// Maximum characters of the diff in the notifications.
+const DIFF_MAX_CHARS: usize = 1000;
+
+/// Delay before the first retry of a request.
+const RETRY_BASE_DELAY: Duration = Duration::from_secs(1);
+
+/// Maximum delay between the retries of a request.
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(30);
+
+/// Maximum wait in the daemon mode to pick up the edits of the config.
+const DAEMON_POLL_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Minimum wait in the daemon mode not to spin on the overdue checks.
+const DAEMON_MIN_WAIT: Duration = Duration::from_secs(1);
+
 struct CheckOk {
     updated: bool,
     site: Site,
@@ -83,6 +235,44 @@ impl std::error::Error for CheckError {
 /// Update checker for web site
 #[derive(Parser)]
 struct Opt {
+    /// Path to the site definitions. Reads the sites from stdin and prints the new config if
+    /// omitted.
+    #[arg(long, env, requires = "state")]
+    config: Option<PathBuf>,
+
+    /// Path to the file to keep the states of the sites such as the hashes.
+    #[arg(long, env, requires = "config")]
+    state: Option<PathBuf>,
+
+    /// Keep running and check each site at its `check_every` interval.
+    #[arg(long, env, requires = "config")]
+    daemon: bool,
+
+    /// Interval of the checks for the sites that have no `check_every`.
+    #[arg(long, env, default_value = "1h", value_parser = parse_duration)]
+    default_check_every: Duration,
+
+    /// Maximum random delay added to each interval not to access the sites at the same time.
+    #[arg(long, env, default_value = "5m", value_parser = parse_duration)]
+    jitter: Duration,
+
+    /// Maximum number of the concurrent requests to a host.
+    #[arg(
+        long,
+        env,
+        default_value_t = 2,
+        value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..),
+    )]
+    max_connections_per_host: usize,
+
+    /// Maximum number of the retries of a request on the network errors and the server errors.
+    #[arg(long, env, default_value_t = 2)]
+    max_retries: u32,
+
+    /// Number of the failed checks in a row to report a site as an error.
+    #[arg(long, env, default_value_t = 1, value_parser = clap::value_parser!(u32).range(1..))]
+    failure_threshold: u32,
+
     #[command(flatten)]
     slack: Option<Slack>,
 
@@ -152,9 +342,9 @@ async fn main() -> Fallible<()> {
         .user_agent("siteupdatechecker")
         .build()?;
 
-    let otel_guard = match opt.otel_logs_endpoint {
+    let otel_guard = match &opt.otel_logs_endpoint {
         Some(endpoint) => {
-            let guard = init_otel(endpoint, env!("CARGO_BIN_NAME"))?;
+            let guard = init_otel(endpoint.clone(), env!("CARGO_BIN_NAME"))?;
             Some(guard)
         }
         None => {
@@ -164,49 +354,324 @@ async fn main() -> Fallible<()> {
             None
         }
     };
+    let use_otel = otel_guard.is_some();
 
-    let mut site_prefs_string = String::new();
-    std::io::stdin().read_to_string(&mut site_prefs_string)?;
-    let site_prefs = toml::from_str::<SitePreferences>(&site_prefs_string)?;
+    let site_client = SiteClient {
+        client: client.clone(),
+        max_retries: opt.max_retries,
+        retry_base_delay: RETRY_BASE_DELAY,
+    };
 
-    let mut futs = Vec::<BoxFuture<Result<CheckOk, CheckError>>>::new();
-    for site in site_prefs.sites {
-        match site.check_method {
-            CheckMethod::Head => futs.push(Box::pin(check_site_head(client.clone(), site))),
-            CheckMethod::Hash => futs.push(Box::pin(check_site_hash(client.clone(), site))),
+    let (Some(config_path), Some(state_path)) = (&opt.config, &opt.state) else {
+        let mut site_prefs_string = String::new();
+        std::io::stdin().read_to_string(&mut site_prefs_string)?;
+        let site_prefs = toml::from_str::<SitePreferences>(&site_prefs_string)?;
+
+        let result = check_sites(&opt, &site_client, site_prefs.sites).await;
+        let otel_log_body = print_result(&result);
+
+        let new_site_prefs = SitePrefsForSerialize {
+            sites: result.sites.clone(),
+        };
+        let new_prefs_string = toml::to_string(&new_site_prefs)?;
+        println!("#");
+        println!("# new config:");
+        println!("{new_prefs_string}");
+
+        notify(&opt, &client, &result).await?;
+        log_result(use_otel, &result, &otel_log_body);
+        return Ok(());
+    };
+
+    let mut state = load_state(state_path)?;
+
+    if !opt.daemon {
+        let sites = load_sites(config_path, &state)?;
+        let result = check_sites(&opt, &site_client, sites).await;
+        let otel_log_body = print_result(&result);
+        update_state(&opt, &mut state, &result.sites, chrono::Utc::now())?;
+        save_state(state_path, &state)?;
+        notify(&opt, &client, &result).await?;
+        log_result(use_otel, &result, &otel_log_body);
+        return Ok(());
+    }
+
+    info!("start daemon");
+    loop {
+        let now = chrono::Utc::now();
+
+        // reload every time to apply the edits of the config without restarting.
+        let config_loaded = match load_sites(config_path, &state) {
+            Ok(sites) => {
+                state
+                    .sites
+                    .retain(|title, _| sites.iter().any(|site| &site.title == title));
+
+                let due_sites = sites
+                    .into_iter()
+                    .filter(|site| site.next_check.is_none_or(|next_check| next_check <= now))
+                    .collect::<Vec<_>>();
+
+                if !due_sites.is_empty() {
+                    let result = check_sites(&opt, &site_client, due_sites).await;
+                    let otel_log_body = print_result(&result);
+                    update_state(&opt, &mut state, &result.sites, now)?;
+                    save_state(state_path, &state)?;
+                    if let Err(e) = notify(&opt, &client, &result).await {
+                        warn!(?e, "failed to notify");
+                    }
+                    log_result(use_otel, &result, &otel_log_body);
+                }
+                true
+            }
+            Err(e) => {
+                warn!(?e, "failed to load the config");
+                false
+            }
+        };
+
+        let wait = daemon_wait(&state, config_loaded, chrono::Utc::now());
+        debug!(?wait, "wait for the next check");
+
+        tokio::select! {
+            _ = tokio::time::sleep(wait) => {}
+            ret = tokio::signal::ctrl_c() => {
+                ret?;
+                info!("stop daemon");
+                return Ok(());
+            }
         }
     }
+}
+
+/// Returns the wait until the next check in the daemon mode.
+///
+/// Waits [DAEMON_POLL_INTERVAL] if there is no check to schedule, e.g. the config is empty or
+/// broken.
+fn daemon_wait(state: &State, config_loaded: bool, now: chrono::DateTime<chrono::Utc>) -> Duration {
+    if !config_loaded {
+        return DAEMON_POLL_INTERVAL;
+    }
+    state
+        .sites
+        .values()
+        .filter_map(|site_state| site_state.next_check)
+        .min()
+        .map_or(DAEMON_POLL_INTERVAL, |next_check| {
+            (next_check - now)
+                .to_std()
+                .unwrap_or_default()
+                .clamp(DAEMON_MIN_WAIT, DAEMON_POLL_INTERVAL)
+        })
+}
+
+struct CheckResult {
+    sites: Vec<Rc<Site>>,
+    updated_sites: Vec<Rc<Site>>,
+    not_modified_sites: Vec<Rc<Site>>,
+
+    /// Sites failed fewer times in a row than the threshold.
+    failing_sites: Vec<(Rc<Site>, anyhow::Error)>,
+
+    error_sites: Vec<(Rc<Site>, anyhow::Error)>,
+}
+
+async fn check_sites(opt: &Opt, client: &SiteClient, sites: Vec<Site>) -> CheckResult {
+    let mut host_semaphores = HashMap::<String, Arc<Semaphore>>::new();
+    let mut futs = Vec::<BoxFuture<Result<CheckOk, CheckError>>>::new();
+    for site in sites {
+        let semaphore = host_semaphores
+            .entry(site.uri.host_str().unwrap_or_default().to_string())
+            .or_insert_with(|| Arc::new(Semaphore::new(opt.max_connections_per_host)))
+            .clone();
+        let client = client.clone();
+        futs.push(Box::pin(async move {
+            let _permit = semaphore.acquire().await.unwrap();
+            check_site(client, site).await
+        }));
+    }
 
     let ret = futures::future::join_all(futs).await;
     let mut new_prefs = Vec::with_capacity(ret.len());
     let mut updated_sites = vec![];
     let mut not_modified_sites = vec![];
+    let mut failing_sites = vec![];
     let mut error_sites = vec![];
     for ret_check in ret {
         match ret_check {
-            Ok(data) => match data.updated {
-                true => {
-                    info!("updated: {}", &data.site.title);
-                    let site = Rc::new(data.site);
-                    new_prefs.push(site.clone());
-                    updated_sites.push(site);
-                }
-                false => {
-                    info!("not modified: {}", &data.site.title);
-                    let site = Rc::new(data.site);
-                    new_prefs.push(site.clone());
-                    not_modified_sites.push(site);
+            Ok(data) => {
+                let site = Site {
+                    consecutive_failures: None,
+                    ..data.site
+                };
+                match data.updated {
+                    true => {
+                        info!("updated: {}", &site.title);
+                        let site = Rc::new(site);
+                        new_prefs.push(site.clone());
+                        updated_sites.push(site);
+                    }
+                    false => {
+                        info!("not modified: {}", &site.title);
+                        let site = Rc::new(site);
+                        new_prefs.push(site.clone());
+                        not_modified_sites.push(site);
+                    }
                 }
-            },
+            }
             Err(e) => {
-                info!(?e.source, "error caused: {}", &e.site.title);
-                let site = Rc::new(e.site);
+                let failures = e.site.consecutive_failures.unwrap_or(0).saturating_add(1);
+                let site = Rc::new(Site {
+                    consecutive_failures: Some(failures),
+                    ..e.site
+                });
                 new_prefs.push(site.clone());
-                error_sites.push((site, e.source));
+
+                // not to report the flaky sites every time.
+                if site.failure_threshold.unwrap_or(opt.failure_threshold) <= failures {
+                    info!(?e.source, "error caused: {}", &site.title);
+                    error_sites.push((site, e.source));
+                } else {
+                    info!(?e.source, "failed {failures} times in a row: {}", &site.title);
+                    failing_sites.push((site, e.source));
+                }
+            }
+        }
+    }
+
+    CheckResult {
+        sites: new_prefs,
+        updated_sites,
+        not_modified_sites,
+        failing_sites,
+        error_sites,
+    }
+}
+
+fn check_site(client: SiteClient, site: Site) -> BoxFuture<'static, Result<CheckOk, CheckError>> {
+    match site.check_method {
+        CheckMethod::Head => Box::pin(check_site_head(client, site)),
+        CheckMethod::Hash => Box::pin(check_site_hash(client, site)),
+        CheckMethod::Regex(_) | CheckMethod::Selector(_) | CheckMethod::JsonPointer(_) => {
+            Box::pin(check_site_region(client, site))
+        }
+        CheckMethod::Feed => Box::pin(check_site_feed(client, site)),
+    }
+}
+
+/// HTTP client to send the requests with the options of the sites.
+#[derive(Clone)]
+struct SiteClient {
+    client: reqwest::Client,
+    max_retries: u32,
+    retry_base_delay: Duration,
+}
+
+impl SiteClient {
+    /// Sends the request with retrying on the network errors and the server errors.
+    async fn send(
+        &self,
+        site: &Site,
+        method: reqwest::Method,
+        headers: header::HeaderMap,
+    ) -> Fallible<reqwest::Response> {
+        let mut retries = 0;
+        loop {
+            let request = self.build_request(site, method.clone(), headers.clone())?;
+            match request.send().await {
+                Ok(response) if retries < self.max_retries && is_retryable(response.status()) => {
+                    warn!(
+                        "retry {}: unexpected status code: {}",
+                        site.title,
+                        response.status().as_u16(),
+                    );
+                }
+                Ok(response) => return Ok(response),
+                Err(e) if retries < self.max_retries && (e.is_connect() || e.is_timeout()) => {
+                    warn!(?e, "retry {}", site.title);
+                }
+                Err(e) => return Err(e.into()),
             }
+
+            tokio::time::sleep(backoff_delay(self.retry_base_delay, retries)).await;
+            retries += 1;
         }
     }
 
+    fn build_request(
+        &self,
+        site: &Site,
+        method: reqwest::Method,
+        mut headers: header::HeaderMap,
+    ) -> Fallible<reqwest::RequestBuilder> {
+        for (name, value) in site.headers.iter().flatten() {
+            headers.insert(
+                header::HeaderName::from_bytes(name.as_bytes())
+                    .with_context(|| format!("invalid header name: {name}"))?,
+                value
+                    .parse()
+                    .with_context(|| format!("invalid header value: {name}"))?,
+            );
+        }
+
+        if let Some(cookies) = &site.cookies {
+            let cookie = cookies
+                .iter()
+                .map(|(name, value)| format!("{name}={value}"))
+                .collect::<Vec<_>>()
+                .join("; ");
+            headers.insert(header::COOKIE, cookie.parse().context("invalid cookies")?);
+        }
+
+        if let Some(user_agent) = &site.user_agent {
+            headers.insert(
+                header::USER_AGENT,
+                user_agent.parse().context("invalid user agent")?,
+            );
+        }
+
+        let mut request = self
+            .client
+            .request(method, site.uri.as_str())
+            .headers(headers);
+
+        match &site.auth {
+            Some(Auth::Basic { username, password }) => {
+                request = request.basic_auth(username, password.as_ref());
+            }
+            Some(Auth::Bearer(token)) => request = request.bearer_auth(token),
+            None => {}
+        }
+
+        if let Some(timeout) = &site.timeout {
+            request = request.timeout(parse_duration(timeout)?);
+        }
+
+        Ok(request)
+    }
+}
+
+fn is_retryable(status_code: StatusCode) -> bool {
+    status_code == StatusCode::TOO_MANY_REQUESTS || status_code.is_server_error()
+}
+
+/// Returns the delay before the retry after `retries` retries.
+fn backoff_delay(base_delay: Duration, retries: u32) -> Duration {
+    base_delay
+        .saturating_mul(2u32.saturating_pow(retries))
+        .min(MAX_RETRY_DELAY)
+}
+
+/// Prints the summary of the checks and returns it for the OpenTelemetry log.
+fn print_result(result: &CheckResult) -> String {
+    let CheckResult {
+        updated_sites,
+        not_modified_sites,
+        failing_sites,
+        error_sites,
+        ..
+    } = result;
+
     let mut otel_log_body = String::new();
 
     println!("# updated:");
@@ -240,6 +705,28 @@ async fn main() -> Fallible<()> {
         }
     }
 
+    println!("#");
+    println!("# failing:");
+    otel_log_body.push_str("failing:\n");
+    if failing_sites.is_empty() {
+        println!("#   (none)");
+        otel_log_body.push_str("  (none)\n");
+    } else {
+        for (site, e) in failing_sites.iter() {
+            let failures = site.consecutive_failures.unwrap_or_default();
+            println!(
+                "#   {} ({failures} times)\n#     reason: {}",
+                site.title, &e
+            );
+            otel_log_body.push_str("  ");
+            otel_log_body.push_str(&site.title);
+            otel_log_body.push_str(&format!(" ({failures} times)\n"));
+            otel_log_body.push_str("    reason: ");
+            otel_log_body.push_str(&e.to_string());
+            otel_log_body.push('\n');
+        }
+    }
+
     println!("#");
     println!("# error:");
     otel_log_body.push_str("error:\n");
@@ -260,73 +747,208 @@ async fn main() -> Fallible<()> {
         }
     }
 
-    let new_site_prefs = SitePrefsForSerialize { sites: new_prefs };
-    let new_prefs_string = toml::to_string(&new_site_prefs)?;
-    println!("#");
-    println!("# new config:");
-    println!("{new_prefs_string}");
-
-    if !updated_sites.is_empty() || !error_sites.is_empty() {
-        if let Some(slack) = opt.slack {
-            info!("notify to slack");
-            let ret_slack = client
-                .post(
-                    slack
-                        .slack_notify_url
-                        .expect("slack_notify_url should not be None"),
-                )
-                .header(header::CONTENT_TYPE, "application/json")
-                .body(generate_slack_payload(
-                    &slack.slack_notify_bot_name,
-                    &slack
-                        .slack_notify_channel
-                        .expect("slack_notify_channel should not be None"),
-                    &updated_sites,
-                    &error_sites,
-                )?)
-                .send()
-                .await?;
-            info!(?ret_slack);
-            info!(ret_slack_response_text = ret_slack.text().await?);
-        }
-
-        if let Some(telegram) = opt.telegram {
-            info!("notify to telegram");
-            let ret_telegram = client
-                .post(format!(
-                    "https://api.telegram.org/bot{}/sendMessage",
-                    telegram
-                        .telegram_bot_token
-                        .expect("telegram_bot_token should not be None"),
-                ))
-                .header(header::CONTENT_TYPE, "application/json")
-                .body(generate_telegram_payload(
-                    &telegram
-                        .telegram_chat_id
-                        .expect("telegram_chat_id should not be None"),
-                    &updated_sites,
-                    &error_sites,
-                )?)
-                .send()
-                .await?;
-            info!(?ret_telegram);
-            info!(ret_telegram_text = ret_telegram.text().await?);
-        }
-    }
-
-    if otel_guard.is_some() {
+    otel_log_body
+}
+
+async fn notify(opt: &Opt, client: &reqwest::Client, result: &CheckResult) -> Fallible<()> {
+    let CheckResult {
+        updated_sites,
+        error_sites,
+        ..
+    } = result;
+
+    if updated_sites.is_empty() && error_sites.is_empty() {
+        return Ok(());
+    }
+
+    if let Some(slack) = &opt.slack {
+        info!("notify to slack");
+        let ret_slack = client
+            .post(
+                slack
+                    .slack_notify_url
+                    .as_deref()
+                    .expect("slack_notify_url should not be None"),
+            )
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(generate_slack_payload(
+                &slack.slack_notify_bot_name,
+                slack
+                    .slack_notify_channel
+                    .as_deref()
+                    .expect("slack_notify_channel should not be None"),
+                updated_sites,
+                error_sites,
+            )?)
+            .send()
+            .await?;
+        info!(?ret_slack);
+        info!(ret_slack_response_text = ret_slack.text().await?);
+    }
+
+    if let Some(telegram) = &opt.telegram {
+        info!("notify to telegram");
+        let ret_telegram = client
+            .post(format!(
+                "https://api.telegram.org/bot{}/sendMessage",
+                telegram
+                    .telegram_bot_token
+                    .as_deref()
+                    .expect("telegram_bot_token should not be None"),
+            ))
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(generate_telegram_payload(
+                telegram
+                    .telegram_chat_id
+                    .as_deref()
+                    .expect("telegram_chat_id should not be None"),
+                updated_sites,
+                error_sites,
+            )?)
+            .send()
+            .await?;
+        info!(?ret_telegram);
+        info!(ret_telegram_text = ret_telegram.text().await?);
+    }
+
+    Ok(())
+}
+
+fn log_result(use_otel: bool, result: &CheckResult, otel_log_body: &str) {
+    if use_otel {
         info!(
             event.name = "device.app.result",
-            has_update = %!updated_sites.is_empty(),
+            has_update = %!result.updated_sites.is_empty(),
             "{}",
             otel_log_body,
         );
     }
+}
+
+/// Loads the sites of the config file and restores their states.
+fn load_sites(config_path: &Path, state: &State) -> Fallible<Vec<Site>> {
+    let site_prefs_string = std::fs::read_to_string(config_path)
+        .with_context(|| format!("failed to read {}", config_path.display()))?;
+    let site_prefs = toml::from_str::<SitePreferences>(&site_prefs_string)
+        .with_context(|| format!("failed to parse {}", config_path.display()))?;
+    verify_sites(&site_prefs.sites)?;
+
+    let mut sites = site_prefs.sites;
+    for site in sites.iter_mut() {
+        if let Some(site_state) = state.sites.get(&site.title) {
+            site.restore_state(site_state.clone());
+        }
+    }
+    Ok(sites)
+}
+
+fn verify_sites(sites: &[Site]) -> Fallible<()> {
+    let mut titles = HashSet::new();
+    for site in sites {
+        // the states are keyed by the title.
+        ensure!(
+            titles.insert(&site.title),
+            "duplicate site title: {}",
+            site.title,
+        );
+
+        if let Some(timeout) = &site.timeout {
+            parse_duration(timeout).with_context(|| format!("invalid timeout: {}", site.title))?;
+        }
+
+        ensure!(
+            site.failure_threshold != Some(0),
+            "failure_threshold should be positive: {}",
+            site.title,
+        );
 
+        // find the typos before the checks instead of failing every check.
+        match &site.check_method {
+            CheckMethod::Regex(pattern) => {
+                regex::Regex::new(pattern)
+                    .with_context(|| format!("invalid regex: {}", site.title))?;
+            }
+            CheckMethod::Selector(selector) => {
+                scraper::Selector::parse(selector)
+                    .map_err(|e| anyhow!("invalid selector: {}: {e}", site.title))?;
+            }
+            _ => (),
+        }
+
+        if let Some(check_every) = &site.check_every {
+            let check_every = parse_duration(check_every)
+                .with_context(|| format!("invalid check_every: {}", site.title))?;
+            ensure!(
+                !check_every.is_zero(),
+                "check_every should be positive: {}",
+                site.title,
+            );
+        }
+    }
     Ok(())
 }
 
-async fn check_site_head(client: reqwest::Client, site: Site) -> Result<CheckOk, CheckError> {
+fn load_state(path: &Path) -> Fallible<State> {
+    let state_string = match std::fs::read_to_string(path) {
+        Ok(data) => data,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(State::default()),
+        Err(e) => return Err(anyhow!(e).context(format!("failed to read {}", path.display()))),
+    };
+    toml::from_str(&state_string).with_context(|| format!("failed to parse {}", path.display()))
+}
+
+fn save_state(path: &Path, state: &State) -> Fallible<()> {
+    write_atomically(path, toml::to_string(state)?)
+}
+
+/// Stores the states of the checked sites and schedules their next checks.
+fn update_state(
+    opt: &Opt,
+    state: &mut State,
+    sites: &[Rc<Site>],
+    checked_at: chrono::DateTime<chrono::Utc>,
+) -> Fallible<()> {
+    for site in sites {
+        let check_every = match &site.check_every {
+            Some(data) => parse_duration(data)?,
+            None => opt.default_check_every,
+        };
+        let jitter = Duration::from_millis(rand::random_range(
+            0..=opt.jitter.as_millis().try_into().unwrap_or(u64::MAX),
+        ));
+
+        let mut site_state = site.to_state();
+        site_state.next_check =
+            Some(checked_at + chrono::TimeDelta::from_std(check_every + jitter)?);
+        state.sites.insert(site.title.clone(), site_state);
+    }
+    Ok(())
+}
+
+/// Parses a duration such as `30m`, `6h` or `1d`.
+fn parse_duration(value: &str) -> Fallible<Duration> {
+    let value = value.trim();
+    let Some(unit_index) = value.find(|c: char| !c.is_ascii_digit()) else {
+        bail!("missing unit: {value}");
+    };
+    let (num, unit) = value.split_at(unit_index);
+    let num = num
+        .parse::<u64>()
+        .with_context(|| format!("invalid duration: {value}"))?;
+    let unit_secs = match unit {
+        "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 60 * 60 * 24,
+        _ => bail!("unknown unit: {value}"),
+    };
+    match num.checked_mul(unit_secs) {
+        Some(secs) => Ok(Duration::from_secs(secs)),
+        None => bail!("too long duration: {value}"),
+    }
+}
+
+async fn check_site_head(client: SiteClient, site: Site) -> Result<CheckOk, CheckError> {
     use reqwest::header::ToStrError;
 
     let mut headers = header::HeaderMap::new();
@@ -357,12 +979,12 @@ async fn check_site_head(client: reqwest::Client, site: Site) -> Result<CheckOk,
         }
     }
 
-    let response = match client.head(site.uri.as_str()).headers(headers).send().await {
+    let response = match client.send(&site, reqwest::Method::HEAD, headers).await {
         Ok(data) => data,
         Err(e) => {
             return Err(CheckError {
                 site,
-                source: anyhow!(e).context("failed to send request"),
+                source: e.context("failed to send request"),
             });
         }
     };
@@ -435,13 +1057,16 @@ async fn check_site_head(client: reqwest::Client, site: Site) -> Result<CheckOk,
     }
 }
 
-async fn check_site_hash(client: reqwest::Client, site: Site) -> Result<CheckOk, CheckError> {
-    let response = match client.get(site.uri.as_str()).send().await {
+async fn check_site_hash(client: SiteClient, site: Site) -> Result<CheckOk, CheckError> {
+    let response = match client
+        .send(&site, reqwest::Method::GET, header::HeaderMap::new())
+        .await
+    {
         Ok(data) => data,
         Err(e) => {
             return Err(CheckError {
                 site,
-                source: anyhow!(e).context("failed to send request"),
+                source: e.context("failed to send request"),
             });
         }
     };
@@ -454,6 +1079,12 @@ async fn check_site_hash(client: reqwest::Client, site: Site) -> Result<CheckOk,
         });
     }
 
+    let is_html = response
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|data| data.to_str().ok())
+        .is_some_and(|data| data.contains("html"));
+
     let response_bytes = match response.bytes().await {
         Ok(data) => data,
         Err(e) => {
@@ -471,15 +1102,288 @@ async fn check_site_hash(client: reqwest::Client, site: Site) -> Result<CheckOk,
         None => true,
     };
 
+    let text = normalize_page_text(&String::from_utf8_lossy(&response_bytes), is_html);
+    let diff = match (updated, &site.text) {
+        (true, Some(prev_text)) => {
+            Some(unified_diff(prev_text, &text)).filter(|data| !data.is_empty())
+        }
+        _ => None,
+    };
+
     Ok(CheckOk {
         updated,
         site: Site {
             hash: Some(response_hash_string),
+            text: Some(text),
+            diff,
+            ..site
+        },
+    })
+}
+
+/// Converts the page to the lines of the text to compare with the previous one.
+fn normalize_page_text(body: &str, is_html: bool) -> String {
+    if !is_html {
+        return body
+            .lines()
+            .map(normalize_whitespace)
+            .filter(|data| !data.is_empty())
+            .collect::<Vec<_>>()
+            .join("\n");
+    }
+
+    let document = scraper::Html::parse_document(body);
+    document
+        .root_element()
+        .descendants()
+        .filter(|node| {
+            !node.ancestors().any(|ancestor| {
+                ancestor.value().as_element().is_some_and(|data| {
+                    matches!(data.name(), "script" | "style" | "noscript" | "template")
+                })
+            })
+        })
+        .filter_map(|node| node.value().as_text())
+        .map(|data| normalize_whitespace(data))
+        .filter(|data| !data.is_empty())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Returns the unified diff of the lines without the file header.
+fn unified_diff(old: &str, new: &str) -> String {
+    // terminate the last lines not to be marked as missing newline.
+    let old = format!("{old}\n");
+    let new = format!("{new}\n");
+    similar::TextDiff::from_lines(&old, &new)
+        .unified_diff()
+        .context_radius(1)
+        .to_string()
+}
+
+/// Returns the head of the diff for the notifications.
+fn truncate_diff(diff: &str) -> String {
+    let lines = diff.lines().collect::<Vec<_>>();
+    let mut ret = String::new();
+    for (index, line) in lines.iter().enumerate() {
+        if DIFF_MAX_LINES <= index || DIFF_MAX_CHARS < ret.chars().count() + line.chars().count() {
+            ret.push_str(&format!("… ({} more lines)", lines.len() - index));
+            return ret;
+        }
+        ret.push_str(line);
+        ret.push('\n');
+    }
+    ret.pop();
+    ret
+}
+
+async fn check_site_region(client: SiteClient, site: Site) -> Result<CheckOk, CheckError> {
+    let response = match client
+        .send(&site, reqwest::Method::GET, header::HeaderMap::new())
+        .await
+    {
+        Ok(data) => data,
+        Err(e) => {
+            return Err(CheckError {
+                site,
+                source: e.context("failed to send request"),
+            });
+        }
+    };
+
+    let status_code = response.status();
+    if status_code != StatusCode::OK {
+        return Err(CheckError {
+            site,
+            source: anyhow!("unexpected status code: {}", status_code.as_u16()),
+        });
+    }
+
+    let response_text = match response.text().await {
+        Ok(data) => data,
+        Err(e) => {
+            return Err(CheckError {
+                site,
+                source: anyhow!(e).context("failed to parse response"),
+            });
+        }
+    };
+
+    let extracted = match extract_region(&site.check_method, &response_text) {
+        Ok(data) => data,
+        Err(e) => {
+            return Err(CheckError {
+                site,
+                source: e.context("failed to extract the region"),
+            });
+        }
+    };
+
+    let extracted_hash = Sha3_224::digest(extracted.as_bytes());
+    let extracted_hash_string = HexFormat(extracted_hash.as_ref()).to_string();
+    let updated = match &site.hash {
+        Some(data) => data != &extracted_hash_string,
+        None => true,
+    };
+
+    Ok(CheckOk {
+        updated,
+        site: Site {
+            hash: Some(extracted_hash_string),
+            extracted: Some(extracted),
+            ..site
+        },
+    })
+}
+
+/// Extracts the region to check from the response body.
+fn extract_region(check_method: &CheckMethod, body: &str) -> Fallible<String> {
+    let extracted = match check_method {
+        CheckMethod::Head | CheckMethod::Hash | CheckMethod::Feed => {
+            bail!("not a region check method")
+        }
+        CheckMethod::Regex(pattern) => {
+            let reg = regex::Regex::new(pattern)?;
+            reg.captures_iter(body)
+                .map(|data| data.get(1).unwrap_or_else(|| data.get_match()).as_str())
+                .map(normalize_whitespace)
+                .collect::<Vec<_>>()
+        }
+        CheckMethod::Selector(selector) => {
+            let selector =
+                scraper::Selector::parse(selector).map_err(|e| anyhow!("invalid selector: {e}"))?;
+            scraper::Html::parse_document(body)
+                .select(&selector)
+                .map(|data| normalize_whitespace(&data.text().collect::<Vec<_>>().join(" ")))
+                .collect::<Vec<_>>()
+        }
+        CheckMethod::JsonPointer(pointer) => {
+            let value = serde_json::from_str::<serde_json::Value>(body)
+                .context("failed to parse the response as JSON")?;
+            let value = value
+                .pointer(pointer)
+                .with_context(|| format!("no value at {pointer}"))?;
+            match value {
+                serde_json::Value::String(data) => vec![data.clone()],
+                _ => vec![serde_json::to_string_pretty(value)?],
+            }
+        }
+    };
+
+    ensure!(!extracted.is_empty(), "no region matched");
+    Ok(extracted.join("\n"))
+}
+
+async fn check_site_feed(client: SiteClient, site: Site) -> Result<CheckOk, CheckError> {
+    let response = match client
+        .send(&site, reqwest::Method::GET, header::HeaderMap::new())
+        .await
+    {
+        Ok(data) => data,
+        Err(e) => {
+            return Err(CheckError {
+                site,
+                source: e.context("failed to send request"),
+            });
+        }
+    };
+
+    let status_code = response.status();
+    if status_code != StatusCode::OK {
+        return Err(CheckError {
+            site,
+            source: anyhow!("unexpected status code: {}", status_code.as_u16()),
+        });
+    }
+
+    let response_bytes = match response.bytes().await {
+        Ok(data) => data,
+        Err(e) => {
+            return Err(CheckError {
+                site,
+                source: anyhow!(e).context("failed to parse response"),
+            });
+        }
+    };
+
+    let entries = match parse_feed(&response_bytes) {
+        Ok(data) => data,
+        Err(e) => {
+            return Err(CheckError {
+                site,
+                source: e.context("failed to parse the feed"),
+            });
+        }
+    };
+
+    // report nothing at the first check not to list every entry.
+    let new_entries = match &site.seen_guids {
+        Some(seen_guids) => entries
+            .iter()
+            .filter(|(id, _)| !seen_guids.contains(id))
+            .map(|(_, entry)| FeedEntry {
+                title: entry.title.clone(),
+                link: entry.link.clone(),
+            })
+            .collect(),
+        None => vec![],
+    };
+    let updated = site.seen_guids.is_none() || !new_entries.is_empty();
+
+    Ok(CheckOk {
+        updated,
+        site: Site {
+            seen_guids: Some(entries.into_iter().map(|(id, _)| id).collect()),
+            new_entries,
             ..site
         },
     })
 }
 
+/// Parses the RSS or Atom feed and returns the IDs and the entries.
+fn parse_feed(body: &[u8]) -> Fallible<Vec<(String, FeedEntry)>> {
+    let feed = feed_rs::parser::parse(body)?;
+    Ok(feed
+        .entries
+        .into_iter()
+        .map(|entry| {
+            let link = entry
+                .links
+                .iter()
+                .find_map(|data| Url::parse(&data.href).ok());
+            let title = entry
+                .title
+                .map(|data| normalize_whitespace(&data.content))
+                .filter(|data| !data.is_empty())
+                .or_else(|| link.as_ref().map(Url::to_string))
+                .unwrap_or_else(|| entry.id.clone());
+            (entry.id, FeedEntry { title, link })
+        })
+        .collect())
+}
+
+/// Collapses the runs of whitespaces into a space.
+fn normalize_whitespace(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Returns the head of the extracted text for the notifications.
+fn truncate_extracted(text: &str) -> String {
+    match text.char_indices().nth(EXTRACTED_TEXT_MAX_CHARS) {
+        Some((index, _)) => format!("{}…", &text[..index]),
+        None => text.to_string(),
+    }
+}
+
+/// Escapes the control characters of the Slack mrkdwn.
+///
+/// See <https://api.slack.com/reference/surfaces/formatting#escaping>.
+fn escape_slack(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
 fn generate_slack_payload(
     bot_name: &str,
     channel_id: &str,
@@ -499,15 +1403,41 @@ fn generate_slack_payload(
         }));
 
         for site in updated_sites {
+            let mut text = format!(
+                "<{}|{}>",
+                site.uri_open.as_ref().unwrap_or(&site.uri),
+                site.title,
+            );
+            if let Some(extracted) = &site.extracted {
+                for line in truncate_extracted(extracted).lines() {
+                    text.push_str("\n>");
+                    text.push_str(&escape_slack(line));
+                }
+            }
+            for entry in site.new_entries.iter().take(FEED_ENTRIES_MAX) {
+                let title = escape_slack(&entry.title);
+                match &entry.link {
+                    Some(link) => text.push_str(&format!("\n• <{link}|{title}>")),
+                    None => text.push_str(&format!("\n• {title}")),
+                }
+            }
+            if FEED_ENTRIES_MAX < site.new_entries.len() {
+                text.push_str(&format!(
+                    "\n• and {} more",
+                    site.new_entries.len() - FEED_ENTRIES_MAX
+                ));
+            }
+            if let Some(diff) = &site.diff {
+                text.push_str(&format!(
+                    "\n```\n{}\n```",
+                    escape_slack(&truncate_diff(diff))
+                ));
+            }
             blocks.push(json!({
                 "type": "section",
                 "text": {
                     "type": "mrkdwn",
-                    "text": format!(
-                        "<{}|{}>",
-                        site.uri_open.as_ref().unwrap_or(&site.uri),
-                        site.title,
-                    ),
+                    "text": text,
                 },
             }));
         }
@@ -564,6 +1494,8 @@ fn generate_telegram_payload(
 ) -> Fallible<String> {
     let mut text = String::new();
     let reg = regex::Regex::new(r#"([_*\[\]()~`>#+=\-|{}\.!])"#)?;
+    let reg_pre = regex::Regex::new(r#"([`\\])"#)?;
+    let reg_link = regex::Regex::new(r#"([)\\])"#)?;
 
     if !updated_sites.is_empty() {
         text += "*Updated*";
@@ -572,8 +1504,35 @@ fn generate_telegram_payload(
             text += &format!(
                 "\n[{}]({})",
                 reg.replace_all(&site.title, r#"\$1"#),
-                site.uri_open.as_ref().unwrap_or(&site.uri)
+                reg_link.replace_all(
+                    site.uri_open.as_ref().unwrap_or(&site.uri).as_str(),
+                    r#"\$1"#
+                )
             );
+            if let Some(extracted) = &site.extracted {
+                for line in truncate_extracted(extracted).lines() {
+                    text += &format!("\n>{}", reg.replace_all(line, r#"\$1"#));
+                }
+            }
+            for entry in site.new_entries.iter().take(FEED_ENTRIES_MAX) {
+                let title = reg.replace_all(&entry.title, r#"\$1"#);
+                match &entry.link {
+                    Some(link) => {
+                        let link = reg_link.replace_all(link.as_str(), r#"\$1"#);
+                        text += &format!("\n• [{title}]({link})");
+                    }
+                    None => text += &format!("\n• {title}"),
+                }
+            }
+            if FEED_ENTRIES_MAX < site.new_entries.len() {
+                text += &format!("\n• and {} more", site.new_entries.len() - FEED_ENTRIES_MAX);
+            }
+            if let Some(diff) = &site.diff {
+                text += &format!(
+                    "\n```\n{}\n```",
+                    reg_pre.replace_all(&truncate_diff(diff), r#"\$1"#)
+                );
+            }
         }
     }
 
@@ -587,7 +1546,10 @@ fn generate_telegram_payload(
             text += &format!(
                 "\n[{}]({})\n>{}",
                 reg.replace_all(&site.title, r#"\$1"#),
-                site.uri_open.as_ref().unwrap_or(&site.uri),
+                reg_link.replace_all(
+                    site.uri_open.as_ref().unwrap_or(&site.uri).as_str(),
+                    r#"\$1"#
+                ),
                 reg.replace_all(&e.to_string(), r#"\$1"#),
             );
         }
@@ -718,4 +1680,656 @@ mod tests {
         ]);
         assert!(opt.is_err());
     }
+
+    #[test]
+    fn extract_region_regex() {
+        let body = "<p>price: 1,000 yen</p><p>updated: 2026-01-01</p><p>price: 2,000 yen</p>";
+        assert_eq!(
+            extract_region(&CheckMethod::Regex(r"price: ([\d,]+)".into()), body).unwrap(),
+            "1,000\n2,000"
+        );
+        assert_eq!(
+            extract_region(&CheckMethod::Regex(r"updated: \S+?<".into()), body).unwrap(),
+            "updated: 2026-01-01<"
+        );
+        assert!(extract_region(&CheckMethod::Regex("stock".into()), body).is_err());
+    }
+
+    #[test]
+    fn extract_region_selector() {
+        let body = r#"<html><body>
+<div class="ad">ad 123</div>
+<ul id="news">
+  <li>first
+    item</li>
+  <li><a href="/2">second</a> item</li>
+</ul>
+</body></html>"#;
+        assert_eq!(
+            extract_region(&CheckMethod::Selector("#news li".into()), body).unwrap(),
+            "first item\nsecond item"
+        );
+        assert!(extract_region(&CheckMethod::Selector(".missing".into()), body).is_err());
+        assert!(extract_region(&CheckMethod::Selector("<<".into()), body).is_err());
+    }
+
+    #[test]
+    fn extract_region_json_pointer() {
+        let body = r#"{"data":{"version":"1.2.3","assets":[{"name":"a"}]},"time":123}"#;
+        assert_eq!(
+            extract_region(&CheckMethod::JsonPointer("/data/version".into()), body).unwrap(),
+            "1.2.3"
+        );
+        assert_eq!(
+            extract_region(&CheckMethod::JsonPointer("/data/assets/0".into()), body).unwrap(),
+            "{\n  \"name\": \"a\"\n}"
+        );
+        assert!(extract_region(&CheckMethod::JsonPointer("/missing".into()), body).is_err());
+    }
+
+    #[test]
+    fn check_method_from_toml() {
+        let prefs = toml::from_str::<SitePreferences>(
+            r##"
+[[sites]]
+title = "hash"
+uri = "https://example.com/"
+check_method = "Hash"
+
+[[sites]]
+title = "selector"
+uri = "https://example.com/"
+check_method = { Selector = "#news li" }
+hash = "abc"
+extracted = "first item"
+"##,
+        )
+        .unwrap();
+        assert!(matches!(prefs.sites[0].check_method, CheckMethod::Hash));
+        assert!(
+            matches!(&prefs.sites[1].check_method, CheckMethod::Selector(data) if data == "#news li")
+        );
+        assert_eq!(prefs.sites[1].extracted.as_deref(), Some("first item"));
+    }
+
+    #[test]
+    fn site_prefs_for_serialize_omits_text() {
+        let prefs = toml::from_str::<SitePreferences>(
+            r##"
+[[sites]]
+title = "hash"
+uri = "https://example.com/"
+check_method = "Hash"
+hash = "abc"
+text = "whole page"
+"##,
+        )
+        .unwrap();
+        assert_eq!(prefs.sites[0].text.as_deref(), Some("whole page"));
+
+        let serialized = toml::to_string(&SitePrefsForSerialize {
+            sites: prefs.sites.into_iter().map(Rc::new).collect(),
+        })
+        .unwrap();
+        assert!(serialized.contains(r#"hash = "abc""#), "{serialized}");
+        assert!(!serialized.contains("whole page"), "{serialized}");
+    }
+
+    #[test]
+    fn cli_config_ok() {
+        Opt::try_parse_from([
+            "siteupdatechecker",
+            "--config",
+            "sites.toml",
+            "--state",
+            "state.toml",
+            "--daemon",
+        ])
+        .unwrap();
+
+        // w/o `--state`.
+        Opt::try_parse_from(["siteupdatechecker", "--config", "sites.toml"]).unwrap_err();
+
+        // w/o `--config`.
+        Opt::try_parse_from(["siteupdatechecker", "--daemon"]).unwrap_err();
+    }
+
+    #[test]
+    fn parse_duration_units() {
+        assert_eq!(parse_duration("90s").unwrap(), Duration::from_secs(90));
+        assert_eq!(parse_duration("30m").unwrap(), Duration::from_secs(30 * 60));
+        assert_eq!(
+            parse_duration("6h").unwrap(),
+            Duration::from_secs(6 * 60 * 60)
+        );
+        assert_eq!(
+            parse_duration("1d").unwrap(),
+            Duration::from_secs(24 * 60 * 60)
+        );
+        assert!(parse_duration("6").is_err());
+        assert!(parse_duration("h").is_err());
+        assert!(parse_duration("6w").is_err());
+    }
+
+    #[test]
+    fn load_sites_restores_state() {
+        let dir = tempfile::tempdir().unwrap();
+        let config_path = dir.path().join("sites.toml");
+        let state_path = dir.path().join("state.toml");
+        std::fs::write(
+            &config_path,
+            r##"
+[[sites]]
+title = "hash"
+uri = "https://example.com/"
+check_method = "Hash"
+check_every = "6h"
+
+[[sites]]
+title = "head"
+uri = "https://example.com/head"
+check_method = "Head"
+"##,
+        )
+        .unwrap();
+
+        let state = load_state(&state_path).unwrap();
+        assert!(state.sites.is_empty());
+
+        let mut state = State::default();
+        state.sites.insert(
+            "hash".into(),
+            SiteState {
+                hash: Some("abc".into()),
+                next_check: Some("2026-01-01T00:00:00Z".parse().unwrap()),
+                ..Default::default()
+            },
+        );
+        save_state(&state_path, &state).unwrap();
+
+        let state = load_state(&state_path).unwrap();
+        let sites = load_sites(&config_path, &state).unwrap();
+        assert_eq!(sites[0].hash.as_deref(), Some("abc"));
+        assert_eq!(sites[0].check_every.as_deref(), Some("6h"));
+        assert_eq!(sites[0].to_state(), state.sites["hash"]);
+        assert_eq!(sites[1].hash, None);
+        assert_eq!(sites[1].next_check, None);
+    }
+
+    #[test]
+    fn load_sites_duplicate_title() {
+        let dir = tempfile::tempdir().unwrap();
+        let config_path = dir.path().join("sites.toml");
+        std::fs::write(
+            &config_path,
+            r##"
+[[sites]]
+title = "site"
+uri = "https://example.com/"
+check_method = "Hash"
+
+[[sites]]
+title = "site"
+uri = "https://example.com/head"
+check_method = "Head"
+"##,
+        )
+        .unwrap();
+        assert!(load_sites(&config_path, &State::default()).is_err());
+    }
+
+    #[test]
+    fn verify_sites_invalid_check_method() {
+        let verify = |check_method: &str| {
+            let prefs = toml::from_str::<SitePreferences>(&format!(
+                r##"
+[[sites]]
+title = "site"
+uri = "https://example.com/"
+check_method = {check_method}
+"##
+            ))
+            .unwrap();
+            verify_sites(&prefs.sites)
+        };
+        assert!(verify(r"{ Regex = 'v(\d+' }").is_err());
+        assert!(verify("{ Selector = '#news [' }").is_err());
+        verify(r"{ Regex = 'v(\d+)' }").unwrap();
+        verify("{ Selector = '#news li' }").unwrap();
+    }
+
+    /// Starts a stand-in of a site that responds with `responses` in order and records the
+    /// request headers.
+    async fn serve_site(
+        responses: Vec<axum::http::StatusCode>,
+    ) -> (Url, Arc<std::sync::Mutex<Vec<axum::http::HeaderMap>>>) {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let uri = Url::parse(&format!("http://{}/", listener.local_addr().unwrap())).unwrap();
+
+        let requests = Arc::new(std::sync::Mutex::new(vec![]));
+        let responses = Arc::new(std::sync::Mutex::new(responses.into_iter()));
+        let router = axum::Router::new().route(
+            "/",
+            axum::routing::get({
+                let requests = requests.clone();
+                move |headers: axum::http::HeaderMap| {
+                    requests.lock().unwrap().push(headers);
+                    let status = responses.lock().unwrap().next().unwrap();
+                    async move { (status, "body") }
+                }
+            }),
+        );
+
+        tokio::spawn(async move {
+            axum::serve(listener, router).await.unwrap();
+        });
+
+        (uri, requests)
+    }
+
+    /// Returns a site of `https://example.com/` that has no states.
+    fn test_site(check_method: CheckMethod) -> Site {
+        Site {
+            title: "Example".into(),
+            uri: Url::parse("https://example.com/").unwrap(),
+            uri_open: None,
+            check_method,
+            hash: None,
+            last_modified: None,
+            etag: None,
+            extracted: None,
+            seen_guids: None,
+            new_entries: vec![],
+            check_every: None,
+            next_check: None,
+            text: None,
+            diff: None,
+            consecutive_failures: None,
+            failure_threshold: None,
+            timeout: None,
+            user_agent: None,
+            auth: None,
+            headers: None,
+            cookies: None,
+        }
+    }
+
+    fn test_site_client() -> SiteClient {
+        SiteClient {
+            client: reqwest::Client::new(),
+            max_retries: 2,
+            retry_base_delay: Duration::from_millis(1),
+        }
+    }
+
+    #[tokio::test]
+    async fn site_client_sends_site_options_with_retry() {
+        let (uri, requests) = serve_site(vec![
+            axum::http::StatusCode::SERVICE_UNAVAILABLE,
+            axum::http::StatusCode::OK,
+        ])
+        .await;
+        let prefs = toml::from_str::<SitePreferences>(&format!(
+            r##"
+[[sites]]
+title = "site"
+uri = "{uri}"
+check_method = "Hash"
+timeout = "10s"
+user_agent = "custom-agent"
+auth = {{ Bearer = "token" }}
+headers = {{ Accept-Language = "ja" }}
+cookies = {{ session = "abc", theme = "dark" }}
+"##
+        ))
+        .unwrap();
+        verify_sites(&prefs.sites).unwrap();
+
+        let response = test_site_client()
+            .send(
+                &prefs.sites[0],
+                reqwest::Method::GET,
+                header::HeaderMap::new(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let requests = requests.lock().unwrap();
+        assert_eq!(requests.len(), 2);
+        let headers = &requests[1];
+        assert_eq!(headers["authorization"], "Bearer token");
+        assert_eq!(headers["user-agent"], "custom-agent");
+        assert_eq!(headers["accept-language"], "ja");
+        assert_eq!(headers["cookie"], "session=abc; theme=dark");
+    }
+
+    #[tokio::test]
+    async fn site_client_gives_up_after_max_retries() {
+        let (uri, requests) = serve_site(vec![axum::http::StatusCode::BAD_GATEWAY; 3]).await;
+        let prefs = toml::from_str::<SitePreferences>(&format!(
+            r##"
+[[sites]]
+title = "site"
+uri = "{uri}"
+check_method = "Hash"
+auth = {{ Basic = {{ username = "user", password = "pass" }} }}
+"##
+        ))
+        .unwrap();
+
+        let response = test_site_client()
+            .send(
+                &prefs.sites[0],
+                reqwest::Method::GET,
+                header::HeaderMap::new(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_GATEWAY);
+
+        let requests = requests.lock().unwrap();
+        assert_eq!(requests.len(), 3);
+        assert_eq!(requests[0]["authorization"], "Basic dXNlcjpwYXNz");
+    }
+
+    #[tokio::test]
+    async fn check_sites_reports_error_after_threshold() {
+        let (uri, _) = serve_site(vec![axum::http::StatusCode::NOT_FOUND; 2]).await;
+        let opt = Opt::try_parse_from(["siteupdatechecker", "--failure-threshold", "2"]).unwrap();
+        let prefs = toml::from_str::<SitePreferences>(&format!(
+            r##"
+[[sites]]
+title = "site"
+uri = "{uri}"
+check_method = "Hash"
+"##
+        ))
+        .unwrap();
+
+        let result = check_sites(&opt, &test_site_client(), prefs.sites).await;
+        assert_eq!(result.failing_sites.len(), 1);
+        assert!(result.error_sites.is_empty());
+        assert_eq!(result.sites[0].consecutive_failures, Some(1));
+
+        let mut site = toml::from_str::<SitePreferences>(&format!(
+            r##"
+[[sites]]
+title = "site"
+uri = "{uri}"
+check_method = "Hash"
+"##
+        ))
+        .unwrap()
+        .sites
+        .remove(0);
+        site.restore_state(result.sites[0].to_state());
+
+        let result = check_sites(&opt, &test_site_client(), vec![site]).await;
+        assert!(result.failing_sites.is_empty());
+        assert_eq!(result.error_sites.len(), 1);
+        assert_eq!(result.sites[0].consecutive_failures, Some(2));
+    }
+
+    #[test]
+    fn generate_telegram_payload_with_extracted() {
+        let site = Rc::new(Site {
+            extracted: Some("v1.2\nrelease!".into()),
+            ..test_site(CheckMethod::Regex("(.+)".into()))
+        });
+        let payload = generate_telegram_payload("123", &[site], &[]).unwrap();
+        let payload = serde_json::from_str::<serde_json::Value>(&payload).unwrap();
+        assert_eq!(
+            payload["text"],
+            "*Updated*\n[Example](https://example.com/)\n>v1\\.2\n>release\\!"
+        );
+    }
+
+    #[test]
+    fn generate_slack_payload_with_extracted() {
+        let site = Rc::new(Site {
+            extracted: Some("<b>v1.2</b>\nQ&A".into()),
+            ..test_site(CheckMethod::Selector("#news".into()))
+        });
+        let payload = generate_slack_payload("bot", "channel", &[site], &[]).unwrap();
+        let payload = serde_json::from_str::<serde_json::Value>(&payload).unwrap();
+        assert_eq!(
+            payload["blocks"][1]["text"]["text"],
+            "<https://example.com/|Example>\n>&lt;b&gt;v1.2&lt;/b&gt;\n>Q&amp;A"
+        );
+    }
+
+    #[test]
+    fn normalize_page_text_html() {
+        let body = r#"<html>
+<head><title>Example</title><style>body { color: red; }</style></head>
+<body>
+  <script>var a = 1;</script>
+  <h1>News</h1>
+  <ul>
+    <li>first
+      item</li>
+    <li>second item</li>
+  </ul>
+</body>
+</html>"#;
+        assert_eq!(
+            normalize_page_text(body, true),
+            "Example\nNews\nfirst item\nsecond item"
+        );
+        assert_eq!(
+            normalize_page_text("{\n  \"a\":  1\n\n}", false),
+            "{\n\"a\": 1\n}"
+        );
+    }
+
+    #[test]
+    fn unified_diff_changed_line() {
+        assert_eq!(
+            unified_diff("a\nb\nc\nd\ne", "a\nb\nC\nd\ne"),
+            "@@ -2,3 +2,3 @@\n b\n-c\n+C\n d\n"
+        );
+        assert_eq!(unified_diff("a\nb", "a\nb"), "");
+    }
+
+    #[test]
+    fn truncate_diff_long_diff() {
+        let diff = (0..30).map(|i| format!("+line {i}\n")).collect::<String>();
+        let truncated = truncate_diff(&diff);
+        assert_eq!(truncated.lines().count(), DIFF_MAX_LINES + 1);
+        assert!(truncated.starts_with("+line 0\n"));
+        assert!(truncated.ends_with("+line 19\n… (10 more lines)"));
+
+        let diff = format!("+{}\n+short\n", "a".repeat(DIFF_MAX_CHARS));
+        assert_eq!(truncate_diff(&diff), "… (2 more lines)");
+
+        assert_eq!(truncate_diff("-a\n+b\n"), "-a\n+b");
+    }
+
+    #[test]
+    fn generate_telegram_payload_with_diff() {
+        let site = Rc::new(Site {
+            text: Some("price: `10`".into()),
+            diff: Some("@@ -1 +1 @@\n-price: `9`\n+price: `10`\n".into()),
+            ..test_site(CheckMethod::Hash)
+        });
+        let payload = generate_telegram_payload("123", &[site], &[]).unwrap();
+        let payload = serde_json::from_str::<serde_json::Value>(&payload).unwrap();
+        assert_eq!(
+            payload["text"],
+            "*Updated*\n[Example](https://example.com/)\n```\n@@ -1 +1 @@\n-price: \\`9\\`\n+price: \\`10\\`\n```"
+        );
+    }
+
+    #[test]
+    fn generate_slack_payload_with_diff() {
+        let site = Rc::new(Site {
+            text: Some("<p>Q&A</p>".into()),
+            diff: Some("@@ -1 +1 @@\n-<p>FAQ</p>\n+<p>Q&A</p>\n".into()),
+            ..test_site(CheckMethod::Hash)
+        });
+        let payload = generate_slack_payload("bot", "channel", &[site], &[]).unwrap();
+        let payload = serde_json::from_str::<serde_json::Value>(&payload).unwrap();
+        assert_eq!(
+            payload["blocks"][1]["text"]["text"],
+            "<https://example.com/|Example>\n```\n@@ -1 +1 @@\n-&lt;p&gt;FAQ&lt;/p&gt;\n+&lt;p&gt;Q&amp;A&lt;/p&gt;\n```"
+        );
+    }
+
+    #[test]
+    fn parse_feed_rss() {
+        let body = r#"<?xml version="1.0"?>
+<rss version="2.0">
+  <channel>
+    <title>Example</title>
+    <link>https://example.com/</link>
+    <description>example</description>
+    <item>
+      <title>Second post</title>
+      <link>https://example.com/2</link>
+      <guid>https://example.com/2</guid>
+    </item>
+    <item>
+      <title>First
+        post</title>
+      <link>https://example.com/1</link>
+      <guid isPermaLink="false">post-1</guid>
+    </item>
+  </channel>
+</rss>"#;
+        let entries = parse_feed(body.as_bytes()).unwrap();
+        assert_eq!(
+            entries,
+            vec![
+                (
+                    "https://example.com/2".to_string(),
+                    FeedEntry {
+                        title: "Second post".into(),
+                        link: Some(Url::parse("https://example.com/2").unwrap()),
+                    }
+                ),
+                (
+                    "post-1".to_string(),
+                    FeedEntry {
+                        title: "First post".into(),
+                        link: Some(Url::parse("https://example.com/1").unwrap()),
+                    }
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_feed_atom() {
+        let body = r#"<?xml version="1.0" encoding="utf-8"?>
+<feed xmlns="http://www.w3.org/2005/Atom">
+  <title>Example</title>
+  <id>urn:example</id>
+  <updated>2026-01-01T00:00:00Z</updated>
+  <entry>
+    <id>urn:example:1</id>
+    <title>Release 1.0</title>
+    <link href="https://example.com/releases/1.0"/>
+    <updated>2026-01-01T00:00:00Z</updated>
+  </entry>
+</feed>"#;
+        let entries = parse_feed(body.as_bytes()).unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].0, "urn:example:1");
+        assert_eq!(entries[0].1.title, "Release 1.0");
+        assert!(parse_feed(b"<html></html>").is_err());
+    }
+
+    #[test]
+    fn generate_slack_payload_with_feed_entries() {
+        let site = Rc::new(Site {
+            uri: Url::parse("https://example.com/feed").unwrap(),
+            uri_open: Some(Url::parse("https://example.com/").unwrap()),
+            seen_guids: Some(vec!["1".into(), "2".into()]),
+            new_entries: (1..=FEED_ENTRIES_MAX + 2)
+                .map(|i| FeedEntry {
+                    title: format!("post {i}"),
+                    link: match i {
+                        1 => None,
+                        _ => Some(Url::parse(&format!("https://example.com/{i}")).unwrap()),
+                    },
+                })
+                .collect(),
+            ..test_site(CheckMethod::Feed)
+        });
+        let payload = generate_slack_payload("bot", "channel", &[site], &[]).unwrap();
+        let payload = serde_json::from_str::<serde_json::Value>(&payload).unwrap();
+        let text = payload["blocks"][1]["text"]["text"].as_str().unwrap();
+        assert!(text.starts_with(
+            "<https://example.com/|Example>\n• post 1\n• <https://example.com/2|post 2>\n"
+        ));
+        assert!(text.ends_with("\n• <https://example.com/10|post 10>\n• and 2 more"));
+    }
+
+    #[test]
+    fn generate_payload_escapes_feed_entries() {
+        let site = Rc::new(Site {
+            uri: Url::parse("https://example.com/feed").unwrap(),
+            seen_guids: Some(vec![]),
+            new_entries: vec![FeedEntry {
+                title: "Q&A <v1.0>".into(),
+                link: Some(Url::parse("https://example.com/wiki/Rust_(language)").unwrap()),
+            }],
+            ..test_site(CheckMethod::Feed)
+        });
+
+        let payload = generate_slack_payload("bot", "channel", &[site.clone()], &[]).unwrap();
+        let payload = serde_json::from_str::<serde_json::Value>(&payload).unwrap();
+        assert_eq!(
+            payload["blocks"][1]["text"]["text"],
+            "<https://example.com/feed|Example>\n• <https://example.com/wiki/Rust_(language)|Q&amp;A &lt;v1.0&gt;>"
+        );
+
+        let payload = generate_telegram_payload("chat", &[site], &[]).unwrap();
+        let payload = serde_json::from_str::<serde_json::Value>(&payload).unwrap();
+        assert_eq!(
+            payload["text"],
+            r#"*Updated*
+[Example](https://example.com/feed)
+• [Q&A <v1\.0\>](https://example.com/wiki/Rust_(language\))"#
+        );
+    }
+
+    #[test]
+    fn truncate_extracted_long_text() {
+        assert_eq!(truncate_extracted("short"), "short");
+        assert_eq!(
+            truncate_extracted(&"あ".repeat(EXTRACTED_TEXT_MAX_CHARS + 1)),
+            format!("{}…", "あ".repeat(EXTRACTED_TEXT_MAX_CHARS))
+        );
+    }
+
+    #[test]
+    fn daemon_wait_ok() {
+        let now = chrono::Utc::now();
+        let state_with = |next_check| State {
+            sites: BTreeMap::from([(
+                "example".to_string(),
+                SiteState {
+                    next_check: Some(next_check),
+                    ..Default::default()
+                },
+            )]),
+        };
+
+        // empty config.
+        assert_eq!(
+            daemon_wait(&State::default(), true, now),
+            DAEMON_POLL_INTERVAL
+        );
+        // broken config after the check has passed.
+        let overdue = state_with(now - chrono::TimeDelta::seconds(10));
+        assert_eq!(daemon_wait(&overdue, false, now), DAEMON_POLL_INTERVAL);
+        assert_eq!(daemon_wait(&overdue, true, now), DAEMON_MIN_WAIT);
+
+        let state = state_with(now + chrono::TimeDelta::seconds(10));
+        assert_eq!(daemon_wait(&state, true, now), Duration::from_secs(10));
+        let state = state_with(now + chrono::TimeDelta::hours(1));
+        assert_eq!(daemon_wait(&state, true, now), DAEMON_POLL_INTERVAL);
+    }
 }
@@ -15,26 +15,39 @@
  */
 
 use clap::builder::ArgPredicate;
-use clap::{Args, Parser};
+use clap::{Args, Parser, Subcommand, ValueEnum, ValueHint};
 use mdns_sd::{IfKind, ServiceEvent};
+use opentelemetry::KeyValue;
+use opentelemetry::metrics::{Counter, Gauge};
 use reqwest::header;
-use rust_myscript::feature::otel::init_otel;
+use rusqlite::{Connection, params};
+use rust_myscript::feature::otel::{init_otel, init_otel_metrics};
 use rust_myscript::prelude::*;
 use serde::{Deserialize, Serialize};
 use serde_json::json;
+use std::collections::BTreeMap;
 use std::fmt::Write;
+use std::future::IntoFuture;
+use std::net::SocketAddr;
 use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
 use std::time::Duration;
 use url::Url;
 
 #[derive(Parser)]
 struct Opt {
+    #[command(subcommand)]
+    command: Option<Command>,
+
     #[command(flatten)]
     hue: Option<OptHue>,
 
     #[command(flatten)]
     remo: Option<OptRemo>,
 
+    #[command(flatten)]
+    sysfs: Option<OptSysfs>,
+
     #[command(flatten)]
     telegram: Option<OptTelegram>,
 
@@ -48,9 +61,122 @@ struct Opt {
     /// OpenTelemetry logs endpoint.
     #[arg(long, env)]
     otel_logs_endpoint: Option<Url>,
+
+    /// OpenTelemetry metrics endpoint.
+    #[arg(long, env)]
+    otel_metrics_endpoint: Option<Url>,
+
+    /// Database to store the readings. Defaults to the user data directory.
+    #[arg(long, env, global = true, value_hint = ValueHint::FilePath)]
+    db_file: Option<PathBuf>,
+
+    /// Alert rule according `FriendlyName:temperature>28` or `FriendlyName:humidity<35` format.
+    /// Telegram gets only the crossed and recovered notifications if specified.
+    #[arg(long, value_parser = parse_alert_rule)]
+    alert: Vec<AlertRule>,
+
+    /// Margin from the threshold to treat the value as recovered.
+    #[arg(long, env, default_value_t = 0.5)]
+    alert_hysteresis: f64,
+
+    /// Minutes to suppress the next crossed notification of the same rule.
+    #[arg(long, env, default_value_t = 60)]
+    alert_cooldown_mins: u64,
+
+    /// File to keep the alert states between runs. Defaults to the user data directory.
+    #[arg(long, env, value_hint = ValueHint::FilePath)]
+    alert_state_file: Option<PathBuf>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Print min/max/avg of the stored readings per sensor.
+    Report(OptReport),
+
+    /// Poll the sources on an interval and expose the readings at `/metrics`.
+    Serve(OptServe),
+
+    /// Utilities for the Hue Bridge.
+    #[command(subcommand)]
+    Hue(HueCommand),
+}
+
+#[derive(Subcommand)]
+enum HueCommand {
+    /// Find the bridge, pair with it and list the temperature-capable devices.
+    Setup(OptHueSetup),
 }
 
 #[derive(Args)]
+struct OptHueSetup {
+    /// Address of the bridge. Find it via mDNS if omitted.
+    #[arg(long)]
+    bridge: Option<std::net::IpAddr>,
+
+    /// Existing Application Key. Run the link-button pairing if omitted.
+    #[arg(long)]
+    app_key: Option<String>,
+
+    /// `devicetype` to register the application key.
+    #[arg(long, default_value = concat!(env!("CARGO_PKG_NAME"), "#setup"))]
+    device_type: String,
+
+    /// Timeout to find the bridge and to wait for the link button.
+    #[arg(long, default_value = "60")]
+    timeout_secs: u64,
+}
+
+#[derive(Args)]
+struct OptServe {
+    /// Address to listen for the OpenMetrics endpoint.
+    #[arg(long, env, default_value = "127.0.0.1:9184")]
+    listen: SocketAddr,
+
+    /// Interval to poll the sources.
+    #[arg(long, env, default_value = "60", value_parser = clap::value_parser!(u64).range(1..))]
+    interval_secs: u64,
+}
+
+#[derive(Args)]
+struct OptReport {
+    /// Period to aggregate the readings.
+    #[arg(long, value_enum, default_value_t = ReportPeriod::Day)]
+    period: ReportPeriod,
+
+    /// Aggregate the readings recorded on or after the date (e.g. `2025-07-01`).
+    #[arg(long)]
+    since: Option<chrono::NaiveDate>,
+
+    /// Output format.
+    #[arg(long, value_enum, default_value_t = ReportFormat::Table)]
+    format: ReportFormat,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum ReportPeriod {
+    Day,
+    Week,
+    Month,
+}
+
+impl ReportPeriod {
+    /// Format of `strftime` to group the readings.
+    fn strftime_format(self) -> &'static str {
+        match self {
+            ReportPeriod::Day => "%Y-%m-%d",
+            ReportPeriod::Week => "%Y-W%W",
+            ReportPeriod::Month => "%Y-%m",
+        }
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum ReportFormat {
+    Table,
+    Csv,
+}
+
+#[derive(Args, Clone)]
 struct OptHue {
     // use flag instead of the ArgGroup to use Option and flatten.
     /// Use Hue device.
@@ -77,7 +203,7 @@ struct OptHue {
     timeout_secs: u64,
 }
 
-#[derive(Args)]
+#[derive(Args, Clone)]
 struct OptRemo {
     // use flag instead of the ArgGroup to use Option and flatten.
     /// Use Hue device.
@@ -100,6 +226,29 @@ struct OptRemo {
     remo: Vec<(String, String)>,
 }
 
+#[derive(Args, Clone)]
+struct OptSysfs {
+    // use flag instead of the ArgGroup to use Option and flatten.
+    /// Use Linux hwmon and 1-Wire sensors.
+    #[arg(
+        long,
+        env,
+        requires_ifs = [
+            (ArgPredicate::IsPresent, "sysfs"),
+        ],
+    )]
+    use_sysfs: bool,
+
+    /// Sensor according `ID=FriendlyName` format. ID is `<hwmon name>/temp<N>` (e.g.
+    /// `coretemp/temp1`) for hwmon or the device ID (e.g. `28-0000012345ab`) for DS18B20
+    #[arg(long, value_parser = parse_key_value_arg, requires = "use_sysfs")]
+    sysfs: Vec<(String, String)>,
+
+    /// Root directory of sysfs
+    #[arg(long, env, default_value = "/sys", value_hint = ValueHint::DirPath)]
+    sysfs_root: PathBuf,
+}
+
 #[derive(Args)]
 struct OptTelegram {
     // use flag instead of the ArgGroup to use Option and flatten.
@@ -158,14 +307,77 @@ struct OptDataverse {
     /// Power Platform environment URL (e.g. `https://<org>.crm.dynamics.com`), not the Web API endpoint
     #[arg(long, env, requires = "use_dataverse")]
     dataverse_environment_url: Option<Url>,
+
+    /// Hours to keep the readings that failed to post in the queue.
+    #[arg(long, env, default_value_t = 168)]
+    dataverse_queue_max_age_hours: u64,
+
+    /// Maximum number of the queued readings including the ones of this run. The oldest ones
+    /// are dropped over the limit.
+    #[arg(
+        long,
+        env,
+        default_value_t = 10000,
+        value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..),
+    )]
+    dataverse_queue_max_size: usize,
 }
 
+#[derive(Clone, Debug, PartialEq)]
 struct SensorValue {
     name: String,
     temperature: f64,
     humidity: Option<f64>,
 }
 
+#[derive(Clone, Debug, PartialEq)]
+struct AlertRule {
+    name: String,
+    metric: AlertMetric,
+
+    /// `true` to alert when the value is above the threshold, `false` for below.
+    above: bool,
+
+    threshold: f64,
+}
+
+impl std::fmt::Display for AlertRule {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let metric = match self.metric {
+            AlertMetric::Temperature => "temperature",
+            AlertMetric::Humidity => "humidity",
+        };
+        let op = if self.above { '>' } else { '<' };
+        write!(f, "{}:{metric}{op}{}", self.name, self.threshold)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum AlertMetric {
+    Temperature,
+    Humidity,
+}
+
+#[derive(Debug, Default, Deserialize, PartialEq, Serialize)]
+struct AlertState {
+    alerting: bool,
+    /// unixepoch (secs) of the last crossed notification.
+    crossed_at: Option<u64>,
+}
+
+#[derive(Debug, PartialEq)]
+struct AlertEvent {
+    rule: AlertRule,
+    value: f64,
+    kind: AlertKind,
+}
+
+#[derive(Debug, PartialEq)]
+enum AlertKind {
+    Crossed,
+    Recovered,
+}
+
 #[derive(Serialize, Deserialize)]
 struct CachedToken {
     access_token: String,
@@ -188,16 +400,15 @@ async fn main() -> Fallible<()> {
 
     let client = create_client_builder().build()?;
 
-    let otel_guard = match opt.otel_logs_endpoint {
+    let otel_service_name = if opt.otel_use_old_service_name {
+        "temperature-remo"
+    } else {
+        env!("CARGO_BIN_NAME")
+    };
+
+    let otel_guard = match &opt.otel_logs_endpoint {
         Some(endpoint) => {
-            let guard = init_otel(
-                endpoint,
-                if opt.otel_use_old_service_name {
-                    "temperature-remo"
-                } else {
-                    env!("CARGO_BIN_NAME")
-                },
-            )?;
+            let guard = init_otel(endpoint.clone(), otel_service_name)?;
             Some(guard)
         }
         None => {
@@ -209,24 +420,38 @@ async fn main() -> Fallible<()> {
         }
     };
 
+    let otel_metrics_guard = match &opt.otel_metrics_endpoint {
+        Some(endpoint) => Some(init_otel_metrics(endpoint.clone(), otel_service_name)?),
+        None => None,
+    };
+
+    let db_path = match &opt.db_file {
+        Some(data) => data.clone(),
+        None => default_db_path()?,
+    };
+
+    if let Some(Command::Report(report)) = &opt.command {
+        let store = ReadingStore::create_with_path(&db_path)?;
+        return print_report(&store, report);
+    }
+
+    if let Some(Command::Serve(serve)) = &opt.command {
+        return run_server(&opt, serve, &client, &db_path).await;
+    }
+
+    if let Some(Command::Hue(HueCommand::Setup(setup))) = &opt.command {
+        return setup_hue(setup).await;
+    }
+
     let mut sensor_values: Vec<SensorValue> = vec![];
 
-    if let Some(hue) = opt.hue {
-        match retrieve_hue_temperature(hue).await {
+    if let Some(hue) = &opt.hue {
+        match retrieve_hue_temperature(hue.clone()).await {
             Ok((list, has_error)) => {
                 if has_error {
                     warn!("failed to retrieve some temperatures from hue");
                 }
-                sensor_values.append(
-                    &mut list
-                        .into_iter()
-                        .map(|(name, temperature)| SensorValue {
-                            name,
-                            temperature,
-                            humidity: None,
-                        })
-                        .collect(),
-                );
+                sensor_values.append(&mut temperature_values(list));
             }
             Err(e) => {
                 info!(?e, "failed to retrieve temperature from hue");
@@ -234,67 +459,147 @@ async fn main() -> Fallible<()> {
         };
     }
 
-    if let Some(remo) = opt.remo {
-        let res_devices = client
-            .get("https://api.nature.global/1/devices")
-            .header(header::ACCEPT, "application/json")
-            .bearer_auth(remo.nature_auth_token.expect("--nature-auth-token"))
-            .send()
-            .await?;
-        info!(?res_devices);
-        let res_devices_text = res_devices.text().await?;
-        debug!(res_devices_text);
+    if let Some(remo) = &opt.remo {
+        sensor_values.append(&mut retrieve_remo_values(&client, remo).await?);
+    }
 
-        let devices = serde_json::from_str::<serde_json::Value>(&res_devices_text)?;
-        for (id, friendly_name) in remo.remo {
-            let device = get_device(&devices, &id)?;
+    if let Some(sysfs) = &opt.sysfs {
+        let (list, has_error) = read_sysfs_temperatures(&sysfs.sysfs_root, &sysfs.sysfs);
+        if has_error {
+            warn!("failed to read some temperatures from sysfs");
+        }
+        sensor_values.append(&mut temperature_values(list));
+    }
 
-            let temperature = get_temperature(device)?;
-            let humidity = get_humidity(device)?;
+    ensure!(!sensor_values.is_empty(), "no reports");
 
-            sensor_values.push(SensorValue {
-                name: friendly_name,
-                temperature,
-                humidity,
-            });
+    if otel_metrics_guard.is_some() {
+        let instruments = OtelInstruments::new();
+        for value in &sensor_values {
+            instruments.record(value);
         }
     }
 
-    ensure!(!sensor_values.is_empty(), "no reports");
+    let unixepoch = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)?
+        .as_secs();
+
+    // keep the history even if the other destinations are unreachable.
+    if let Err(e) = ReadingStore::create_with_path(&db_path)
+        .and_then(|store| store.insert(&sensor_values, unixepoch))
+    {
+        warn!(?e, "failed to store the readings");
+    }
+
+    let alert_update = if opt.alert.is_empty() {
+        None
+    } else {
+        // keep posting the readings even if the alert states are broken.
+        match process_alerts(&opt, &sensor_values, unixepoch) {
+            Ok(update) => {
+                for AlertEvent { rule, value, kind } in &update.events {
+                    info!(%rule, value, ?kind, "alert");
+                }
+                Some(update)
+            }
+            Err(e) => {
+                warn!(?e, "failed to process the alerts; skip alerting");
+                None
+            }
+        }
+    };
+    let alert_events = match &alert_update {
+        _ if opt.alert.is_empty() => None,
+        Some(update) => Some(update.events.as_slice()),
+        None => Some(&[][..]),
+    };
 
+    let mut alerts_notified = false;
     if let Some(telegram) = opt.telegram {
-        info!("notify to telegram");
-        let ret_telegram = client
-            .post(format!(
-                "https://api.telegram.org/bot{}/sendMessage",
-                telegram
-                    .telegram_bot_token
-                    .expect("telegram_bot_token should not be None"),
-            ))
-            .header(header::ACCEPT, "application/json")
-            .header(header::CONTENT_TYPE, "application/json")
-            .body(generate_telegram_payload(
-                &telegram
-                    .telegram_chat_id
-                    .expect("telegram_chat_id should not be None"),
-                &telegram
-                    .telegram_text_template
-                    .expect("telegram_text_template should not be None"),
+        let chat_id = telegram
+            .telegram_chat_id
+            .expect("telegram_chat_id should not be None");
+        let text_template = telegram
+            .telegram_text_template
+            .expect("telegram_text_template should not be None");
+        let payload = match alert_events {
+            Some([]) => None,
+            Some(events) => Some(generate_telegram_alert_payload(
+                &chat_id,
+                &text_template,
+                events,
+            )?),
+            None => Some(generate_telegram_payload(
+                &chat_id,
+                &text_template,
                 &sensor_values,
-            )?)
-            .send()
-            .await?;
-        info!(?ret_telegram);
-        debug!(ret_telegram_text = %ret_telegram.text().await?);
+            )?),
+        };
+
+        match payload {
+            Some(payload) => {
+                info!("notify to telegram");
+                let ret_telegram = client
+                    .post(format!(
+                        "https://api.telegram.org/bot{}/sendMessage",
+                        telegram
+                            .telegram_bot_token
+                            .expect("telegram_bot_token should not be None"),
+                    ))
+                    .header(header::ACCEPT, "application/json")
+                    .header(header::CONTENT_TYPE, "application/json")
+                    .body(payload)
+                    .send()
+                    .await?;
+                info!(?ret_telegram);
+                let status = ret_telegram.status();
+                debug!(ret_telegram_text = %ret_telegram.text().await?);
+                if status.is_success() {
+                    alerts_notified = true;
+                } else {
+                    warn!(%status, "failed to notify to telegram");
+                }
+            }
+            None => info!("no alerts to notify to telegram"),
+        }
+    }
+
+    // save the states after notifying not to lose the events while telegram is unreachable.
+    if let Some(AlertUpdate {
+        events,
+        state_path,
+        states,
+    }) = &alert_update
+    {
+        if events.is_empty() || alerts_notified {
+            if let Err(e) = save_alert_states(state_path, states) {
+                warn!(?e, "failed to save the alert states");
+            }
+        } else {
+            warn!("the alerts are not notified; retry them on the next run");
+        }
     }
 
-    if let Some(dataverse) = opt.dataverse {
+    let mut dataverse_flush = None;
+    if let Some(dataverse) = &opt.dataverse {
         info!("post to dataverse");
-        let unixepoch = std::time::SystemTime::now()
-            .duration_since(std::time::UNIX_EPOCH)?
-            .as_secs();
-        if let Err(e) = post_to_dataverse(&client, dataverse, &sensor_values, unixepoch).await {
-            warn!(?e, "failed to post to dataverse");
+        let store = match ReadingStore::create_with_path(&db_path) {
+            Ok(data) => data,
+            Err(e) => {
+                warn!(?e, "failed to open the queue; readings are lost on failure");
+                ReadingStore::create_with_conn(Connection::open_in_memory()?)?
+            }
+        };
+        match post_to_dataverse(&client, dataverse, &store, &sensor_values, unixepoch).await {
+            Ok(data) => {
+                info!(
+                    flushed = data.flushed,
+                    pending = data.pending,
+                    "posted to dataverse",
+                );
+                dataverse_flush = Some(data);
+            }
+            Err(e) => warn!(?e, "failed to post to dataverse"),
         }
     }
 
@@ -323,142 +628,994 @@ async fn main() -> Fallible<()> {
         }
     }
 
+    if let Some(DataverseFlush { flushed, pending }) = dataverse_flush {
+        println!("dataverse queue:\n  flushed: {flushed}\n  pending: {pending}");
+    }
+
     Ok(())
 }
 
-fn create_client_builder() -> reqwest::ClientBuilder {
-    reqwest::Client::builder().user_agent(concat!(
-        env!("CARGO_PKG_NAME"),
-        " (https://github.com/sukawasatoru/rust-myscript/)",
-    ))
+fn default_db_path() -> Fallible<PathBuf> {
+    let dirs = directories::ProjectDirs::from("com", "sukawasatoru", "temperature-sensor")
+        .context("failed to retrieve project directories")?;
+    Ok(dirs.data_dir().join("readings.db"))
 }
 
-#[tracing::instrument(skip_all)]
-async fn retrieve_hue_temperature(hue: OptHue) -> Fallible<(Vec<(String, f64)>, bool)> {
-    let mdns = mdns_sd::ServiceDaemon::new().context("failed to create mdns daemon")?;
-    let receiver = mdns.browse("_hue._tcp.local.")?;
-
-    mdns.disable_interface(IfKind::IPv6)?;
-    let client = create_client_builder()
-        .danger_accept_invalid_certs(true)
-        .build()?;
-    let (tx, mut rx) = tokio::sync::mpsc::channel(1);
-    let mut devices = hue.hue.clone();
+/// Time series of the readings.
+struct ReadingStore {
+    conn: Connection,
+}
 
-    let handle = tokio::task::spawn(tokio::time::timeout(
-        Duration::from_secs(hue.timeout_secs),
-        async move {
-            while let Ok(event) = receiver.recv_async().await {
-                let service_info = match event {
-                    ServiceEvent::ServiceResolved(service_info) => {
-                        info!(?service_info, "ServiceResolved");
-                        service_info
-                    }
-                    event => {
-                        debug!(?event);
-                        continue;
-                    }
-                };
+impl ReadingStore {
+    fn create_with_path(db_path: &Path) -> Fallible<Self> {
+        if let Some(parent) = db_path.parent() {
+            std::fs::create_dir_all(parent)
+                .with_context(|| format!("failed to create {}", parent.display()))?;
+        }
+        Self::create_with_conn(Connection::open(db_path)?)
+    }
 
-                let addresses = service_info.get_addresses_v4();
-                let address = match addresses.iter().next() {
-                    Some(data) => data,
-                    None => continue,
-                };
-                let res = client
-                    .get(format!("https://{address}/clip/v2/resource/temperature"))
-                    .header(
-                        "hue-application-key",
-                        hue.hue_app_key.as_ref().expect("--hue-app-key"),
-                    )
-                    .send()
-                    .await;
-                let res_body = match res {
-                    Ok(data) => match data.text().await {
-                        Ok(data) => data,
-                        Err(e) => {
-                            info!(?e, "failed to retrieve response");
-                            continue;
-                        }
-                    },
-                    Err(e) => {
-                        info!(?e, "failed to sending request");
-                        continue;
-                    }
-                };
+    fn create_with_conn(conn: Connection) -> Fallible<Self> {
+        let db_version = conn.query_row("pragma user_version", [], |row| row.get::<_, i32>(0))?;
+        const CREATE_DATAVERSE_QUEUE: &str = "create table dataverse_queue (id integer primary key not null, recorded_at integer not null, name text not null, temperature real not null, humidity real)";
 
-                debug!(res_body);
-                let res_json = match serde_json::from_str::<serde_json::Value>(&res_body) {
-                    Ok(data) => data,
-                    Err(e) => {
-                        info!(?e, "failed to parse response");
-                        continue;
-                    }
-                };
+        match db_version {
+            0 => {
+                conn.execute_batch(&format!(
+                    "create table reading (id integer primary key not null, recorded_at integer not null, name text not null, temperature real not null, humidity real);
+create index index_reading_recorded_at on reading (recorded_at);
+{CREATE_DATAVERSE_QUEUE}",
+                ))?;
 
-                /// https://developers.meethue.com/develop/hue-api-v2/api-reference/#resource_temperature
-                fn parse_res_json(res: &serde_json::Value) -> Fallible<Vec<(String, f64)>> {
-                    let data = res["data"].as_array().context(".data.as_array()")?;
-                    let mut list = Vec::with_capacity(data.len());
-                    for entry in data {
-                        let id = entry["id"]
-                            .as_str()
-                            .map(ToOwned::to_owned)
-                            .context(".data[].id")?;
-                        let temperature = entry["temperature"]["temperature_report"]["temperature"]
-                            .as_f64()
-                            .context(".data[].temperature.temperature_report.temperature")?;
-                        list.push((id, temperature));
-                    }
-                    Ok(list)
-                }
-                let temperatures = match parse_res_json(&res_json) {
-                    Ok(data) => data,
-                    Err(e) => {
-                        info!(?e, "failed to collect temperature");
-                        continue;
-                    }
-                };
+                conn.execute("pragma user_version = 2", ())?;
+            }
+            1 => {
+                conn.execute_batch(CREATE_DATAVERSE_QUEUE)?;
 
-                for (id, temperature) in temperatures {
-                    let index = match devices.iter().position(|(data, _)| &id == data) {
-                        Some(data) => data,
-                        None => continue,
-                    };
-                    let (_, friendly_name) = devices.swap_remove(index);
+                conn.execute("pragma user_version = 2", ())?;
+            }
+            2 => (),
+            _ => bail!("unsupported db version: {db_version}"),
+        }
 
-                    if tx.send((friendly_name, temperature)).await.is_err() {
-                        return;
-                    }
+        Ok(Self { conn })
+    }
 
-                    if devices.is_empty() {
-                        return;
-                    }
-                }
-            }
-        },
-    ));
+    fn insert(&self, values: &[SensorValue], unixepoch: u64) -> Fallible<()> {
+        let mut stmt = self.conn.prepare(
+            "insert into reading (recorded_at, name, temperature, humidity) values (?1, ?2, ?3, ?4)",
+        )?;
+        for value in values {
+            stmt.execute(params![
+                i64::try_from(unixepoch)?,
+                value.name,
+                value.temperature,
+                value.humidity,
+            ])?;
+        }
+        Ok(())
+    }
 
-    let mut result = vec![];
-    while let Some(data) = rx.recv().await {
-        result.push(data);
+    /// Appends the readings to post to Dataverse and returns the ID of the first one.
+    fn enqueue_dataverse(&self, values: &[SensorValue], unixepoch: u64) -> Fallible<i64> {
+        let mut stmt = self.conn.prepare(
+            "insert into dataverse_queue (recorded_at, name, temperature, humidity) values (?1, ?2, ?3, ?4)",
+        )?;
+        let mut first_id = None;
+        for value in values {
+            stmt.execute(params![
+                i64::try_from(unixepoch)?,
+                value.name,
+                value.temperature,
+                value.humidity,
+            ])?;
+            first_id.get_or_insert(self.conn.last_insert_rowid());
+        }
+        first_id.context("no readings to enqueue")
     }
 
-    match handle.await {
-        Ok(Ok(())) => (),
-        Ok(Err(e)) => info!(?e, "timed out to retrieve hue device info"),
-        Err(e) => info!(?e, "failed to join the task"),
+    /// Returns the queued readings in the order of the insertion.
+    fn dataverse_queue(&self) -> Fallible<Vec<QueuedReading>> {
+        let mut stmt = self.conn.prepare(
+            "select id, recorded_at, name, temperature, humidity from dataverse_queue order by id",
+        )?;
+        let rows = stmt.query_map([], |row| {
+            Ok(QueuedReading {
+                id: row.get(0)?,
+                recorded_at: row.get(1)?,
+                value: SensorValue {
+                    name: row.get(2)?,
+                    temperature: row.get(3)?,
+                    humidity: row.get(4)?,
+                },
+            })
+        })?;
+        Ok(rows.collect::<Result<_, _>>()?)
     }
 
-    let has_error = hue.hue.len() != result.len();
+    fn remove_dataverse_queue(&self, id: i64) -> Fallible<()> {
+        self.conn
+            .execute("delete from dataverse_queue where id = ?1", [id])?;
+        Ok(())
+    }
 
-    while let Err(mdns_sd::Error::Again) = mdns.shutdown() {
-        debug!("retry shutting down");
+    /// Drops the readings recorded before `min_recorded_at` and the oldest ones over `max_size`.
+    ///
+    /// Returns the number of the dropped readings.
+    fn prune_dataverse_queue(&self, min_recorded_at: u64, max_size: usize) -> Fallible<usize> {
+        let expired = self.conn.execute(
+            "delete from dataverse_queue where recorded_at < ?1",
+            [i64::try_from(min_recorded_at)?],
+        )?;
+        let overflowed = self.conn.execute(
+            "delete from dataverse_queue where id not in (select id from dataverse_queue order by id desc limit ?1)",
+            [i64::try_from(max_size)?],
+        )?;
+        Ok(expired + overflowed)
+    }
+
+    /// Aggregates the readings recorded on or after `since` per sensor per period.
+    ///
+    /// `utc_offset_secs` is the offset of the local time to split the periods.
+    fn summarize(
+        &self,
+        period: ReportPeriod,
+        since: i64,
+        utc_offset_secs: i32,
+    ) -> Fallible<Vec<ReportRow>> {
+        let mut stmt = self.conn.prepare(
+            "select strftime(?1, recorded_at + ?2, 'unixepoch') as period, name, count(*),
+  min(temperature), max(temperature), avg(temperature),
+  min(humidity), max(humidity), avg(humidity)
+from reading
+where ?3 <= recorded_at
+group by period, name
+order by period, name",
+        )?;
+        let rows = stmt
+            .query_map(
+                params![period.strftime_format(), utc_offset_secs, since],
+                |row| {
+                    Ok(ReportRow {
+                        period: row.get(0)?,
+                        name: row.get(1)?,
+                        count: row.get(2)?,
+                        temperature: (row.get(3)?, row.get(4)?, row.get(5)?),
+                        humidity: match (row.get(6)?, row.get(7)?, row.get(8)?) {
+                            (Some(min), Some(max), Some(avg)) => Some((min, max, avg)),
+                            _ => None,
+                        },
+                    })
+                },
+            )?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(rows)
+    }
+}
+
+/// Statistics of a sensor in a period.
+#[derive(Debug, PartialEq)]
+struct ReportRow {
+    period: String,
+    name: String,
+    count: i64,
+
+    /// min, max and avg.
+    temperature: (f64, f64, f64),
+
+    /// min, max and avg.
+    humidity: Option<(f64, f64, f64)>,
+}
+
+fn print_report(store: &ReadingStore, report: &OptReport) -> Fallible<()> {
+    let utc_offset_secs = chrono::Local::now().offset().local_minus_utc();
+    let since = match report.since {
+        Some(data) => {
+            data.and_time(chrono::NaiveTime::MIN).and_utc().timestamp() - i64::from(utc_offset_secs)
+        }
+        None => 0,
+    };
+
+    let rows = store.summarize(report.period, since, utc_offset_secs)?;
+    match report.format {
+        ReportFormat::Table => print!("{}", format_report_table(&rows)),
+        ReportFormat::Csv => print!("{}", format_report_csv(&rows)),
+    }
+    Ok(())
+}
+
+fn format_report_table(rows: &[ReportRow]) -> String {
+    let name_width = rows
+        .iter()
+        .map(|data| data.name.chars().count())
+        .chain([4])
+        .max()
+        .unwrap_or_default();
+
+    let mut text = format!(
+        "{:<10}  {:<name_width$}  {:>5}  {:<25}  {}\n",
+        "period", "name", "count", "temperature min/max/avg", "humidity min/max/avg",
+    );
+    for row in rows {
+        let (min, max, avg) = row.temperature;
+        let temperature = format!("{min:.1} / {max:.1} / {avg:.1}");
+        let humidity = match row.humidity {
+            Some((min, max, avg)) => format!("{min:.1} / {max:.1} / {avg:.1}"),
+            None => "N/A".into(),
+        };
+        // pad by chars since the names may contain multibyte characters.
+        let name_padding = " ".repeat(name_width - row.name.chars().count());
+        text.push_str(&format!(
+            "{:<10}  {}{name_padding}  {:>5}  {temperature:<25}  {humidity}\n",
+            row.period, row.name, row.count,
+        ));
+    }
+    text
+}
+
+fn format_report_csv(rows: &[ReportRow]) -> String {
+    let mut text = String::from(
+        "period,name,count,temperature_min,temperature_max,temperature_avg,humidity_min,humidity_max,humidity_avg\n",
+    );
+    for row in rows {
+        let (min, max, avg) = row.temperature;
+        let humidity = match row.humidity {
+            Some((min, max, avg)) => format!("{min},{max},{avg:.2}"),
+            None => ",,".into(),
+        };
+        text.push_str(&format!(
+            "{},{},{},{min},{max},{avg:.2},{humidity}\n",
+            row.period,
+            escape_csv(&row.name),
+            row.count,
+        ));
+    }
+    text
+}
+
+fn escape_csv(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+const OPENMETRICS_CONTENT_TYPE: &str = "application/openmetrics-text; version=1.0.0; charset=utf-8";
+
+/// Latest readings and the status of the sources for `/metrics`.
+#[derive(Default)]
+struct Metrics {
+    /// Keyed by the device name.
+    values: BTreeMap<String, SensorValue>,
+
+    /// Keyed by the source name.
+    sources: BTreeMap<&'static str, SourceMetrics>,
+}
+
+#[derive(Debug, Default, PartialEq)]
+struct SourceMetrics {
+    /// unixepoch (secs) of the last poll without errors.
+    last_success: Option<u64>,
+    errors: u64,
+}
+
+impl Metrics {
+    /// Renders the metrics in the OpenMetrics text format.
+    fn render(&self) -> String {
+        let mut text = String::new();
+        text.push_str(
+            "# TYPE temperature_sensor_temperature_celsius gauge
+# UNIT temperature_sensor_temperature_celsius celsius
+# HELP temperature_sensor_temperature_celsius Temperature of the device.
+",
+        );
+        for (name, value) in &self.values {
+            text.push_str(&format!(
+                "temperature_sensor_temperature_celsius{{device=\"{}\"}} {}\n",
+                escape_label_value(name),
+                value.temperature,
+            ));
+        }
+
+        text.push_str(
+            "# TYPE temperature_sensor_humidity_percent gauge
+# UNIT temperature_sensor_humidity_percent percent
+# HELP temperature_sensor_humidity_percent Humidity of the device.
+",
+        );
+        for (name, value) in &self.values {
+            if let Some(humidity) = value.humidity {
+                text.push_str(&format!(
+                    "temperature_sensor_humidity_percent{{device=\"{}\"}} {humidity}\n",
+                    escape_label_value(name),
+                ));
+            }
+        }
+
+        text.push_str(
+            "# TYPE temperature_sensor_last_success_timestamp_seconds gauge
+# UNIT temperature_sensor_last_success_timestamp_seconds seconds
+# HELP temperature_sensor_last_success_timestamp_seconds Time of the last poll without errors.
+",
+        );
+        for (source, metrics) in &self.sources {
+            if let Some(last_success) = metrics.last_success {
+                text.push_str(&format!(
+                    "temperature_sensor_last_success_timestamp_seconds{{source=\"{source}\"}} {last_success}\n",
+                ));
+            }
+        }
+
+        text.push_str(
+            "# TYPE temperature_sensor_errors counter
+# HELP temperature_sensor_errors Failures to retrieve the values from the source.
+",
+        );
+        for (source, metrics) in &self.sources {
+            text.push_str(&format!(
+                "temperature_sensor_errors_total{{source=\"{source}\"}} {}\n",
+                metrics.errors,
+            ));
+        }
+
+        text.push_str("# EOF\n");
+        text
+    }
+}
+
+fn escape_label_value(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+/// Instruments to export the readings as the OpenTelemetry metrics.
+struct OtelInstruments {
+    temperature: Gauge<f64>,
+    humidity: Gauge<f64>,
+    errors: Counter<u64>,
+}
+
+impl OtelInstruments {
+    fn new() -> Self {
+        let meter = opentelemetry::global::meter(env!("CARGO_BIN_NAME"));
+        Self {
+            temperature: meter
+                .f64_gauge("device.temperature")
+                .with_unit("Cel")
+                .with_description("Temperature of the device")
+                .build(),
+            humidity: meter
+                .f64_gauge("device.humidity")
+                .with_unit("%")
+                .with_description("Humidity of the device")
+                .build(),
+            errors: meter
+                .u64_counter("source.errors")
+                .with_description("Failures to retrieve the values from the source")
+                .build(),
+        }
+    }
+
+    fn record(&self, value: &SensorValue) {
+        let attributes = [KeyValue::new("device.name", value.name.clone())];
+        self.temperature.record(value.temperature, &attributes);
+        if let Some(humidity) = value.humidity {
+            self.humidity.record(humidity, &attributes);
+        }
+    }
+}
+
+async fn run_server(
+    opt: &Opt,
+    serve: &OptServe,
+    client: &reqwest::Client,
+    db_path: &Path,
+) -> Fallible<()> {
+    ensure!(
+        opt.hue.is_some() || opt.remo.is_some() || opt.sysfs.is_some(),
+        "no sources",
+    );
+
+    // keep exporting the metrics even if the history is unavailable.
+    let store = match ReadingStore::create_with_path(db_path) {
+        Ok(data) => Some(data),
+        Err(e) => {
+            warn!(?e, "failed to open the database; readings are not stored");
+            None
+        }
+    };
+
+    let metrics = Arc::new(Mutex::new(Metrics::default()));
+    let instruments = OtelInstruments::new();
+
+    let router = axum::Router::new().route(
+        "/metrics",
+        axum::routing::get({
+            let metrics = metrics.clone();
+            move || {
+                let body = metrics.lock().unwrap().render();
+                async move { ([(header::CONTENT_TYPE, OPENMETRICS_CONTENT_TYPE)], body) }
+            }
+        }),
+    );
+    let listener = tokio::net::TcpListener::bind(serve.listen)
+        .await
+        .with_context(|| format!("failed to listen {}", serve.listen))?;
+    info!(listen = %serve.listen, "serve metrics");
+
+    let server = axum::serve(listener, router).with_graceful_shutdown(async {
+        if let Err(e) = tokio::signal::ctrl_c().await {
+            warn!(?e, "failed to listen ctrl-c");
+        }
+    });
+
+    let poll = async {
+        let mut interval = tokio::time::interval(Duration::from_secs(serve.interval_secs));
+        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        loop {
+            interval.tick().await;
+            poll_sources(opt, client, store.as_ref(), &metrics, &instruments).await;
+        }
+    };
+
+    tokio::select! {
+        ret = server.into_future() => ret?,
+        _ = poll => (),
+    }
+
+    Ok(())
+}
+
+/// Retrieves the values from the sources, updates the metrics and stores the values to `store`.
+async fn poll_sources(
+    opt: &Opt,
+    client: &reqwest::Client,
+    store: Option<&ReadingStore>,
+    metrics: &Mutex<Metrics>,
+    instruments: &OtelInstruments,
+) {
+    // (source, (values, has_error))
+    let mut results = Vec::<(&'static str, Fallible<(Vec<SensorValue>, bool)>)>::new();
+    if let Some(hue) = &opt.hue {
+        let ret = retrieve_hue_temperature(hue.clone())
+            .await
+            .map(|(list, has_error)| (temperature_values(list), has_error));
+        results.push(("hue", ret));
+    }
+    if let Some(remo) = &opt.remo {
+        let ret = retrieve_remo_values(client, remo)
+            .await
+            .map(|data| (data, false));
+        results.push(("remo", ret));
+    }
+    if let Some(sysfs) = &opt.sysfs {
+        let (list, has_error) = read_sysfs_temperatures(&sysfs.sysfs_root, &sysfs.sysfs);
+        results.push(("sysfs", Ok((temperature_values(list), has_error))));
+    }
+
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    let mut readings = vec![];
+    let mut metrics = metrics.lock().unwrap();
+    let metrics = &mut *metrics;
+    // don't export the devices that stopped reporting.
+    metrics.values.clear();
+    for (source, ret) in results {
+        let source_metrics = metrics.sources.entry(source).or_default();
+        let values = match ret {
+            Ok((values, false)) => {
+                source_metrics.last_success = Some(now);
+                values
+            }
+            Ok((values, true)) => {
+                warn!(source, "failed to retrieve some values");
+                source_metrics.errors += 1;
+                instruments
+                    .errors
+                    .add(1, &[KeyValue::new("source", source)]);
+                values
+            }
+            Err(e) => {
+                warn!(?e, source, "failed to retrieve values");
+                source_metrics.errors += 1;
+                instruments
+                    .errors
+                    .add(1, &[KeyValue::new("source", source)]);
+                continue;
+            }
+        };
+
+        for value in values {
+            instruments.record(&value);
+            readings.push(value.clone());
+            metrics.values.insert(value.name.clone(), value);
+        }
+    }
+
+    if let Some(store) = store
+        && !readings.is_empty()
+        && let Err(e) = store.insert(&readings, now)
+    {
+        warn!(?e, "failed to store the readings");
+    }
+}
+
+fn temperature_values(list: Vec<(String, f64)>) -> Vec<SensorValue> {
+    list.into_iter()
+        .map(|(name, temperature)| SensorValue {
+            name,
+            temperature,
+            humidity: None,
+        })
+        .collect()
+}
+
+fn create_client_builder() -> reqwest::ClientBuilder {
+    reqwest::Client::builder().user_agent(concat!(
+        env!("CARGO_PKG_NAME"),
+        " (https://github.com/sukawasatoru/rust-myscript/)",
+    ))
+}
+
+#[tracing::instrument(skip_all)]
+async fn retrieve_hue_temperature(hue: OptHue) -> Fallible<(Vec<(String, f64)>, bool)> {
+    let mdns = mdns_sd::ServiceDaemon::new().context("failed to create mdns daemon")?;
+    let receiver = mdns.browse("_hue._tcp.local.")?;
+
+    mdns.disable_interface(IfKind::IPv6)?;
+    let client = create_client_builder()
+        .danger_accept_invalid_certs(true)
+        .build()?;
+    let (tx, mut rx) = tokio::sync::mpsc::channel(1);
+    let mut devices = hue.hue.clone();
+
+    let handle = tokio::task::spawn(tokio::time::timeout(
+        Duration::from_secs(hue.timeout_secs),
+        async move {
+            while let Ok(event) = receiver.recv_async().await {
+                let service_info = match event {
+                    ServiceEvent::ServiceResolved(service_info) => {
+                        info!(?service_info, "ServiceResolved");
+                        service_info
+                    }
+                    event => {
+                        debug!(?event);
+                        continue;
+                    }
+                };
+
+                let addresses = service_info.get_addresses_v4();
+                let address = match addresses.iter().next() {
+                    Some(data) => data,
+                    None => continue,
+                };
+                let res = client
+                    .get(format!("https://{address}/clip/v2/resource/temperature"))
+                    .header(
+                        "hue-application-key",
+                        hue.hue_app_key.as_ref().expect("--hue-app-key"),
+                    )
+                    .send()
+                    .await;
+                let res_body = match res {
+                    Ok(data) => match data.text().await {
+                        Ok(data) => data,
+                        Err(e) => {
+                            info!(?e, "failed to retrieve response");
+                            continue;
+                        }
+                    },
+                    Err(e) => {
+                        info!(?e, "failed to sending request");
+                        continue;
+                    }
+                };
+
+                debug!(res_body);
+                let res_json = match serde_json::from_str::<serde_json::Value>(&res_body) {
+                    Ok(data) => data,
+                    Err(e) => {
+                        info!(?e, "failed to parse response");
+                        continue;
+                    }
+                };
+
+                /// https://developers.meethue.com/develop/hue-api-v2/api-reference/#resource_temperature
+                fn parse_res_json(res: &serde_json::Value) -> Fallible<Vec<(String, f64)>> {
+                    let data = res["data"].as_array().context(".data.as_array()")?;
+                    let mut list = Vec::with_capacity(data.len());
+                    for entry in data {
+                        let id = entry["id"]
+                            .as_str()
+                            .map(ToOwned::to_owned)
+                            .context(".data[].id")?;
+                        let temperature = entry["temperature"]["temperature_report"]["temperature"]
+                            .as_f64()
+                            .context(".data[].temperature.temperature_report.temperature")?;
+                        list.push((id, temperature));
+                    }
+                    Ok(list)
+                }
+                let temperatures = match parse_res_json(&res_json) {
+                    Ok(data) => data,
+                    Err(e) => {
+                        info!(?e, "failed to collect temperature");
+                        continue;
+                    }
+                };
+
+                for (id, temperature) in temperatures {
+                    let index = match devices.iter().position(|(data, _)| &id == data) {
+                        Some(data) => data,
+                        None => continue,
+                    };
+                    let (_, friendly_name) = devices.swap_remove(index);
+
+                    if tx.send((friendly_name, temperature)).await.is_err() {
+                        return;
+                    }
+
+                    if devices.is_empty() {
+                        return;
+                    }
+                }
+            }
+        },
+    ));
+
+    let mut result = vec![];
+    while let Some(data) = rx.recv().await {
+        result.push(data);
+    }
+
+    match handle.await {
+        Ok(Ok(())) => (),
+        Ok(Err(e)) => info!(?e, "timed out to retrieve hue device info"),
+        Err(e) => info!(?e, "failed to join the task"),
+    }
+
+    let has_error = hue.hue.len() != result.len();
+
+    while let Err(mdns_sd::Error::Again) = mdns.shutdown() {
+        debug!("retry shutting down");
     }
 
     Ok((result, has_error))
 }
 
+const HUE_PAIRING_POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+async fn setup_hue(setup: &OptHueSetup) -> Fallible<()> {
+    let timeout = Duration::from_secs(setup.timeout_secs);
+    let bridge = match setup.bridge {
+        Some(data) => data.to_string(),
+        None => discover_hue_bridge(timeout).await?,
+    };
+    println!("bridge: {bridge}");
+
+    let client = create_client_builder()
+        .danger_accept_invalid_certs(true)
+        .build()?;
+    let base_url = format!("https://{bridge}");
+
+    let app_key = match &setup.app_key {
+        Some(data) => data.clone(),
+        None => {
+            println!("press the link button on the bridge");
+            let key = tokio::time::timeout(
+                timeout,
+                pair_hue_bridge(
+                    &client,
+                    &base_url,
+                    &setup.device_type,
+                    HUE_PAIRING_POLL_INTERVAL,
+                ),
+            )
+            .await
+            .context("timed out to wait for the link button")??;
+            if let Some(client_key) = &key.client_key {
+                println!("client key: {client_key}");
+            }
+            key.app_key
+        }
+    };
+
+    let devices = retrieve_hue_temperature_devices(&client, &base_url, &app_key).await?;
+
+    println!();
+    println!("--use-hue --hue-app-key='{app_key}'");
+    for (id, name) in devices {
+        println!("--hue='{id}={}'", name.replace('\'', "'\\''"));
+    }
+
+    Ok(())
+}
+
+/// Returns the IPv4 address of the first bridge found via mDNS.
+async fn discover_hue_bridge(timeout: Duration) -> Fallible<String> {
+    let mdns = mdns_sd::ServiceDaemon::new().context("failed to create mdns daemon")?;
+    let receiver = mdns.browse("_hue._tcp.local.")?;
+    mdns.disable_interface(IfKind::IPv6)?;
+
+    let ret = tokio::time::timeout(timeout, async {
+        while let Ok(event) = receiver.recv_async().await {
+            match event {
+                ServiceEvent::ServiceResolved(service_info) => {
+                    info!(?service_info, "ServiceResolved");
+                    if let Some(address) = service_info.get_addresses_v4().iter().next() {
+                        return Some(address.to_string());
+                    }
+                }
+                event => debug!(?event),
+            }
+        }
+        None
+    })
+    .await;
+
+    while let Err(mdns_sd::Error::Again) = mdns.shutdown() {
+        debug!("retry shutting down");
+    }
+
+    match ret {
+        Ok(Some(data)) => Ok(data),
+        Ok(None) => bail!("mdns daemon stopped before finding the bridge"),
+        Err(_) => bail!("no bridge found"),
+    }
+}
+
+#[derive(Debug, PartialEq)]
+struct HueKey {
+    app_key: String,
+    client_key: Option<String>,
+}
+
+/// Requests the application key until the link button is pressed.
+async fn pair_hue_bridge(
+    client: &reqwest::Client,
+    base_url: &str,
+    device_type: &str,
+    poll_interval: Duration,
+) -> Fallible<HueKey> {
+    let body = serde_json::json!({
+        "devicetype": device_type,
+        "generateclientkey": true,
+    });
+    loop {
+        let res = client
+            .post(format!("{base_url}/api"))
+            .json(&body)
+            .send()
+            .await
+            .context("failed to request the application key")?;
+        let res_json = res.json::<serde_json::Value>().await?;
+        debug!(?res_json);
+
+        if let Some(key) = parse_hue_pairing_response(&res_json)? {
+            return Ok(key);
+        }
+        tokio::time::sleep(poll_interval).await;
+    }
+}
+
+/// Returns `None` while the link button is not pressed.
+///
+/// https://developers.meethue.com/develop/hue-api-v2/getting-started/
+fn parse_hue_pairing_response(res: &serde_json::Value) -> Fallible<Option<HueKey>> {
+    /// link button not pressed.
+    const ERROR_TYPE_LINK_BUTTON: u64 = 101;
+
+    let entry = res
+        .as_array()
+        .and_then(|data| data.first())
+        .context("unexpected response")?;
+
+    if let Some(error) = entry.get("error") {
+        if error["type"].as_u64() == Some(ERROR_TYPE_LINK_BUTTON) {
+            return Ok(None);
+        }
+        bail!("failed to pair: {}", error["description"]);
+    }
+
+    let success = entry.get("success").context("[0].success")?;
+    Ok(Some(HueKey {
+        app_key: success["username"]
+            .as_str()
+            .map(ToOwned::to_owned)
+            .context("[0].success.username")?,
+        client_key: success["clientkey"].as_str().map(ToOwned::to_owned),
+    }))
+}
+
+async fn retrieve_hue_temperature_devices(
+    client: &reqwest::Client,
+    base_url: &str,
+    app_key: &str,
+) -> Fallible<Vec<(String, String)>> {
+    let mut resources = Vec::with_capacity(2);
+    for resource in ["device", "temperature"] {
+        let res = client
+            .get(format!("{base_url}/clip/v2/resource/{resource}"))
+            .header("hue-application-key", app_key)
+            .send()
+            .await?
+            .error_for_status()
+            .with_context(|| format!("failed to retrieve {resource}"))?;
+        resources.push(res.json::<serde_json::Value>().await?);
+    }
+
+    collect_hue_temperature_devices(&resources[0], &resources[1])
+}
+
+/// Returns the `(ID, Name)` of the temperature resources with the name of the owner device.
+///
+/// https://developers.meethue.com/develop/hue-api-v2/api-reference/#resource_device
+fn collect_hue_temperature_devices(
+    devices: &serde_json::Value,
+    temperatures: &serde_json::Value,
+) -> Fallible<Vec<(String, String)>> {
+    let names = devices["data"]
+        .as_array()
+        .context("device.data.as_array()")?
+        .iter()
+        .filter_map(|entry| Some((entry["id"].as_str()?, entry["metadata"]["name"].as_str()?)))
+        .collect::<BTreeMap<_, _>>();
+
+    let temperatures = temperatures["data"]
+        .as_array()
+        .context("temperature.data.as_array()")?;
+    let mut list = Vec::with_capacity(temperatures.len());
+    for entry in temperatures {
+        let id = entry["id"].as_str().context(".data[].id")?;
+        let owner = entry["owner"]["rid"]
+            .as_str()
+            .context(".data[].owner.rid")?;
+        let name = names.get(owner).copied().unwrap_or(owner);
+        list.push((id.to_owned(), name.to_owned()));
+    }
+    Ok(list)
+}
+
+/// Reads the temperatures of the `devices` that are `(ID, FriendlyName)`.
+fn read_sysfs_temperatures(
+    sysfs_root: &Path,
+    devices: &[(String, String)],
+) -> (Vec<(String, f64)>, bool) {
+    let mut result = Vec::with_capacity(devices.len());
+    let mut has_error = false;
+    for (id, friendly_name) in devices {
+        match read_sysfs_temperature(sysfs_root, id) {
+            Ok(temperature) => result.push((friendly_name.clone(), temperature)),
+            Err(e) => {
+                info!(?e, id, "failed to read temperature from sysfs");
+                has_error = true;
+            }
+        }
+    }
+    (result, has_error)
+}
+
+fn read_sysfs_temperature(sysfs_root: &Path, id: &str) -> Fallible<f64> {
+    // the ID is joined to the sysfs root, so it must not point outside of it.
+    ensure!(
+        id.split('/').count() <= 2
+            && id
+                .split('/')
+                .all(|data| !data.is_empty() && data != "." && data != ".."),
+        "invalid sysfs ID: {id}"
+    );
+    let Some((chip, sensor)) = id.split_once('/') else {
+        // https://docs.kernel.org/w1/slaves/w1_therm.html
+        let path = sysfs_root.join("bus/w1/devices").join(id).join("w1_slave");
+        let data = std::fs::read_to_string(&path)
+            .with_context(|| format!("failed to read {}", path.display()))?;
+        return parse_w1_slave(&data);
+    };
+
+    // https://docs.kernel.org/hwmon/sysfs-interface.html
+    // the number of hwmonN is not stable, so find the chip by the name too.
+    // some boards have several chips with the same name, so try all of them.
+    let hwmon_dir = sysfs_root.join("class/hwmon");
+    let mut found_chip = false;
+    for entry in std::fs::read_dir(&hwmon_dir)
+        .with_context(|| format!("failed to read {}", hwmon_dir.display()))?
+    {
+        let path = entry?.path();
+        let is_chip = path.file_name().is_some_and(|data| data == chip)
+            || std::fs::read_to_string(path.join("name")).is_ok_and(|data| data.trim() == chip);
+        if !is_chip {
+            continue;
+        }
+        found_chip = true;
+
+        let input_path = path.join(format!("{sensor}_input"));
+        let data = match std::fs::read_to_string(&input_path) {
+            Ok(data) => data,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                debug!(path = %input_path.display(), "sensor is not found; try the next chip");
+                continue;
+            }
+            Err(e) => {
+                return Err(e).with_context(|| format!("failed to read {}", input_path.display()));
+            }
+        };
+        let millidegree = data
+            .trim()
+            .parse::<i64>()
+            .with_context(|| format!("unexpected value: {data}"))?;
+        return Ok(millidegree as f64 / 1000.0);
+    }
+    if found_chip {
+        bail!("{sensor} of hwmon {chip} is not found")
+    }
+    bail!("hwmon {chip} is not found")
+}
+
+/// Parses the `w1_slave` of DS18B20 like the following:
+///
+/// ```text
+/// 72 01 4b 46 7f ff 0e 10 57 : crc=57 YES
+/// 72 01 4b 46 7f ff 0e 10 57 t=23125
+/// ```
+fn parse_w1_slave(data: &str) -> Fallible<f64> {
+    let mut lines = data.lines();
+    let crc_line = lines.next().context("crc line")?;
+    ensure!(
+        crc_line.trim_end().ends_with("YES"),
+        "crc error: {crc_line}"
+    );
+
+    let millidegree = lines
+        .next()
+        .and_then(|data| data.rsplit_once("t="))
+        .context("temperature line")?
+        .1
+        .trim()
+        .parse::<i64>()
+        .context("temperature")?;
+    Ok(millidegree as f64 / 1000.0)
+}
+
+async fn retrieve_remo_values(
+    client: &reqwest::Client,
+    remo: &OptRemo,
+) -> Fallible<Vec<SensorValue>> {
+    let res_devices = client
+        .get("https://api.nature.global/1/devices")
+        .header(header::ACCEPT, "application/json")
+        .bearer_auth(
+            remo.nature_auth_token
+                .as_ref()
+                .expect("--nature-auth-token"),
+        )
+        .send()
+        .await?;
+    info!(?res_devices);
+    let res_devices_text = res_devices.text().await?;
+    debug!(res_devices_text);
+
+    let devices = serde_json::from_str::<serde_json::Value>(&res_devices_text)?;
+    let mut sensor_values = Vec::with_capacity(remo.remo.len());
+    for (id, friendly_name) in &remo.remo {
+        let device = get_device(&devices, id)?;
+
+        let temperature = get_temperature(device)?;
+        let humidity = get_humidity(device)?;
+
+        sensor_values.push(SensorValue {
+            name: friendly_name.clone(),
+            temperature,
+            humidity,
+        });
+    }
+    Ok(sensor_values)
+}
+
 fn get_device<'a>(res: &'a serde_json::Value, remo_id: &str) -> Fallible<&'a serde_json::Value> {
     res.as_array()
         .expect(". should be array")
@@ -485,26 +1642,60 @@ fn get_humidity(device: &serde_json::Value) -> Fallible<Option<f64>> {
 /// margin to absorb clock skew and processing time.
 const TOKEN_EXPIRY_MARGIN_SECS: u64 = 120;
 
+struct QueuedReading {
+    id: i64,
+    /// unixepoch (secs)
+    recorded_at: i64,
+    value: SensorValue,
+}
+
+#[derive(Debug, PartialEq)]
+struct DataverseFlush {
+    /// Number of the readings queued by the previous runs and posted in this run.
+    flushed: usize,
+    /// Number of the readings left in the queue.
+    pending: usize,
+}
+
+/// Queues the `sensor_values` and posts the queue in order.
+///
+/// The readings stay in the queue until Dataverse accepts them, so the next run retries them with
+/// the original timestamp.
 #[tracing::instrument(skip_all)]
 async fn post_to_dataverse(
     client: &reqwest::Client,
-    dataverse: OptDataverse,
+    dataverse: &OptDataverse,
+    store: &ReadingStore,
     sensor_values: &[SensorValue],
     unixepoch: u64,
-) -> Fallible<()> {
+) -> Fallible<DataverseFlush> {
     let tenant = dataverse
         .dataverse_tenant
+        .as_ref()
         .expect("dataverse_tenant should not be None");
     let client_id = dataverse
         .dataverse_client_id
+        .as_ref()
         .expect("dataverse_client_id should not be None");
     let client_secret = dataverse
         .dataverse_client_secret
+        .as_ref()
         .expect("dataverse_client_secret should not be None");
     let environment_url = dataverse
         .dataverse_environment_url
+        .as_ref()
         .expect("dataverse_environment_url should not be None");
 
+    let first_current_id = store.enqueue_dataverse(sensor_values, unixepoch)?;
+    // prune after enqueueing not to exceed the maximum size with this run.
+    let dropped = store.prune_dataverse_queue(
+        unixepoch.saturating_sub(dataverse.dataverse_queue_max_age_hours.saturating_mul(3600)),
+        dataverse.dataverse_queue_max_size,
+    )?;
+    if dropped != 0 {
+        warn!(dropped, "dropped the queued readings by the queue policy");
+    }
+
     let scope = format!(
         "{}/.default",
         environment_url.as_str().trim_end_matches('/')
@@ -512,7 +1703,7 @@ async fn post_to_dataverse(
 
     let cache_path = token_cache_path()?;
 
-    let mut access_token = match load_cached_token(&cache_path, &scope, unixepoch) {
+    let access_token = match load_cached_token(&cache_path, &scope, unixepoch) {
         Some(data) => {
             debug!("use cached access token");
             data
@@ -520,9 +1711,9 @@ async fn post_to_dataverse(
         None => {
             fetch_and_cache_token(
                 client,
-                &tenant,
-                &client_id,
-                &client_secret,
+                tenant,
+                client_id,
+                client_secret,
                 &scope,
                 &cache_path,
             )
@@ -530,16 +1721,58 @@ async fn post_to_dataverse(
         }
     };
 
-    let api_url = create_dataverse_api_url(&environment_url)?;
+    let api_url = create_dataverse_api_url(environment_url)?;
+
+    flush_dataverse_queue(
+        client,
+        store,
+        &api_url,
+        first_current_id,
+        access_token,
+        async || {
+            fetch_and_cache_token(
+                client,
+                tenant,
+                client_id,
+                client_secret,
+                &scope,
+                &cache_path,
+            )
+            .await
+        },
+    )
+    .await
+}
 
+/// Posts the queued readings in order until a request fails.
+///
+/// The readings queued before `first_current_id` are counted as flushed. `refresh_token` is called
+/// once when the access token is rejected.
+async fn flush_dataverse_queue(
+    client: &reqwest::Client,
+    store: &ReadingStore,
+    api_url: &Url,
+    first_current_id: i64,
+    mut access_token: String,
+    mut refresh_token: impl AsyncFnMut() -> Fallible<String>,
+) -> Fallible<DataverseFlush> {
+    let queue = store.dataverse_queue()?;
+    let mut flushed = 0;
+    let mut pending = queue.len();
     let mut refreshed = false;
-    for SensorValue {
-        name,
-        temperature,
-        humidity,
-    } in sensor_values
+    'queue: for QueuedReading {
+        id,
+        recorded_at,
+        value:
+            SensorValue {
+                name,
+                temperature,
+                humidity,
+            },
+    } in queue
     {
-        let payload = generate_dataverse_payload(name, *temperature, *humidity, unixepoch)?;
+        let payload =
+            generate_dataverse_payload(&name, temperature, humidity, recorded_at.try_into()?)?;
         debug!(payload);
         loop {
             let ret = client
@@ -558,30 +1791,45 @@ async fn post_to_dataverse(
                     if res.status() == reqwest::StatusCode::UNAUTHORIZED && !refreshed {
                         info!("access token was rejected; refresh access token");
                         refreshed = true;
-                        access_token = fetch_and_cache_token(
-                            client,
-                            &tenant,
-                            &client_id,
-                            &client_secret,
-                            &scope,
-                            &cache_path,
-                        )
-                        .await?;
+                        access_token = refresh_token().await?;
                         continue;
                     }
-                    if !res.status().is_success() {
-                        warn!(res_text = %res.text().await.unwrap_or_default(), "failed to post to dataverse");
+                    let status = res.status();
+                    if status.is_success() {
+                        if id < first_current_id {
+                            flushed += 1;
+                        }
+                    } else if is_permanent_rejection(status) {
+                        warn!(%status, res_text = %res.text().await.unwrap_or_default(), name, recorded_at, "dataverse rejected the reading; drop it");
+                    } else {
+                        warn!(%status, res_text = %res.text().await.unwrap_or_default(), "failed to post to dataverse");
+                        break 'queue;
                     }
                 }
                 Err(e) => {
                     warn!(?e, name, "failed to post to dataverse");
+                    break 'queue;
                 }
             }
             break;
         }
+
+        store.remove_dataverse_queue(id)?;
+        pending -= 1;
     }
 
-    Ok(())
+    Ok(DataverseFlush { flushed, pending })
+}
+
+/// Returns `true` if Dataverse rejected the payload itself so retrying it never succeeds.
+///
+/// The other errors such as a wrong environment URL (404) may be fixed later, so the queue is
+/// kept for them.
+fn is_permanent_rejection(status: reqwest::StatusCode) -> bool {
+    matches!(
+        status,
+        reqwest::StatusCode::BAD_REQUEST | reqwest::StatusCode::UNPROCESSABLE_ENTITY
+    )
 }
 
 fn token_cache_path() -> Fallible<PathBuf> {
@@ -722,24 +1970,219 @@ fn generate_telegram_payload(
             report.write_str("\n")?;
         }
     }
-    // escape for MarkdownV2.
-    let report = report.replace('.', r"\.");
+    generate_telegram_report_payload(chat_id, template_txt, &report)
+}
+
+fn generate_telegram_alert_payload(
+    chat_id: &str,
+    template_txt: &str,
+    events: &[AlertEvent],
+) -> Fallible<String> {
+    let mut report = String::new();
+    for (i, AlertEvent { rule, value, kind }) in events.iter().enumerate() {
+        let (label, unit) = match rule.metric {
+            AlertMetric::Temperature => ("温度", "℃"),
+            AlertMetric::Humidity => ("湿度", "%"),
+        };
+        write!(report, "*{}*\n  ", rule.name)?;
+        match kind {
+            AlertKind::Crossed => write!(
+                report,
+                "{label}が{}{unit}を{}：{value}{unit}",
+                rule.threshold,
+                if rule.above {
+                    "上回りました"
+                } else {
+                    "下回りました"
+                },
+            )?,
+            AlertKind::Recovered => write!(report, "{label}が回復しました：{value}{unit}")?,
+        }
+        if i != events.len() - 1 {
+            report.write_str("\n")?;
+        }
+    }
+    generate_telegram_report_payload(chat_id, template_txt, &report)
+}
+
+/// Inserts the `report` to the `{value}` of the template.
+fn generate_telegram_report_payload(
+    chat_id: &str,
+    template_txt: &str,
+    report: &str,
+) -> Fallible<String> {
+    // escape for MarkdownV2.
+    let report = report.replace('.', r"\.");
+
+    let reg = regex::Regex::new(r#"([_\[\]()~`>#+=\-|{}.!])"#)?;
+
+    // `\n` to new line for serde_json::json.
+    let text = template_txt.replace(r"\n", "\n");
+    let text = reg
+        .replace_all(&text, r#"\$1"#)
+        .replace(r"\{value\}", &report);
+
+    info!(%text);
+    let payload = json!({
+        "chat_id": chat_id,
+        "text": text,
+        "parse_mode": "MarkdownV2",
+    });
+    Ok(serde_json::to_string(&payload)?)
+}
+
+fn default_alert_state_path() -> Fallible<PathBuf> {
+    let dirs = directories::ProjectDirs::from("com", "sukawasatoru", "temperature-sensor")
+        .context("failed to retrieve project directories")?;
+    Ok(dirs.data_dir().join("alert-state.json"))
+}
+
+/// Alert events and the updated states to save after the events are notified.
+struct AlertUpdate {
+    events: Vec<AlertEvent>,
+    state_path: PathBuf,
+    states: BTreeMap<String, AlertState>,
+}
+
+/// Evaluates the alert rules with the states stored in the alert state file.
+fn process_alerts(
+    opt: &Opt,
+    sensor_values: &[SensorValue],
+    unixepoch: u64,
+) -> Fallible<AlertUpdate> {
+    let alert_state_path = match &opt.alert_state_file {
+        Some(data) => data.clone(),
+        None => default_alert_state_path()?,
+    };
+    let mut alert_states = load_alert_states(&alert_state_path)?;
+    let events = evaluate_alerts(
+        &opt.alert,
+        sensor_values,
+        &mut alert_states,
+        opt.alert_hysteresis,
+        opt.alert_cooldown_mins * 60,
+        unixepoch,
+    );
+    Ok(AlertUpdate {
+        events,
+        state_path: alert_state_path,
+        states: alert_states,
+    })
+}
+
+fn load_alert_states(path: &Path) -> Fallible<BTreeMap<String, AlertState>> {
+    let data = match std::fs::read_to_string(path) {
+        Ok(data) => data,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(BTreeMap::new()),
+        Err(e) => return Err(e).with_context(|| format!("failed to read {}", path.display())),
+    };
+    serde_json::from_str(&data).with_context(|| format!("failed to parse {}", path.display()))
+}
+
+fn save_alert_states(path: &Path, states: &BTreeMap<String, AlertState>) -> Fallible<()> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)
+            .with_context(|| format!("failed to create {}", parent.display()))?;
+    }
+    write_atomically(path, serde_json::to_string(states)?)
+}
+
+/// Updates the alert states by the values and returns the crossed and recovered events.
+///
+/// The rules are recovered after the value goes back beyond the threshold by `hysteresis`, and
+/// a rule is not crossed again within `cooldown_secs` since its last crossed event.
+fn evaluate_alerts(
+    rules: &[AlertRule],
+    values: &[SensorValue],
+    states: &mut BTreeMap<String, AlertState>,
+    hysteresis: f64,
+    cooldown_secs: u64,
+    now: u64,
+) -> Vec<AlertEvent> {
+    // forget the states of the removed rules.
+    states.retain(|key, _| rules.iter().any(|rule| &rule.to_string() == key));
+
+    let mut events = vec![];
+    for rule in rules {
+        let value = values
+            .iter()
+            .find(|data| data.name == rule.name)
+            .and_then(|data| match rule.metric {
+                AlertMetric::Temperature => Some(data.temperature),
+                AlertMetric::Humidity => data.humidity,
+            });
+        let Some(value) = value else {
+            debug!(%rule, "no value for the alert rule");
+            continue;
+        };
+
+        let state = states.entry(rule.to_string()).or_default();
+        let (crossed, recovered) = if rule.above {
+            (rule.threshold < value, value <= rule.threshold - hysteresis)
+        } else {
+            (value < rule.threshold, rule.threshold + hysteresis <= value)
+        };
+
+        if !state.alerting && crossed {
+            if state
+                .crossed_at
+                .is_some_and(|crossed_at| now < crossed_at.saturating_add(cooldown_secs))
+            {
+                debug!(%rule, "in the cooldown");
+                continue;
+            }
+            state.alerting = true;
+            state.crossed_at = Some(now);
+            events.push(AlertEvent {
+                rule: rule.clone(),
+                value,
+                kind: AlertKind::Crossed,
+            });
+        } else if state.alerting && recovered {
+            state.alerting = false;
+            events.push(AlertEvent {
+                rule: rule.clone(),
+                value,
+                kind: AlertKind::Recovered,
+            });
+        }
+    }
+    events
+}
 
-    let reg = regex::Regex::new(r#"([_\[\]()~`>#+=\-|{}.!])"#)?;
+const PARSE_ALERT_RULE_ERR_MSG: &str =
+    "format should be the following: <friendly name>:<temperature|humidity><'>'|'<'><threshold>";
+
+fn parse_alert_rule(value: &str) -> Result<AlertRule, &'static str> {
+    let (name, condition) = value.rsplit_once(':').ok_or(PARSE_ALERT_RULE_ERR_MSG)?;
+    let (metric, above, threshold) = if let Some((metric, threshold)) = condition.split_once('>') {
+        (metric, true, threshold)
+    } else if let Some((metric, threshold)) = condition.split_once('<') {
+        (metric, false, threshold)
+    } else {
+        return Err(PARSE_ALERT_RULE_ERR_MSG);
+    };
 
-    // `\n` to new line for serde_json::json.
-    let text = template_txt.replace(r"\n", "\n");
-    let text = reg
-        .replace_all(&text, r#"\$1"#)
-        .replace(r"\{value\}", &report);
+    let metric = match metric {
+        "temperature" => AlertMetric::Temperature,
+        "humidity" => AlertMetric::Humidity,
+        _ => return Err(PARSE_ALERT_RULE_ERR_MSG),
+    };
+    let threshold = threshold
+        .parse::<f64>()
+        .ok()
+        .filter(|data| data.is_finite())
+        .ok_or(PARSE_ALERT_RULE_ERR_MSG)?;
+    if name.is_empty() {
+        return Err(PARSE_ALERT_RULE_ERR_MSG);
+    }
 
-    info!(%text);
-    let payload = json!({
-        "chat_id": chat_id,
-        "text": text,
-        "parse_mode": "MarkdownV2",
-    });
-    Ok(serde_json::to_string(&payload)?)
+    Ok(AlertRule {
+        name: name.to_owned(),
+        metric,
+        above,
+        threshold,
+    })
 }
 
 const PARSE_KEY_VALUE_ARG_ERR_MSG: &str = "format should be the following: <uuid>=<friendly name>";
@@ -872,6 +2315,891 @@ mod tests {
         assert!(opt.is_err());
     }
 
+    #[test]
+    fn opt_report_ok() {
+        let opt = Opt::try_parse_from([
+            "temperature-sensor",
+            "report",
+            "--period",
+            "week",
+            "--since",
+            "2025-07-01",
+            "--format",
+            "csv",
+            "--db-file",
+            "readings.db",
+        ])
+        .unwrap();
+
+        let Some(Command::Report(report)) = opt.command else {
+            panic!("report should be parsed");
+        };
+        assert!(matches!(report.period, ReportPeriod::Week));
+        assert_eq!(report.since, chrono::NaiveDate::from_ymd_opt(2025, 7, 1));
+        assert!(matches!(report.format, ReportFormat::Csv));
+        assert_eq!(opt.db_file, Some(PathBuf::from("readings.db")));
+    }
+
+    #[test]
+    fn reading_store_summarize() {
+        let store = ReadingStore::create_with_conn(Connection::open_in_memory().unwrap()).unwrap();
+        let values = |temperature: f64, humidity: Option<f64>| {
+            vec![
+                SensorValue {
+                    name: "living".into(),
+                    temperature,
+                    humidity,
+                },
+                SensorValue {
+                    name: "bedroom".into(),
+                    temperature: temperature - 2.0,
+                    humidity: None,
+                },
+            ]
+        };
+        // 2025-07-12 17:46:40 JST.
+        store.insert(&values(25.0, Some(40.0)), 1752310000).unwrap();
+        // 2025-07-12 23:46:40 JST.
+        store.insert(&values(27.0, Some(50.0)), 1752331600).unwrap();
+        // 2025-07-13 05:46:40 JST.
+        store.insert(&values(23.0, Some(60.0)), 1752353200).unwrap();
+
+        let jst = 9 * 3600;
+        let rows = store.summarize(ReportPeriod::Day, 0, jst).unwrap();
+        assert_eq!(
+            rows,
+            vec![
+                ReportRow {
+                    period: "2025-07-12".into(),
+                    name: "bedroom".into(),
+                    count: 2,
+                    temperature: (23.0, 25.0, 24.0),
+                    humidity: None,
+                },
+                ReportRow {
+                    period: "2025-07-12".into(),
+                    name: "living".into(),
+                    count: 2,
+                    temperature: (25.0, 27.0, 26.0),
+                    humidity: Some((40.0, 50.0, 45.0)),
+                },
+                ReportRow {
+                    period: "2025-07-13".into(),
+                    name: "bedroom".into(),
+                    count: 1,
+                    temperature: (21.0, 21.0, 21.0),
+                    humidity: None,
+                },
+                ReportRow {
+                    period: "2025-07-13".into(),
+                    name: "living".into(),
+                    count: 1,
+                    temperature: (23.0, 23.0, 23.0),
+                    humidity: Some((60.0, 60.0, 60.0)),
+                },
+            ],
+        );
+
+        let rows = store.summarize(ReportPeriod::Month, 0, jst).unwrap();
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows[1].period, "2025-07");
+        assert_eq!(rows[1].count, 3);
+        assert_eq!(rows[1].humidity, Some((40.0, 60.0, 50.0)));
+
+        // UTC splits the readings at the different time.
+        let rows = store.summarize(ReportPeriod::Day, 0, 0).unwrap();
+        assert_eq!(rows[1].period, "2025-07-12");
+        assert_eq!(rows[1].count, 3);
+
+        let rows = store.summarize(ReportPeriod::Day, 1752353200, jst).unwrap();
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows[0].period, "2025-07-13");
+    }
+
+    #[test]
+    fn format_report_csv_ok() {
+        let rows = vec![
+            ReportRow {
+                period: "2025-07".into(),
+                name: "foo, \"bar\"".into(),
+                count: 3,
+                temperature: (23.0, 27.5, 25.0 + 1.0 / 3.0),
+                humidity: Some((40.0, 60.0, 50.0)),
+            },
+            ReportRow {
+                period: "2025-07".into(),
+                name: "baz".into(),
+                count: 1,
+                temperature: (21.0, 21.0, 21.0),
+                humidity: None,
+            },
+        ];
+        assert_eq!(
+            format_report_csv(&rows),
+            "period,name,count,temperature_min,temperature_max,temperature_avg,humidity_min,humidity_max,humidity_avg
+2025-07,\"foo, \"\"bar\"\"\",3,23,27.5,25.33,40,60,50.00
+2025-07,baz,1,21,21,21.00,,,
+",
+        );
+    }
+
+    #[test]
+    fn format_report_table_ok() {
+        let rows = vec![ReportRow {
+            period: "2025-07-12".into(),
+            name: "寝室".into(),
+            count: 2,
+            temperature: (23.0, 25.0, 24.0),
+            humidity: None,
+        }];
+        assert_eq!(
+            format_report_table(&rows),
+            "period      name  count  temperature min/max/avg    humidity min/max/avg
+2025-07-12  寝室        2  23.0 / 25.0 / 24.0         N/A
+",
+        );
+    }
+
+    #[test]
+    fn opt_alert_ok() {
+        let opt = Opt::try_parse_from([
+            "temperature-sensor",
+            "--alert",
+            "foo room:temperature>28",
+            "--alert",
+            "bar:humidity<35.5",
+        ])
+        .unwrap();
+
+        assert_eq!(
+            opt.alert,
+            vec![
+                AlertRule {
+                    name: "foo room".into(),
+                    metric: AlertMetric::Temperature,
+                    above: true,
+                    threshold: 28.0,
+                },
+                AlertRule {
+                    name: "bar".into(),
+                    metric: AlertMetric::Humidity,
+                    above: false,
+                    threshold: 35.5,
+                },
+            ],
+        );
+        assert_eq!(opt.alert[1].to_string(), "bar:humidity<35.5");
+    }
+
+    #[test]
+    fn opt_alert_invalid_format() {
+        for value in [
+            "temperature>28",
+            "foo:temperature=28",
+            "foo:pressure>1000",
+            "foo:temperature>hot",
+            ":temperature>28",
+        ] {
+            let opt = Opt::try_parse_from(["temperature-sensor", "--alert", value]);
+            assert!(opt.is_err(), "{value}");
+        }
+    }
+
+    #[test]
+    fn evaluate_alerts_hysteresis_and_cooldown() {
+        let rules = vec![parse_alert_rule("foo:temperature>28").unwrap()];
+        let mut states = BTreeMap::new();
+        let mut evaluate = |temperature: f64, now: u64| {
+            let values = vec![SensorValue {
+                name: "foo".into(),
+                temperature,
+                humidity: None,
+            }];
+            evaluate_alerts(&rules, &values, &mut states, 0.5, 600, now)
+                .into_iter()
+                .map(|data| data.kind)
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(evaluate(27.0, 0), vec![]);
+        assert_eq!(evaluate(28.5, 100), vec![AlertKind::Crossed]);
+        assert_eq!(evaluate(29.0, 200), vec![]);
+        // within the hysteresis.
+        assert_eq!(evaluate(27.8, 300), vec![]);
+        assert_eq!(evaluate(27.5, 400), vec![AlertKind::Recovered]);
+        // within the cooldown.
+        assert_eq!(evaluate(28.5, 500), vec![]);
+        assert_eq!(evaluate(28.5, 700), vec![AlertKind::Crossed]);
+    }
+
+    #[test]
+    fn evaluate_alerts_below() {
+        let rules = vec![
+            parse_alert_rule("foo:humidity<35").unwrap(),
+            parse_alert_rule("bar:humidity<35").unwrap(),
+        ];
+        let mut states = BTreeMap::new();
+        states.insert("removed:temperature>30".into(), AlertState::default());
+        let values = vec![SensorValue {
+            name: "foo".into(),
+            temperature: 20.0,
+            humidity: Some(30.0),
+        }];
+
+        let events = evaluate_alerts(&rules, &values, &mut states, 1.0, 0, 0);
+        assert_eq!(
+            events,
+            vec![AlertEvent {
+                rule: rules[0].clone(),
+                value: 30.0,
+                kind: AlertKind::Crossed,
+            }],
+        );
+        assert_eq!(states.keys().collect::<Vec<_>>(), vec!["foo:humidity<35"],);
+    }
+
+    #[test]
+    fn save_alert_states_ok() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("nested").join("alert-state.json");
+        assert_eq!(load_alert_states(&path).unwrap(), BTreeMap::new());
+
+        let mut states = BTreeMap::new();
+        states.insert(
+            "foo:temperature>28".to_string(),
+            AlertState {
+                alerting: true,
+                crossed_at: Some(1752310000),
+            },
+        );
+        save_alert_states(&path, &states).unwrap();
+        assert_eq!(load_alert_states(&path).unwrap(), states);
+    }
+
+    #[test]
+    fn process_alerts_broken_state() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("alert-state.json");
+        std::fs::write(&path, "{broken").unwrap();
+        let opt = Opt::try_parse_from([
+            "temperature-sensor",
+            "--alert",
+            "foo:temperature>28",
+            "--alert-state-file",
+            path.to_str().unwrap(),
+        ])
+        .unwrap();
+        let values = [SensorValue {
+            name: "foo".into(),
+            temperature: 29.0,
+            humidity: None,
+        }];
+        assert!(process_alerts(&opt, &values, 1752310000).is_err());
+
+        std::fs::remove_file(&path).unwrap();
+        let update = process_alerts(&opt, &values, 1752310000).unwrap();
+        assert_eq!(update.events.len(), 1);
+
+        // the states are saved by the caller after notifying.
+        assert!(!path.exists());
+        let update = process_alerts(&opt, &values, 1752310000).unwrap();
+        assert_eq!(update.events.len(), 1);
+        save_alert_states(&update.state_path, &update.states).unwrap();
+        let update = process_alerts(&opt, &values, 1752310000).unwrap();
+        assert!(update.events.is_empty());
+    }
+
+    #[test]
+    fn generate_telegram_alert_payload_ok() {
+        let events = vec![
+            AlertEvent {
+                rule: parse_alert_rule("foo room:temperature>28").unwrap(),
+                value: 28.5,
+                kind: AlertKind::Crossed,
+            },
+            AlertEvent {
+                rule: parse_alert_rule("bar:humidity<35").unwrap(),
+                value: 36.0,
+                kind: AlertKind::Recovered,
+            },
+        ];
+        let actual =
+            generate_telegram_alert_payload("chat-id", "alert!\\n{value}", &events).unwrap();
+        let actual = serde_json::from_str::<serde_json::Value>(&actual).unwrap();
+        assert_eq!(
+            actual["text"],
+            "alert\\!\n*foo room*\n  温度が28℃を上回りました：28\\.5℃\n*bar*\n  湿度が回復しました：36%",
+        );
+    }
+
+    #[test]
+    fn opt_sysfs_ok() {
+        let opt = Opt::try_parse_from([
+            "temperature-sensor",
+            "--use-sysfs",
+            "--sysfs",
+            "coretemp/temp1=cpu",
+            "--sysfs",
+            "28-0000012345ab=outside",
+            "--sysfs-root",
+            "/tmp/sys",
+        ])
+        .unwrap();
+
+        let sysfs = opt.sysfs.unwrap();
+        assert_eq!(sysfs.sysfs[0], ("coretemp/temp1".into(), "cpu".into()));
+        assert_eq!(sysfs.sysfs[1], ("28-0000012345ab".into(), "outside".into()));
+        assert_eq!(sysfs.sysfs_root, PathBuf::from("/tmp/sys"));
+    }
+
+    #[test]
+    fn opt_sysfs_missing_use_sysfs() {
+        let opt = Opt::try_parse_from(["temperature-sensor", "--sysfs", "coretemp/temp1=cpu"]);
+        assert!(opt.is_err());
+    }
+
+    #[test]
+    fn read_sysfs_temperatures_fake_tree() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        let hwmon0 = root.join("class/hwmon/hwmon0");
+        std::fs::create_dir_all(&hwmon0).unwrap();
+        std::fs::write(hwmon0.join("name"), "acpitz\n").unwrap();
+        std::fs::write(hwmon0.join("temp1_input"), "27800\n").unwrap();
+        let hwmon1 = root.join("class/hwmon/hwmon1");
+        std::fs::create_dir_all(&hwmon1).unwrap();
+        std::fs::write(hwmon1.join("name"), "coretemp\n").unwrap();
+        std::fs::write(hwmon1.join("temp2_input"), "45500\n").unwrap();
+        let hwmon2 = root.join("class/hwmon/hwmon2");
+        std::fs::create_dir_all(&hwmon2).unwrap();
+        std::fs::write(hwmon2.join("name"), "coretemp\n").unwrap();
+        std::fs::write(hwmon2.join("temp3_input"), "47000\n").unwrap();
+        let w1 = root.join("bus/w1/devices/28-0000012345ab");
+        std::fs::create_dir_all(&w1).unwrap();
+        std::fs::write(
+            w1.join("w1_slave"),
+            "72 01 4b 46 7f ff 0e 10 57 : crc=57 YES\n72 01 4b 46 7f ff 0e 10 57 t=23125\n",
+        )
+        .unwrap();
+
+        let (list, has_error) = read_sysfs_temperatures(
+            root,
+            &[
+                ("coretemp/temp2".into(), "cpu".into()),
+                ("coretemp/temp3".into(), "cpu2".into()),
+                ("hwmon0/temp1".into(), "board".into()),
+                ("28-0000012345ab".into(), "outside".into()),
+            ],
+        );
+        assert_eq!(
+            list,
+            vec![
+                ("cpu".into(), 45.5),
+                ("cpu2".into(), 47.0),
+                ("board".into(), 27.8),
+                ("outside".into(), 23.125),
+            ],
+        );
+        assert!(!has_error);
+
+        let (list, has_error) = read_sysfs_temperatures(
+            root,
+            &[
+                ("coretemp/temp9".into(), "cpu".into()),
+                ("nct6775/temp1".into(), "board".into()),
+                ("28-ffffffffffff".into(), "outside".into()),
+            ],
+        );
+        assert!(list.is_empty());
+        assert!(has_error);
+
+        for id in [
+            "..",
+            "../hwmon/hwmon0",
+            "hwmon0/../temp1",
+            "coretemp/temp2/x",
+            "/coretemp",
+        ] {
+            assert!(read_sysfs_temperature(root, id).is_err(), "{id}");
+        }
+    }
+
+    #[test]
+    fn parse_w1_slave_crc_error() {
+        let actual = parse_w1_slave(
+            "72 01 4b 46 7f ff 0e 10 57 : crc=00 NO\n72 01 4b 46 7f ff 0e 10 57 t=23125\n",
+        );
+        assert!(actual.is_err());
+    }
+
+    #[test]
+    fn parse_w1_slave_negative() {
+        let actual = parse_w1_slave(
+            "5e ff 55 00 7f ff 0c 10 21 : crc=21 YES\n5e ff 55 00 7f ff 0c 10 21 t=-10125\n",
+        )
+        .unwrap();
+        assert_eq!(actual, -10.125);
+    }
+
+    #[test]
+    fn opt_serve_ok() {
+        let opt = Opt::try_parse_from([
+            "temperature-sensor",
+            "serve",
+            "--listen",
+            "0.0.0.0:9000",
+            "--interval-secs",
+            "30",
+            "--use-sysfs",
+            "--sysfs",
+            "coretemp/temp1=cpu",
+        ])
+        .unwrap();
+
+        let Some(Command::Serve(serve)) = &opt.command else {
+            panic!("serve should be parsed");
+        };
+        assert_eq!(serve.listen, "0.0.0.0:9000".parse().unwrap());
+        assert_eq!(serve.interval_secs, 30);
+        assert!(opt.sysfs.is_some());
+    }
+
+    #[test]
+    fn metrics_render() {
+        let mut metrics = Metrics::default();
+        metrics.values.insert(
+            "foo \"room\"".into(),
+            SensorValue {
+                name: "foo \"room\"".into(),
+                temperature: 25.6,
+                humidity: Some(41.0),
+            },
+        );
+        metrics.values.insert(
+            "cpu".into(),
+            SensorValue {
+                name: "cpu".into(),
+                temperature: 45.5,
+                humidity: None,
+            },
+        );
+        metrics.sources.insert(
+            "remo",
+            SourceMetrics {
+                last_success: Some(1752310000),
+                errors: 0,
+            },
+        );
+        metrics.sources.insert(
+            "hue",
+            SourceMetrics {
+                last_success: None,
+                errors: 2,
+            },
+        );
+
+        assert_eq!(
+            metrics.render(),
+            r#"# TYPE temperature_sensor_temperature_celsius gauge
+# UNIT temperature_sensor_temperature_celsius celsius
+# HELP temperature_sensor_temperature_celsius Temperature of the device.
+temperature_sensor_temperature_celsius{device="cpu"} 45.5
+temperature_sensor_temperature_celsius{device="foo \"room\""} 25.6
+# TYPE temperature_sensor_humidity_percent gauge
+# UNIT temperature_sensor_humidity_percent percent
+# HELP temperature_sensor_humidity_percent Humidity of the device.
+temperature_sensor_humidity_percent{device="foo \"room\""} 41
+# TYPE temperature_sensor_last_success_timestamp_seconds gauge
+# UNIT temperature_sensor_last_success_timestamp_seconds seconds
+# HELP temperature_sensor_last_success_timestamp_seconds Time of the last poll without errors.
+temperature_sensor_last_success_timestamp_seconds{source="remo"} 1752310000
+# TYPE temperature_sensor_errors counter
+# HELP temperature_sensor_errors Failures to retrieve the values from the source.
+temperature_sensor_errors_total{source="hue"} 2
+temperature_sensor_errors_total{source="remo"} 0
+# EOF
+"#,
+        );
+    }
+
+    #[tokio::test]
+    async fn poll_sources_sysfs() {
+        let dir = tempfile::tempdir().unwrap();
+        let hwmon0 = dir.path().join("class/hwmon/hwmon0");
+        std::fs::create_dir_all(&hwmon0).unwrap();
+        std::fs::write(hwmon0.join("name"), "coretemp\n").unwrap();
+        std::fs::write(hwmon0.join("temp1_input"), "45500\n").unwrap();
+
+        let opt = Opt::try_parse_from([
+            "temperature-sensor",
+            "--use-sysfs",
+            "--sysfs",
+            "coretemp/temp1=cpu",
+            "--sysfs",
+            "coretemp/temp2=gpu",
+            "--sysfs-root",
+            dir.path().to_str().unwrap(),
+        ])
+        .unwrap();
+        let store = ReadingStore::create_with_conn(Connection::open_in_memory().unwrap()).unwrap();
+        let metrics = Mutex::new(Metrics::default());
+        poll_sources(
+            &opt,
+            &reqwest::Client::new(),
+            Some(&store),
+            &metrics,
+            &OtelInstruments::new(),
+        )
+        .await;
+
+        assert_eq!(metrics.lock().unwrap().values["cpu"].temperature, 45.5);
+        let stored = store
+            .conn
+            .query_row("select name, temperature from reading", [], |row| {
+                Ok((row.get::<_, String>(0)?, row.get::<_, f64>(1)?))
+            })
+            .unwrap();
+        assert_eq!(stored, ("cpu".to_string(), 45.5));
+        assert_eq!(
+            metrics.lock().unwrap().sources["sysfs"],
+            SourceMetrics {
+                last_success: None,
+                errors: 1,
+            },
+        );
+
+        std::fs::remove_file(hwmon0.join("temp1_input")).unwrap();
+        poll_sources(
+            &opt,
+            &reqwest::Client::new(),
+            Some(&store),
+            &metrics,
+            &OtelInstruments::new(),
+        )
+        .await;
+
+        let metrics = metrics.into_inner().unwrap();
+        assert!(metrics.values.is_empty());
+        assert_eq!(metrics.sources["sysfs"].errors, 2);
+    }
+
+    #[test]
+    fn opt_serve_zero_interval() {
+        let ret = Opt::try_parse_from(["temperature-sensor", "serve", "--interval-secs", "0"]);
+        assert!(ret.is_err());
+    }
+
+    #[test]
+    fn opt_hue_setup_ok() {
+        let opt = Opt::try_parse_from([
+            "temperature-sensor",
+            "hue",
+            "setup",
+            "--bridge",
+            "192.168.1.2",
+        ])
+        .unwrap();
+
+        let Some(Command::Hue(HueCommand::Setup(setup))) = &opt.command else {
+            panic!("hue setup should be parsed");
+        };
+        assert_eq!(setup.bridge, Some("192.168.1.2".parse().unwrap()));
+        assert_eq!(setup.app_key, None);
+        assert_eq!(setup.device_type, "temperature-sensor#setup");
+        assert_eq!(setup.timeout_secs, 60);
+    }
+
+    #[test]
+    fn parse_hue_pairing_response_ok() {
+        let res = serde_json::json!([{
+            "error": {"type": 101, "address": "", "description": "link button not pressed"},
+        }]);
+        assert_eq!(parse_hue_pairing_response(&res).unwrap(), None);
+
+        let res = serde_json::json!([{
+            "success": {"username": "app-key", "clientkey": "client-key"},
+        }]);
+        assert_eq!(
+            parse_hue_pairing_response(&res).unwrap(),
+            Some(HueKey {
+                app_key: "app-key".into(),
+                client_key: Some("client-key".into()),
+            }),
+        );
+
+        let res = serde_json::json!([{
+            "error": {"type": 7, "address": "/devicetype", "description": "invalid value"},
+        }]);
+        assert!(parse_hue_pairing_response(&res).is_err());
+    }
+
+    #[tokio::test]
+    async fn pair_hue_bridge_waits_link_button() {
+        let count = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let router = axum::Router::new().route(
+            "/api",
+            axum::routing::post({
+                let count = count.clone();
+                move |axum::Json(body): axum::Json<serde_json::Value>| async move {
+                    assert_eq!(body["devicetype"], "temperature-sensor#test");
+                    assert_eq!(body["generateclientkey"], true);
+                    if count.fetch_add(1, std::sync::atomic::Ordering::SeqCst) == 0 {
+                        axum::Json(serde_json::json!([{
+                            "error": {"type": 101, "address": "", "description": "link button not pressed"},
+                        }]))
+                    } else {
+                        axum::Json(serde_json::json!([{
+                            "success": {"username": "app-key", "clientkey": "client-key"},
+                        }]))
+                    }
+                }
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base_url = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, router).await });
+
+        let key = pair_hue_bridge(
+            &reqwest::Client::new(),
+            &base_url,
+            "temperature-sensor#test",
+            Duration::from_millis(10),
+        )
+        .await
+        .unwrap();
+        assert_eq!(key.app_key, "app-key");
+        assert_eq!(count.load(std::sync::atomic::Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn collect_hue_temperature_devices_ok() {
+        let devices = serde_json::json!({
+            "errors": [],
+            "data": [
+                {"id": "device-1", "metadata": {"name": "Hallway sensor"}, "type": "device"},
+                {"id": "device-2", "metadata": {"name": "Desk lamp"}, "type": "device"},
+            ],
+        });
+        let temperatures = serde_json::json!({
+            "errors": [],
+            "data": [
+                {
+                    "id": "temperature-1",
+                    "owner": {"rid": "device-1", "rtype": "device"},
+                    "temperature": {"temperature_report": {"temperature": 25.5}},
+                },
+                {
+                    "id": "temperature-2",
+                    "owner": {"rid": "device-3", "rtype": "device"},
+                    "temperature": {"temperature_report": {"temperature": 24.0}},
+                },
+            ],
+        });
+
+        assert_eq!(
+            collect_hue_temperature_devices(&devices, &temperatures).unwrap(),
+            vec![
+                ("temperature-1".to_string(), "Hallway sensor".to_string()),
+                ("temperature-2".to_string(), "device-3".to_string()),
+            ],
+        );
+    }
+
+    #[test]
+    fn reading_store_migrate_from_v1() {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(
+            "create table reading (id integer primary key not null, recorded_at integer not null, name text not null, temperature real not null, humidity real);
+create index index_reading_recorded_at on reading (recorded_at);
+pragma user_version = 1",
+        )
+        .unwrap();
+
+        let store = ReadingStore::create_with_conn(conn).unwrap();
+        let db_version = store
+            .conn
+            .query_row("pragma user_version", [], |row| row.get::<_, i32>(0))
+            .unwrap();
+        assert_eq!(db_version, 2);
+        assert!(store.dataverse_queue().unwrap().is_empty());
+    }
+
+    #[test]
+    fn reading_store_dataverse_queue() {
+        let store = ReadingStore::create_with_conn(Connection::open_in_memory().unwrap()).unwrap();
+        let values = |name: &str, temperature: f64| {
+            vec![SensorValue {
+                name: name.into(),
+                temperature,
+                humidity: Some(40.0),
+            }]
+        };
+        let first_id = store
+            .enqueue_dataverse(&values("living", 25.0), 1752310000)
+            .unwrap();
+        store
+            .enqueue_dataverse(&values("living", 26.0), 1752313600)
+            .unwrap();
+        let third_id = store
+            .enqueue_dataverse(&values("living", 27.0), 1752317200)
+            .unwrap();
+
+        let queue = store.dataverse_queue().unwrap();
+        assert_eq!(
+            queue
+                .iter()
+                .map(|data| (data.recorded_at, data.value.temperature))
+                .collect::<Vec<_>>(),
+            vec![(1752310000, 25.0), (1752313600, 26.0), (1752317200, 27.0)],
+        );
+        assert_eq!(queue[0].id, first_id);
+        assert_eq!(queue[2].id, third_id);
+
+        store.remove_dataverse_queue(first_id).unwrap();
+        assert_eq!(store.dataverse_queue().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn reading_store_prune_dataverse_queue() {
+        let store = ReadingStore::create_with_conn(Connection::open_in_memory().unwrap()).unwrap();
+        for (i, unixepoch) in [1752310000, 1752313600, 1752317200, 1752320800]
+            .into_iter()
+            .enumerate()
+        {
+            store
+                .enqueue_dataverse(
+                    &[SensorValue {
+                        name: "living".into(),
+                        temperature: 25.0 + i as f64,
+                        humidity: None,
+                    }],
+                    unixepoch,
+                )
+                .unwrap();
+        }
+
+        // expire the first one and keep the latest two.
+        let dropped = store.prune_dataverse_queue(1752313600, 2).unwrap();
+        assert_eq!(dropped, 2);
+        assert_eq!(
+            store
+                .dataverse_queue()
+                .unwrap()
+                .into_iter()
+                .map(|data| data.recorded_at)
+                .collect::<Vec<_>>(),
+            vec![1752317200, 1752320800],
+        );
+
+        assert_eq!(store.prune_dataverse_queue(0, 0).unwrap(), 2);
+        assert!(store.dataverse_queue().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn flush_dataverse_queue_in_order() {
+        // (authorization, logid) of the requests.
+        let requests = Arc::new(Mutex::new(Vec::<(String, String)>::new()));
+        let statuses = Arc::new(Mutex::new(std::collections::VecDeque::from([
+            401u16, 204, 400, 503,
+        ])));
+        let router = axum::Router::new().route(
+            "/api/data/v9.2/cre1f_temperaturesensors",
+            axum::routing::post({
+                let requests = requests.clone();
+                let statuses = statuses.clone();
+                move |headers: axum::http::HeaderMap, body: String| async move {
+                    let body = serde_json::from_str::<serde_json::Value>(&body).unwrap();
+                    requests.lock().unwrap().push((
+                        headers[header::AUTHORIZATION.as_str()]
+                            .to_str()
+                            .unwrap()
+                            .to_string(),
+                        body["cre1f_logid"].as_str().unwrap().to_string(),
+                    ));
+                    let status = statuses.lock().unwrap().pop_front().unwrap();
+                    axum::http::StatusCode::from_u16(status).unwrap()
+                }
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let api_url = Url::parse(&format!(
+            "http://{}/api/data/v9.2/cre1f_temperaturesensors",
+            listener.local_addr().unwrap()
+        ))
+        .unwrap();
+        tokio::spawn(async move { axum::serve(listener, router).await });
+
+        let store = ReadingStore::create_with_conn(Connection::open_in_memory().unwrap()).unwrap();
+        let value = |name: &str| {
+            vec![SensorValue {
+                name: name.into(),
+                temperature: 25.0,
+                humidity: None,
+            }]
+        };
+        store.enqueue_dataverse(&value("a"), 1752310000).unwrap();
+        store.enqueue_dataverse(&value("b"), 1752313600).unwrap();
+        store.enqueue_dataverse(&value("c"), 1752317200).unwrap();
+        let first_current_id = store.enqueue_dataverse(&value("d"), 1752320800).unwrap();
+
+        let mut refresh_count = 0;
+        let flush = flush_dataverse_queue(
+            &reqwest::Client::new(),
+            &store,
+            &api_url,
+            first_current_id,
+            "old".into(),
+            async || {
+                refresh_count += 1;
+                Ok("new".to_string())
+            },
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(refresh_count, 1);
+        // "a" is posted after refreshing the token, "b" is rejected and "c" stops the flush.
+        assert_eq!(
+            flush,
+            DataverseFlush {
+                flushed: 1,
+                pending: 2,
+            }
+        );
+        assert_eq!(
+            *requests.lock().unwrap(),
+            vec![
+                ("Bearer old".to_string(), "a-1752310000".to_string()),
+                ("Bearer new".to_string(), "a-1752310000".to_string()),
+                ("Bearer new".to_string(), "b-1752313600".to_string()),
+                ("Bearer new".to_string(), "c-1752317200".to_string()),
+            ]
+        );
+        assert_eq!(
+            store
+                .dataverse_queue()
+                .unwrap()
+                .into_iter()
+                .map(|data| data.value.name)
+                .collect::<Vec<_>>(),
+            vec!["c", "d"]
+        );
+    }
+
+    #[test]
+    fn is_permanent_rejection_ok() {
+        use reqwest::StatusCode;
+
+        assert!(is_permanent_rejection(StatusCode::BAD_REQUEST));
+        assert!(is_permanent_rejection(StatusCode::UNPROCESSABLE_ENTITY));
+        assert!(!is_permanent_rejection(StatusCode::NOT_FOUND));
+        assert!(!is_permanent_rejection(StatusCode::CONFLICT));
+        assert!(!is_permanent_rejection(StatusCode::PRECONDITION_FAILED));
+        assert!(!is_permanent_rejection(StatusCode::UNAUTHORIZED));
+        assert!(!is_permanent_rejection(StatusCode::TOO_MANY_REQUESTS));
+        assert!(!is_permanent_rejection(StatusCode::SERVICE_UNAVAILABLE));
+    }
+
     #[test]
     fn create_dataverse_api_url_ok() {
         let environment_url = Url::parse("https://org00000000.crm0.dynamics.com").unwrap();